@@ -0,0 +1,9 @@
+-- down.sql
+DROP INDEX idx_unique_thought_short_id;
+ALTER TABLE thoughts DROP COLUMN short_id;
+
+DROP INDEX idx_unique_subtask_short_id;
+ALTER TABLE subtasks DROP COLUMN short_id;
+
+DROP INDEX idx_unique_task_short_id;
+ALTER TABLE tasks DROP COLUMN short_id;
@@ -0,0 +1,16 @@
+-- up.sql
+-- Every row gets a short, typeable identifier (e.g. `t-4f2a`). New rows get a
+-- random one from the application; existing rows are derived from their id so
+-- the backfill can't collide. The empty default only exists to satisfy
+-- ALTER TABLE and is never left in place.
+ALTER TABLE tasks ADD COLUMN short_id TEXT NOT NULL DEFAULT '';
+UPDATE tasks SET short_id = printf('t-%04x', id);
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+
+ALTER TABLE subtasks ADD COLUMN short_id TEXT NOT NULL DEFAULT '';
+UPDATE subtasks SET short_id = printf('s-%04x', id);
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
+
+ALTER TABLE thoughts ADD COLUMN short_id TEXT NOT NULL DEFAULT '';
+UPDATE thoughts SET short_id = printf('th-%04x', id);
+CREATE UNIQUE INDEX idx_unique_thought_short_id ON thoughts(short_id);
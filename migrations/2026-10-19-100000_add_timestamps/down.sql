@@ -0,0 +1,59 @@
+-- down.sql
+CREATE TABLE projects_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP NOT NULL
+);
+
+CREATE TABLE tasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL,
+    short_id TEXT NOT NULL DEFAULT '',
+    FOREIGN KEY (project_id) REFERENCES projects_old(id)
+);
+
+CREATE TABLE subtasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL,
+    short_id TEXT NOT NULL DEFAULT '',
+    FOREIGN KEY (task_id) REFERENCES tasks_old(id)
+);
+
+CREATE TABLE thoughts_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    heading TEXT NOT NULL,
+    note TEXT NOT NULL,
+    created_on TIMESTAMP NOT NULL,
+    short_id TEXT NOT NULL DEFAULT ''
+);
+
+INSERT INTO projects_old (id, name, created_on)
+    SELECT id, name, created_on FROM projects;
+INSERT INTO tasks_old (id, project_id, name, completed, created_on, short_id)
+    SELECT id, project_id, name, completed, created_on, short_id FROM tasks;
+INSERT INTO subtasks_old (id, task_id, name, completed, created_on, short_id)
+    SELECT id, task_id, name, completed, created_on, short_id FROM subtasks;
+INSERT INTO thoughts_old (id, heading, note, created_on, short_id)
+    SELECT id, heading, note, created_on, short_id FROM thoughts;
+
+DROP TABLE thoughts;
+DROP TABLE subtasks;
+DROP TABLE tasks;
+DROP TABLE projects;
+
+ALTER TABLE projects_old RENAME TO projects;
+ALTER TABLE tasks_old RENAME TO tasks;
+ALTER TABLE subtasks_old RENAME TO subtasks;
+ALTER TABLE thoughts_old RENAME TO thoughts;
+
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name);
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name);
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
+CREATE UNIQUE INDEX idx_unique_thought_short_id ON thoughts(short_id);
@@ -0,0 +1,113 @@
+-- up.sql
+-- SQLite can't change a column default in place, so each table is rebuilt
+-- with `created_on` defaulting to CURRENT_TIMESTAMP and the new columns added.
+-- Database::new turns foreign keys on before migrating, and the bundled
+-- SQLite defaults to having them on anyway, so the rows copied into the new
+-- tables are checked against them. The new tables reference each other and
+-- the old ones are dropped children first; renaming then rewrites the
+-- references to the final table names.
+--
+-- Older schemas never enforced foreign keys, so they can hold tasks whose
+-- project is gone and subtasks whose task is gone, which the copy would
+-- reject. Rather than dropping them, they're moved into a "Recovered
+-- orphans" project, subtasks under a "Recovered subtasks" task there, with
+-- their short id added to their name so they can't clash. The orphans are
+-- noted first: the rows added for them can be given the very ids their
+-- missing parents had.
+CREATE TEMP TABLE orphaned_tasks AS
+    SELECT id FROM tasks WHERE project_id NOT IN (SELECT id FROM projects);
+CREATE TEMP TABLE orphaned_subtasks AS
+    SELECT id FROM subtasks WHERE task_id NOT IN (SELECT id FROM tasks);
+
+INSERT INTO projects (name, created_on)
+    SELECT 'Recovered orphans', CURRENT_TIMESTAMP
+    WHERE (EXISTS (SELECT 1 FROM orphaned_tasks) OR EXISTS (SELECT 1 FROM orphaned_subtasks))
+      AND NOT EXISTS (SELECT 1 FROM projects WHERE name = 'Recovered orphans');
+
+UPDATE tasks
+    SET name = name || ' (' || short_id || ')',
+        project_id = (SELECT id FROM projects WHERE name = 'Recovered orphans')
+    WHERE id IN (SELECT id FROM orphaned_tasks);
+
+INSERT INTO tasks (project_id, name, created_on, short_id)
+    SELECT id, 'Recovered subtasks', CURRENT_TIMESTAMP, 't-' || lower(hex(randomblob(4)))
+    FROM projects
+    WHERE name = 'Recovered orphans'
+      AND EXISTS (SELECT 1 FROM orphaned_subtasks)
+      AND NOT EXISTS (SELECT 1 FROM tasks
+                      WHERE project_id = projects.id AND name = 'Recovered subtasks');
+
+UPDATE subtasks
+    SET name = name || ' (' || short_id || ')',
+        task_id = (SELECT tasks.id FROM tasks JOIN projects ON projects.id = tasks.project_id
+                   WHERE projects.name = 'Recovered orphans'
+                     AND tasks.name = 'Recovered subtasks')
+    WHERE id IN (SELECT id FROM orphaned_subtasks);
+
+DROP TABLE orphaned_tasks;
+DROP TABLE orphaned_subtasks;
+
+CREATE TABLE projects_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_on TIMESTAMP
+);
+
+CREATE TABLE tasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects_new(id)
+);
+
+CREATE TABLE subtasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks_new(id)
+);
+
+CREATE TABLE thoughts_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    heading TEXT NOT NULL,
+    note TEXT NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP
+);
+
+INSERT INTO projects_new (id, name, created_on)
+    SELECT id, name, created_on FROM projects;
+INSERT INTO tasks_new (id, project_id, name, completed, created_on, short_id)
+    SELECT id, project_id, name, completed, created_on, short_id FROM tasks;
+INSERT INTO subtasks_new (id, task_id, name, completed, created_on, short_id)
+    SELECT id, task_id, name, completed, created_on, short_id FROM subtasks;
+INSERT INTO thoughts_new (id, heading, note, created_on, short_id)
+    SELECT id, heading, note, created_on, short_id FROM thoughts;
+
+DROP TABLE thoughts;
+DROP TABLE subtasks;
+DROP TABLE tasks;
+DROP TABLE projects;
+
+ALTER TABLE projects_new RENAME TO projects;
+ALTER TABLE tasks_new RENAME TO tasks;
+ALTER TABLE subtasks_new RENAME TO subtasks;
+ALTER TABLE thoughts_new RENAME TO thoughts;
+
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name);
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name);
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
+CREATE UNIQUE INDEX idx_unique_thought_short_id ON thoughts(short_id);
@@ -0,0 +1,4 @@
+-- down.sql
+DROP INDEX idx_events_task_id;
+DROP INDEX idx_events_created_on;
+DROP TABLE events;
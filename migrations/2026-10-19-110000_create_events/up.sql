@@ -0,0 +1,18 @@
+-- up.sql
+-- Audit log of every change made through `Database`. `task_id` is the task
+-- an event belongs to (the task itself, or a subtask's parent) so a task's
+-- history survives its subtasks being deleted.
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id INTEGER NOT NULL,
+    entity_name TEXT NOT NULL,
+    task_id INTEGER,
+    action TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_events_created_on ON events(created_on);
+CREATE INDEX idx_events_task_id ON events(task_id);
@@ -0,0 +1,11 @@
+-- up.sql
+-- Operation journal backing `doit undo` / `doit redo`. Each row is one
+-- mutating command with the JSON-encoded operations that revert and replay it.
+CREATE TABLE journal (
+    id INTEGER PRIMARY KEY NOT NULL,
+    description TEXT NOT NULL,
+    undo_ops TEXT NOT NULL,
+    redo_ops TEXT NOT NULL,
+    undone BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
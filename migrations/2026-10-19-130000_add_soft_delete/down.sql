@@ -0,0 +1,55 @@
+-- down.sql
+-- Trashed rows are dropped; the old schema has nowhere to keep them.
+CREATE TABLE projects_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_on TIMESTAMP
+);
+
+CREATE TABLE tasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects_old(id)
+);
+
+CREATE TABLE subtasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks_old(id)
+);
+
+INSERT INTO projects_old (id, name, created_on, updated_on)
+    SELECT id, name, created_on, updated_on FROM projects
+    WHERE deleted_on IS NULL;
+INSERT INTO tasks_old (id, project_id, name, completed, created_on, short_id, updated_on, completed_on)
+    SELECT id, project_id, name, completed, created_on, short_id, updated_on, completed_on FROM tasks
+    WHERE deleted_on IS NULL AND project_id IN (SELECT id FROM projects_old);
+INSERT INTO subtasks_old (id, task_id, name, completed, created_on, short_id, updated_on, completed_on)
+    SELECT id, task_id, name, completed, created_on, short_id, updated_on, completed_on FROM subtasks
+    WHERE deleted_on IS NULL AND task_id IN (SELECT id FROM tasks_old);
+
+DROP TABLE subtasks;
+DROP TABLE tasks;
+DROP TABLE projects;
+
+ALTER TABLE projects_old RENAME TO projects;
+ALTER TABLE tasks_old RENAME TO tasks;
+ALTER TABLE subtasks_old RENAME TO subtasks;
+
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name);
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name);
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
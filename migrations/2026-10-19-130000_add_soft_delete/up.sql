@@ -0,0 +1,60 @@
+-- up.sql
+-- Deleting now moves rows to the trash by stamping `deleted_on`. Names only
+-- have to be unique among live rows, so a trashed task doesn't block a new
+-- one with the same name. The project name uniqueness lives in the column
+-- definition, hence the rebuild (see 2026-10-19-100000_add_timestamps for
+-- why the new tables reference each other).
+CREATE TABLE projects_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_on TIMESTAMP,
+    deleted_on TIMESTAMP
+);
+
+CREATE TABLE tasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects_new(id)
+);
+
+CREATE TABLE subtasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks_new(id)
+);
+
+INSERT INTO projects_new (id, name, created_on, updated_on)
+    SELECT id, name, created_on, updated_on FROM projects;
+INSERT INTO tasks_new (id, project_id, name, completed, created_on, short_id, updated_on, completed_on)
+    SELECT id, project_id, name, completed, created_on, short_id, updated_on, completed_on FROM tasks;
+INSERT INTO subtasks_new (id, task_id, name, completed, created_on, short_id, updated_on, completed_on)
+    SELECT id, task_id, name, completed, created_on, short_id, updated_on, completed_on FROM subtasks;
+
+DROP TABLE subtasks;
+DROP TABLE tasks;
+DROP TABLE projects;
+
+ALTER TABLE projects_new RENAME TO projects;
+ALTER TABLE tasks_new RENAME TO tasks;
+ALTER TABLE subtasks_new RENAME TO subtasks;
+
+CREATE UNIQUE INDEX idx_unique_project_name ON projects(name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
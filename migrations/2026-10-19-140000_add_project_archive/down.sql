@@ -0,0 +1,2 @@
+-- down.sql
+ALTER TABLE projects DROP COLUMN archived_on;
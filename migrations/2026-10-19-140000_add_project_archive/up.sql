@@ -0,0 +1,2 @@
+-- up.sql
+ALTER TABLE projects ADD COLUMN archived_on TIMESTAMP;
@@ -0,0 +1,40 @@
+-- down.sql
+CREATE TABLE tasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id)
+);
+
+CREATE TABLE subtasks_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks_old(id)
+);
+
+INSERT INTO tasks_old SELECT * FROM tasks;
+INSERT INTO subtasks_old SELECT * FROM subtasks;
+
+DROP TABLE subtasks;
+DROP TABLE tasks;
+
+ALTER TABLE tasks_old RENAME TO tasks;
+ALTER TABLE subtasks_old RENAME TO subtasks;
+
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
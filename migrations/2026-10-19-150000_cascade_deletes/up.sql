@@ -0,0 +1,53 @@
+-- up.sql
+-- Rebuild tasks and subtasks so removing a row takes its children with it.
+-- Database::new turns foreign keys on before migrating, and the bundled
+-- SQLite defaults to having them on anyway, so as in earlier rebuilds the
+-- new tables reference each other and the old ones are dropped children
+-- first. The timestamps migration already moved any orphans into a
+-- project of their own, and enforcement has kept new ones out since, so
+-- every row is carried over.
+CREATE TABLE tasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    project_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE TABLE subtasks_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    task_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT FALSE,
+    created_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    short_id TEXT NOT NULL,
+    updated_on TIMESTAMP,
+    completed_on TIMESTAMP,
+    deleted_on TIMESTAMP,
+    FOREIGN KEY (task_id) REFERENCES tasks_new(id) ON DELETE CASCADE
+);
+
+INSERT INTO tasks_new
+    SELECT id, project_id, name, completed, created_on, short_id, updated_on, completed_on, deleted_on
+    FROM tasks;
+INSERT INTO subtasks_new
+    SELECT id, task_id, name, completed, created_on, short_id, updated_on, completed_on, deleted_on
+    FROM subtasks;
+
+DROP TABLE subtasks;
+DROP TABLE tasks;
+
+ALTER TABLE tasks_new RENAME TO tasks;
+ALTER TABLE subtasks_new RENAME TO subtasks;
+
+CREATE UNIQUE INDEX idx_unique_task_name_per_project ON tasks(project_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_task_short_id ON tasks(short_id);
+CREATE UNIQUE INDEX idx_unique_subtask_name_per_task ON subtasks(task_id, name) WHERE deleted_on IS NULL;
+CREATE UNIQUE INDEX idx_unique_subtask_short_id ON subtasks(short_id);
+CREATE INDEX idx_tasks_project_id ON tasks(project_id);
+CREATE INDEX idx_subtasks_task_id ON subtasks(task_id);
@@ -0,0 +1,5 @@
+-- down.sql
+ALTER TABLE tasks DROP COLUMN metadata;
+ALTER TABLE tasks DROP COLUMN tags;
+ALTER TABLE tasks DROP COLUMN due_on;
+ALTER TABLE tasks DROP COLUMN priority;
@@ -0,0 +1,7 @@
+-- up.sql
+ALTER TABLE tasks ADD COLUMN priority TEXT;
+ALTER TABLE tasks ADD COLUMN due_on DATE;
+-- space separated, e.g. `phone errands`
+ALTER TABLE tasks ADD COLUMN tags TEXT NOT NULL DEFAULT '';
+-- a JSON object of string values that other tools attach to a task
+ALTER TABLE tasks ADD COLUMN metadata TEXT NOT NULL DEFAULT '{}';
@@ -0,0 +1,2 @@
+-- down.sql
+ALTER TABLE thoughts DROP COLUMN tags;
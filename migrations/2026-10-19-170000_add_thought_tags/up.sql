@@ -0,0 +1,3 @@
+-- up.sql
+-- space separated, like tasks.tags
+ALTER TABLE thoughts ADD COLUMN tags TEXT NOT NULL DEFAULT '';
@@ -2,6 +2,7 @@ use clap::Subcommand;
 
 // `doit add task --project <some-name> --task <some-task>`
 // `doit add project --project <some-name>`
+// `doit add subtask --task <task-ref> --subtask <some-subtask>`
 // `doit add point --project <some-name> --point <some-point>`
 // `doit add thought <some-thought>`
 
@@ -19,6 +20,14 @@ pub enum AddCommands {
         project: String,
     },
 
+    Subtask {
+        /// Task name, `project/task` path or short id (e.g. `t-4f2a`)
+        #[arg(long)]
+        task: String,
+        #[arg(long)]
+        subtask: String,
+    },
+
     Point {
         #[arg(long)]
         point: String,
@@ -0,0 +1,13 @@
+use clap::Subcommand;
+
+// `doit archive project --project <some-name>`
+// `doit unarchive project --project <some-name>`
+
+#[derive(Subcommand)]
+pub enum ArchiveCommands {
+    Project {
+        /// Name of the project
+        #[arg(long)]
+        project: String,
+    },
+}
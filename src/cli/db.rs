@@ -0,0 +1,19 @@
+use clap::Subcommand;
+
+// `doit db check [--repair]`
+// `doit db vacuum`
+// `doit db stats`
+
+#[derive(Subcommand)]
+pub enum DbCommands {
+    /// Check the database for corruption and orphaned tasks and subtasks
+    Check {
+        /// Delete orphaned tasks and subtasks
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Reclaim unused space in the database file
+    Vacuum,
+    /// Show row counts and file size
+    Stats,
+}
@@ -0,0 +1,18 @@
+use clap::Subcommand;
+
+// `doit delete project --project <some-name>`
+// `doit delete task --task <task-ref>`
+
+#[derive(Subcommand)]
+pub enum DeleteCommands {
+    Project {
+        #[arg(long)]
+        project: String,
+    },
+
+    Task {
+        /// Task name, `project/task` path or short id (e.g. `t-4f2a`)
+        #[arg(long)]
+        task: String,
+    },
+}
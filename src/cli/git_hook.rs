@@ -0,0 +1,13 @@
+use clap::Subcommand;
+
+// `doit git-hook install [--repo <dir>]`
+
+#[derive(Subcommand)]
+pub enum GitHookCommands {
+    /// Add a post-commit hook to a repository that runs `doit git-sync`
+    /// after every commit
+    Install {
+        #[arg(long, default_value = ".")]
+        repo: String,
+    },
+}
@@ -1,39 +1,321 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+
+use crate::db::errors::DatabaseError;
+use crate::db::models::{Event, ImportReport};
+use crate::db::operations::plural;
+use crate::service::dates::format_local;
 use crate::service::errors::ServiceError;
+use crate::service::formats::{csv, Format, ImportOptions};
+use crate::service::git::GitSyncSummary;
+use crate::service::scan::ScanSummary;
 use crate::service::svc::DoitService;
+use crate::service::vault::SyncReport;
 
 use super::{
     add::AddCommands,
+    archive::ArchiveCommands,
+    db::DbCommands,
+    delete::DeleteCommands,
+    git_hook::GitHookCommands,
+    list::ListCommands,
+    picker::{confirm, terminal_picker},
+    thoughts::ThoughtsCommands,
+    trash::TrashCommands,
     types::{Cli, Commands},
+    update::UpdateCommands,
 };
 
 pub struct CommandHandler {
     service: DoitService,
+    dry_run: bool,
 }
 
 impl CommandHandler {
     pub fn new(database_url: &str) -> Result<Self, ServiceError> {
-        let service = DoitService::new(database_url)?;
-        Ok(CommandHandler { service })
+        Ok(Self::with_service(DoitService::new(database_url)?))
     }
 
+    /// For `db check --repair`, see [`DoitService::without_migrating`].
+    pub fn without_migrating(database_url: &str) -> Result<Self, ServiceError> {
+        Ok(Self::with_service(DoitService::without_migrating(
+            database_url,
+        )?))
+    }
+
+    fn with_service(mut service: DoitService) -> Self {
+        if let Some(picker) = terminal_picker() {
+            service.set_picker(picker);
+        }
+        CommandHandler {
+            service,
+            dry_run: false,
+        }
+    }
+
+    /// Runs a command. With `--dry-run` it runs inside a transaction that is
+    /// rolled back, and the changes it would have made are reported instead.
     pub fn handle_command(&mut self, cli: Cli) -> Result<String, ServiceError> {
-        match cli.command {
+        if !cli.dry_run {
+            return self.run(cli.command, cli.yes);
+        }
+
+        self.dry_run = true;
+        let dry_run = self.service.begin_dry_run()?;
+        let result = self.run(cli.command, true);
+        let changes = self.service.rollback_dry_run(dry_run)?;
+        let output = result?;
+
+        if changes.is_empty() {
+            return Ok(output);
+        }
+        Ok(changes
+            .iter()
+            .map(|change| format!("Would {}", change))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn run(&mut self, command: Commands, yes: bool) -> Result<String, ServiceError> {
+        match command {
             Commands::Add { subcmd } => self.handle_add_command(subcmd),
-            Commands::Update {} => Ok("Update command to be implemented".to_string()),
+            Commands::List { subcmd, csv } => self.handle_list_command(subcmd, csv),
+            Commands::Update { subcmd } => self.handle_update_command(subcmd),
+            Commands::Delete { subcmd } if yes => self.handle_delete_command(subcmd),
+            Commands::Delete { subcmd } => match self.confirm_delete(subcmd)? {
+                Some(subcmd) => self.handle_delete_command(subcmd),
+                None => Ok("Nothing was deleted".to_string()),
+            },
+            Commands::Archive {
+                subcmd: ArchiveCommands::Project { project },
+            } => {
+                let project = self.service.archive_project(project)?;
+                Ok(format!("Archived project '{}'", project.name))
+            }
+            Commands::Unarchive {
+                subcmd: ArchiveCommands::Project { project },
+            } => {
+                let project = self.service.unarchive_project(project)?;
+                Ok(format!("Unarchived project '{}'", project.name))
+            }
+            Commands::Backup { path } if self.dry_run => Ok(format!(
+                "Would back up to '{}'",
+                path.unwrap_or_else(|| "doit-backup-<timestamp>.db".to_string())
+            )),
+            Commands::Backup { path } => {
+                let path = self.service.backup(path)?;
+                Ok(format!("Backed up to '{}'", path.display()))
+            }
+            // the backup API writes outside the dry run's transaction, so
+            // only validate
+            Commands::Restore { file } if self.dry_run => {
+                self.service.validate_backup(&file)?;
+                Ok(format!("Would replace the database with '{}'", file))
+            }
+            Commands::Restore { file } => {
+                self.service.validate_backup(&file)?;
+                if !yes {
+                    match confirm(&format!(
+                        "This will replace the database with '{}'. Continue?",
+                        file
+                    )) {
+                        Some(true) => {}
+                        Some(false) => return Ok("Nothing was restored".to_string()),
+                        None => {
+                            return Err(ServiceError::InvalidInput(
+                                "Refusing to restore without confirmation, pass --yes to go ahead"
+                                    .to_string(),
+                            ))
+                        }
+                    }
+                }
+                self.service.restore(&file)?;
+                Ok(format!("Restored the database from '{}'", file))
+            }
+            Commands::Export {
+                format,
+                project,
+                output,
+            } => {
+                let document = self.service.export(format, project)?;
+                match output {
+                    Some(path) if self.dry_run => Ok(format!("Would export to '{}'", path)),
+                    Some(path) => {
+                        fs::write(&path, document + "\n").map_err(|e| {
+                            ServiceError::OperationFailed(format!(
+                                "Failed to write '{}': {}",
+                                path, e
+                            ))
+                        })?;
+                        Ok(format!("Exported to '{}'", path))
+                    }
+                    None => Ok(document),
+                }
+            }
+            Commands::Import {
+                file,
+                format,
+                replace,
+                project,
+                skip_conflicts,
+                map,
+                preview,
+            } => {
+                let format = match format.or_else(|| Format::from_path(Path::new(&file))) {
+                    Some(format) => format,
+                    None => {
+                        return Err(ServiceError::InvalidInput(format!(
+                            "Can't tell the format of '{}', pass --format",
+                            file
+                        )))
+                    }
+                };
+                let input = read_input(&file)?;
+                let options = ImportOptions {
+                    replace,
+                    skip_conflicts,
+                    project,
+                    map,
+                };
+
+                if let Some(limit) = preview {
+                    if format != Format::Csv {
+                        return Err(ServiceError::InvalidInput(
+                            "--preview only applies to CSV, try --dry-run".to_string(),
+                        ));
+                    }
+                    let rows = self.service.check_csv(&input, &options)?;
+                    return Ok(format_preview(&rows, limit));
+                }
+
+                if replace && !yes {
+                    match confirm("This will replace everything in the database. Continue?") {
+                        Some(true) => {}
+                        Some(false) => return Ok("Nothing was imported".to_string()),
+                        None => {
+                            return Err(ServiceError::InvalidInput(
+                                "Refusing to replace without confirmation, pass --yes to go ahead"
+                                    .to_string(),
+                            ))
+                        }
+                    }
+                }
+
+                let report = self
+                    .service
+                    .import(&input, format, options)
+                    .map_err(|e| match e {
+                        ServiceError::Database(DatabaseError::ImportConflict(paths)) => {
+                            ServiceError::InvalidInput(format!(
+                                "Nothing was imported, these already exist \
+                                 (pass --skip-conflicts to import the rest):\n  {}",
+                                paths.join("\n  ")
+                            ))
+                        }
+                        e => e,
+                    })?;
+                Ok(format_import(&report))
+            }
+            Commands::Db { subcmd } => self.handle_db_command(subcmd),
+            Commands::Trash { subcmd } => self.handle_trash_command(subcmd),
+            Commands::Thoughts {
+                subcmd: ThoughtsCommands::Sync { dir, prefer },
+            } => {
+                let report = self
+                    .service
+                    .sync_thoughts(Path::new(&dir), prefer, !self.dry_run)?;
+                Ok(format_sync(&report, self.dry_run))
+            }
+            Commands::Scan { dir, project } => {
+                let summary = self.service.scan(Path::new(&dir), project)?;
+                Ok(format_scan(&summary))
+            }
+            Commands::GitSync { repo, since } => {
+                let summary = self
+                    .service
+                    .git_sync(Path::new(&repo), since, !self.dry_run)?;
+                Ok(format_git_sync(&summary))
+            }
+            Commands::GitHook {
+                subcmd: GitHookCommands::Install { repo },
+            } => {
+                let path = self
+                    .service
+                    .install_git_hook(Path::new(&repo), !self.dry_run)?;
+                let verb = if self.dry_run {
+                    "Would install"
+                } else {
+                    "Installed"
+                };
+                Ok(format!("{} {}", verb, path.display()))
+            }
+            Commands::Log {
+                since,
+                until,
+                limit,
+            } => {
+                let events = self.service.get_log(since, until, Some(limit))?;
+                Ok(format_events(&events))
+            }
+            Commands::History { task, since, until } => {
+                let (task, events) = self.service.get_task_history(task, since, until)?;
+                Ok(format!(
+                    "History of '{}' ({})\n{}",
+                    task.name,
+                    task.short_id,
+                    format_events(&events)
+                ))
+            }
+            Commands::Undo { list: true } => {
+                let entries = self.service.get_undo_list(20)?;
+                if entries.is_empty() {
+                    return Ok("Nothing to undo".to_string());
+                }
+                Ok(entries
+                    .iter()
+                    .map(|entry| {
+                        format!("{}  {}", format_local(entry.created_on), entry.description)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            Commands::Undo { list: false } => {
+                let entry = self.service.undo()?;
+                let verb = if self.dry_run { "Would undo" } else { "Undid" };
+                Ok(format!("{}: {}", verb, entry.description))
+            }
+            Commands::Redo => {
+                let entry = self.service.redo()?;
+                let verb = if self.dry_run { "Would redo" } else { "Redid" };
+                Ok(format!("{}: {}", verb, entry.description))
+            }
         }
     }
 
     fn handle_add_command(&mut self, command: AddCommands) -> Result<String, ServiceError> {
         match command {
             AddCommands::Project { project } => {
-                self.service.add_new_project(project.clone())?;
-                Ok(format!("Successfully created project '{}'", project))
+                let project = self.service.add_new_project(project)?;
+                Ok(format!(
+                    "Successfully created project '{}' (id {})",
+                    project.name, project.id
+                ))
             }
             AddCommands::Task { project, task } => {
-                self.service.add_task(project.clone(), task.clone())?;
+                let task = self.service.add_task(project.clone(), task)?;
                 Ok(format!(
-                    "Successfully added task '{}' to project '{}'",
-                    task, project
+                    "Successfully added task '{}' ({}) to project '{}'",
+                    task.name, task.short_id, project
+                ))
+            }
+            AddCommands::Subtask { task, subtask } => {
+                let subtask = self.service.add_subtask(task.clone(), subtask)?;
+                Ok(format!(
+                    "Successfully added subtask '{}' ({}) to task '{}'",
+                    subtask.name, subtask.short_id, task
                 ))
             }
             AddCommands::Point { point: _ } => Ok("Point command not implemented yet".to_string()),
@@ -42,4 +324,600 @@ impl CommandHandler {
             }
         }
     }
+
+    fn handle_update_command(&mut self, command: UpdateCommands) -> Result<String, ServiceError> {
+        match command {
+            UpdateCommands::Task {
+                task,
+                name,
+                done,
+                not_done,
+            } => {
+                let mut messages = Vec::new();
+                let mut task = task;
+
+                if let Some(name) = name {
+                    let old = self.service.resolve_task(&task)?;
+                    let renamed = self.service.rename_task(old.short_id, name)?;
+                    messages.push(format!("Renamed task '{}' to '{}'", old.name, renamed.name));
+                    task = renamed.short_id;
+                }
+
+                if done || not_done {
+                    let updated = self.service.update_task_status(task, done)?;
+                    let status = if done { "done" } else { "not done" };
+                    messages.push(format!("Marked task '{}' as {}", updated.name, status));
+                }
+
+                if messages.is_empty() {
+                    return Err(ServiceError::InvalidInput(
+                        "Nothing to update, pass --name, --done or --not-done".to_string(),
+                    ));
+                }
+
+                Ok(messages.join("\n"))
+            }
+        }
+    }
+
+    fn handle_delete_command(&mut self, command: DeleteCommands) -> Result<String, ServiceError> {
+        match command {
+            DeleteCommands::Project { project } => {
+                let project = self.service.delete_project(project)?;
+                Ok(format!("Moved project '{}' to the trash", project.name))
+            }
+            DeleteCommands::Task { task } => {
+                let task = self.service.delete_task(task)?;
+                Ok(format!(
+                    "Successfully deleted task '{}' ({})",
+                    task.name, task.short_id
+                ))
+            }
+        }
+    }
+
+    /// Deletes cascade, so unless `--yes` was given the command is first
+    /// run as a dry run and the user is shown what it would take with it.
+    /// Returns the command with its reference pinned to what was resolved,
+    /// so confirming doesn't prompt for a pick twice.
+    fn confirm_delete(
+        &mut self,
+        command: DeleteCommands,
+    ) -> Result<Option<DeleteCommands>, ServiceError> {
+        let dry_run = self.service.begin_dry_run()?;
+        let result = match &command {
+            DeleteCommands::Project { project } => self
+                .service
+                .delete_project(project.clone())
+                .map(|p| DeleteCommands::Project { project: p.name }),
+            DeleteCommands::Task { task } => self
+                .service
+                .delete_task(task.clone())
+                .map(|t| DeleteCommands::Task { task: t.short_id }),
+        };
+        let changes = self.service.rollback_dry_run(dry_run)?;
+        let command = result?;
+
+        match confirm(&format!("This will {}. Continue?", changes.join(", "))) {
+            Some(true) => Ok(Some(command)),
+            Some(false) => Ok(None),
+            None => Err(ServiceError::InvalidInput(
+                "Refusing to delete without confirmation, pass --yes to go ahead".to_string(),
+            )),
+        }
+    }
+
+    fn handle_db_command(&mut self, command: DbCommands) -> Result<String, ServiceError> {
+        match command {
+            DbCommands::Check { repair } => {
+                // Repairing comes first: it's what brings a database whose
+                // migrations were held back up to the schema checked below.
+                let mut lines = Vec::new();
+                if repair {
+                    let repaired = self.service.repair_database()?;
+                    let verb = if self.dry_run {
+                        "Would remove"
+                    } else {
+                        "Removed"
+                    };
+                    for task in &repaired.tasks {
+                        lines.push(format!(
+                            "{} orphaned task '{}' in missing project {}",
+                            verb, task.name, task.parent_id
+                        ));
+                    }
+                    for subtask in &repaired.subtasks {
+                        lines.push(format!(
+                            "{} orphaned subtask '{}' of missing task {}",
+                            verb, subtask.name, subtask.parent_id
+                        ));
+                    }
+                    if repaired.removed() > 0 {
+                        lines.push(format!("{} {} orphaned rows", verb, repaired.removed()));
+                    }
+                }
+
+                let report = self.service.check_database()?;
+                if report.is_ok() {
+                    if lines.is_empty() {
+                        return Ok("No problems found".to_string());
+                    }
+                    return Ok(lines.join("\n"));
+                }
+
+                for problem in &report.integrity {
+                    lines.push(format!("integrity: {}", problem));
+                }
+                for problem in &report.foreign_keys {
+                    lines.push(format!("foreign key: {}", problem));
+                }
+                for task in &report.orphaned_tasks {
+                    lines.push(format!(
+                        "orphaned task '{}' ({}) in missing project {}",
+                        task.name, task.short_id, task.project_id
+                    ));
+                }
+                for subtask in &report.orphaned_subtasks {
+                    lines.push(format!(
+                        "orphaned subtask '{}' ({}) of missing task {}",
+                        subtask.name, subtask.short_id, subtask.task_id
+                    ));
+                }
+
+                if !report.orphaned_tasks.is_empty() || !report.orphaned_subtasks.is_empty() {
+                    lines.push("Run `doit db check --repair` to remove orphaned rows".to_string());
+                }
+                if !report.integrity.is_empty() {
+                    lines.push(
+                        "The file itself is damaged; `doit restore` a backup or snapshot"
+                            .to_string(),
+                    );
+                }
+
+                Ok(lines.join("\n"))
+            }
+            // VACUUM can't run inside the dry run's transaction
+            DbCommands::Vacuum if self.dry_run => Ok("Would vacuum the database".to_string()),
+            DbCommands::Vacuum => {
+                let (before, after) = self.service.vacuum_database()?;
+                Ok(format!(
+                    "Vacuumed the database: {} -> {}",
+                    format_size(before),
+                    format_size(after)
+                ))
+            }
+            DbCommands::Stats => {
+                let (stats, size) = self.service.get_database_stats()?;
+                Ok([
+                    format!(
+                        "projects   {:>7}  ({} archived, {} in trash)",
+                        stats.projects, stats.archived_projects, stats.trashed_projects
+                    ),
+                    format!(
+                        "tasks      {:>7}  ({} done, {} in trash)",
+                        stats.tasks, stats.completed_tasks, stats.trashed_tasks
+                    ),
+                    format!(
+                        "subtasks   {:>7}  ({} in trash)",
+                        stats.subtasks, stats.trashed_subtasks
+                    ),
+                    format!("thoughts   {:>7}", stats.thoughts),
+                    format!("events     {:>7}", stats.events),
+                    format!("journal    {:>7}", stats.journal_entries),
+                    format!("file size  {:>7}", format_size(size)),
+                ]
+                .join("\n"))
+            }
+        }
+    }
+
+    fn handle_trash_command(&mut self, command: TrashCommands) -> Result<String, ServiceError> {
+        match command {
+            TrashCommands::List => {
+                let items = self.service.get_trash()?;
+                if items.is_empty() {
+                    return Ok("Trash is empty".to_string());
+                }
+                Ok(items
+                    .iter()
+                    .map(|item| {
+                        format!(
+                            "{:<8} {:<8} {:<40} deleted {}",
+                            item.short_id().unwrap_or("-"),
+                            item.kind().as_str(),
+                            item.path(),
+                            item.deleted_on().map(format_local).unwrap_or_default()
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            TrashCommands::Restore { item } => {
+                let item = self.service.restore_from_trash(item)?;
+                Ok(format!(
+                    "Restored {} '{}'",
+                    item.kind().as_str(),
+                    item.path()
+                ))
+            }
+            TrashCommands::Empty { older_than } => {
+                let purged = self.service.empty_trash(older_than)?;
+                let verb = if self.dry_run {
+                    "Would permanently remove"
+                } else {
+                    "Permanently removed"
+                };
+                Ok(format!("{} {} items from the trash", verb, purged))
+            }
+        }
+    }
+
+    fn handle_list_command(
+        &mut self,
+        command: ListCommands,
+        as_csv: bool,
+    ) -> Result<String, ServiceError> {
+        match command {
+            ListCommands::Projects { archived } => {
+                let projects = self.service.get_all_projects(archived)?;
+                if as_csv {
+                    return csv::write(
+                        &["name", "created_on", "archived_on"],
+                        projects.iter().map(|project| {
+                            vec![
+                                project.name.clone(),
+                                format_local(project.created_on),
+                                project.archived_on.map(format_local).unwrap_or_default(),
+                            ]
+                        }),
+                    );
+                }
+                Ok(projects
+                    .iter()
+                    .map(|project| match project.archived_on {
+                        Some(archived_on) => format!(
+                            "{:<24} archived {}",
+                            project.name,
+                            format_local(archived_on)
+                        ),
+                        None => format!(
+                            "{:<24} added {}",
+                            project.name,
+                            format_local(project.created_on)
+                        ),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            ListCommands::Tasks { project } => {
+                let tasks = self.service.get_all_tasks(project)?;
+                if as_csv {
+                    return csv::write(
+                        &[
+                            "id",
+                            "name",
+                            "done",
+                            "created_on",
+                            "completed_on",
+                            "priority",
+                            "due",
+                            "tags",
+                        ],
+                        tasks.iter().map(|task| {
+                            vec![
+                                task.short_id.clone(),
+                                task.name.clone(),
+                                csv::yes_no(task.completed),
+                                format_local(task.created_on),
+                                task.completed_on
+                                    .filter(|_| task.completed)
+                                    .map(format_local)
+                                    .unwrap_or_default(),
+                                task.priority.clone().unwrap_or_default(),
+                                task.due_on.map(|due| due.to_string()).unwrap_or_default(),
+                                task.tags().collect::<Vec<_>>().join(" "),
+                            ]
+                        }),
+                    );
+                }
+                Ok(tasks
+                    .iter()
+                    .map(|task| {
+                        format_item(
+                            &task.short_id,
+                            &task.name,
+                            task.created_on,
+                            task.completed_on.filter(|_| task.completed),
+                            task.completed,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            ListCommands::Subtasks { task } => {
+                let subtasks = self.service.get_all_subtasks(task)?;
+                if as_csv {
+                    return csv::write(
+                        &["id", "name", "done", "created_on", "completed_on"],
+                        subtasks.iter().map(|subtask| {
+                            vec![
+                                subtask.short_id.clone(),
+                                subtask.name.clone(),
+                                csv::yes_no(subtask.completed),
+                                format_local(subtask.created_on),
+                                subtask
+                                    .completed_on
+                                    .filter(|_| subtask.completed)
+                                    .map(format_local)
+                                    .unwrap_or_default(),
+                            ]
+                        }),
+                    );
+                }
+                Ok(subtasks
+                    .iter()
+                    .map(|subtask| {
+                        format_item(
+                            &subtask.short_id,
+                            &subtask.name,
+                            subtask.created_on,
+                            subtask.completed_on.filter(|_| subtask.completed),
+                            subtask.completed,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+        }
+    }
+}
+
+fn format_item(
+    short_id: &str,
+    name: &str,
+    created_on: NaiveDateTime,
+    completed_on: Option<NaiveDateTime>,
+    completed: bool,
+) -> String {
+    let mark = if completed { "x" } else { " " };
+    let when = match completed_on {
+        Some(completed_on) => format!("done {}", format_local(completed_on)),
+        None => format!("added {}", format_local(created_on)),
+    };
+    format!("{:<8} [{}] {:<32} {}", short_id, mark, name, when)
+}
+
+fn format_import(report: &ImportReport) -> String {
+    let mut lines = vec![format!(
+        "Imported {}, {}, {} and {}",
+        plural(report.projects, "project"),
+        plural(report.tasks, "task"),
+        plural(report.subtasks, "subtask"),
+        plural(report.thoughts, "thought")
+    )];
+    if report.updated > 0 {
+        lines.push(format!(
+            "Updated {}",
+            plural(report.updated, "existing item")
+        ));
+    }
+    if !report.conflicts.is_empty() {
+        lines.push("Skipped, as they already exist:".to_string());
+        lines.extend(report.conflicts.iter().map(|path| format!("  {}", path)));
+    }
+
+    lines.join("\n")
+}
+
+fn format_preview(rows: &[csv::Row], limit: usize) -> String {
+    let error_line = |row: &csv::Row| {
+        let error = row.values.as_ref().err()?;
+        Some(format!("{:<6} error: {}", row.line, error))
+    };
+
+    let mut lines = vec![preview_line([
+        "line", "project", "name", "done", "pri", "due", "tags",
+    ])];
+    for row in rows.iter().take(limit) {
+        let Ok(values) = &row.values else {
+            lines.extend(error_line(row));
+            continue;
+        };
+        let due = values.due_on.map(|due| due.to_string()).unwrap_or_default();
+        lines.push(preview_line([
+            &row.line.to_string(),
+            values.project.as_deref().unwrap_or_default(),
+            &values.name,
+            if values.done { "x" } else { "" },
+            values.priority.as_deref().unwrap_or_default(),
+            &due,
+            &values.tags.join(" "),
+        ]));
+    }
+
+    let further: Vec<String> = rows.iter().skip(limit).filter_map(error_line).collect();
+    if !further.is_empty() {
+        lines.push("Errors further down:".to_string());
+        lines.extend(further);
+    }
+    let failing = rows.iter().filter(|row| row.values.is_err()).count();
+    lines.push(format!(
+        "Showing {} of {}, {} with errors. Nothing was imported",
+        rows.len().min(limit),
+        plural(rows.len(), "row"),
+        failing
+    ));
+
+    lines.join("\n")
+}
+
+/// Line, project, name, done, priority, due and tags, lined up.
+fn preview_line(cells: [&str; 7]) -> String {
+    let [line, project, name, done, priority, due, tags] = cells;
+    format!(
+        "{:<6} {:<16} {:<32} {:<4} {:<3} {:<10} {}",
+        line, project, name, done, priority, due, tags
+    )
+    .trim_end()
+    .to_string()
+}
+
+fn format_sync(report: &SyncReport, dry_run: bool) -> String {
+    let mut lines = Vec::new();
+    let [add, update, delete, write, remove] = match dry_run {
+        true => ["Would add", "update", "delete", "Would write", "remove"],
+        false => ["Added", "updated", "deleted", "Wrote", "removed"],
+    };
+    if report.added + report.updated + report.deleted > 0 {
+        lines.push(format!(
+            "{} {}, {} {} and {} {}",
+            add,
+            plural(report.added, "thought"),
+            update,
+            report.updated,
+            delete,
+            report.deleted
+        ));
+    }
+    if report.written + report.removed > 0 {
+        lines.push(format!(
+            "{} {} and {} {}",
+            write,
+            plural(report.written, "file"),
+            remove,
+            report.removed
+        ));
+    }
+    if lines.is_empty() && report.conflicts.is_empty() {
+        lines.push("Already in sync".to_string());
+    }
+    if !report.conflicts.is_empty() {
+        lines.push(
+            "Left alone on both sides, pass --prefer doit or --prefer vault to settle:".to_string(),
+        );
+        lines.extend(
+            report
+                .conflicts
+                .iter()
+                .map(|conflict| format!("  {}", conflict)),
+        );
+    }
+
+    lines.join("\n")
+}
+
+fn format_scan(summary: &ScanSummary) -> String {
+    let report = &summary.report;
+    let mut lines = vec![format!(
+        "Scanned {} and found {}",
+        plural(summary.files, "file"),
+        plural(summary.comments, "comment")
+    )];
+    lines.extend(report.added.iter().map(|name| format!("Added '{}'", name)));
+    lines.extend(
+        report
+            .reopened
+            .iter()
+            .map(|name| format!("Reopened '{}', its comment is back", name)),
+    );
+    lines.extend(
+        report
+            .closed
+            .iter()
+            .map(|name| format!("Marked '{}' as done, its comment is gone", name)),
+    );
+    if report.moved > 0 {
+        lines.push(format!(
+            "Updated where {} {}",
+            plural(report.moved, "comment"),
+            if report.moved == 1 { "is" } else { "are" }
+        ));
+    }
+
+    lines.join("\n")
+}
+
+fn format_git_sync(summary: &GitSyncSummary) -> String {
+    let report = &summary.report;
+    let mut lines = Vec::new();
+    if let Some(lost) = &summary.lost {
+        lines.push(format!(
+            "The last synced commit {} no longer exists, so only the latest commit was \
+             read; use --since to read from an earlier one",
+            lost
+        ));
+    }
+    lines.push(format!("Read {}", plural(summary.commits, "commit")));
+    lines.extend(
+        report
+            .closed
+            .iter()
+            .map(|name| format!("Marked '{}' as done", name)),
+    );
+    lines.extend(
+        report
+            .referenced
+            .iter()
+            .map(|name| format!("Attached a commit to '{}'", name)),
+    );
+    if !summary.unresolved.is_empty() {
+        lines.push("Skipped:".to_string());
+        lines.extend(summary.unresolved.iter().map(|e| format!("  {}", e)));
+    }
+
+    lines.join("\n")
+}
+
+/// Reads a file, or standard input for `-`.
+fn read_input(file: &str) -> Result<String, ServiceError> {
+    let result = if file == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input).map(|_| input)
+    } else {
+        fs::read_to_string(file)
+    };
+
+    result.map_err(|e| ServiceError::InvalidInput(format!("Failed to read '{}': {}", file, e)))
+}
+
+fn format_events(events: &[Event]) -> String {
+    if events.is_empty() {
+        return "No changes recorded".to_string();
+    }
+
+    events
+        .iter()
+        .map(|event| {
+            let old = event.old_value.as_deref().unwrap_or("");
+            let new = event.new_value.as_deref().unwrap_or("");
+            let change = match event.action.as_str() {
+                "create" => "created".to_string(),
+                "rename" => format!("renamed '{}' -> '{}'", old, new),
+                "status" => format!("{} -> {}", old, new),
+                "delete" => "deleted".to_string(),
+                "restore" => "restored".to_string(),
+                "purge" => "purged from trash".to_string(),
+                "archive" => "archived".to_string(),
+                "unarchive" => "unarchived".to_string(),
+                "edit" => "edited".to_string(),
+                other => other.to_string(),
+            };
+            format!(
+                "{}  {:<8} {:<32} {}",
+                format_local(event.created_on),
+                event.entity_type,
+                event.entity_name,
+                change
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MiB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KiB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
 }
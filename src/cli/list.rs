@@ -0,0 +1,26 @@
+use clap::Subcommand;
+
+// `doit list projects [--archived]`
+// `doit list tasks --project <some-name>`
+// `doit list subtasks --task <task-ref>`
+// any of them with `--csv`
+
+#[derive(Subcommand)]
+pub enum ListCommands {
+    Projects {
+        /// Include archived projects
+        #[arg(long)]
+        archived: bool,
+    },
+
+    Tasks {
+        #[arg(long)]
+        project: String,
+    },
+
+    Subtasks {
+        /// Task name, `project/task` path or short id (e.g. `t-4f2a`)
+        #[arg(long)]
+        task: String,
+    },
+}
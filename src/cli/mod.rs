@@ -1,3 +1,12 @@
 pub mod add;
+pub mod archive;
+pub mod db;
+pub mod delete;
+pub mod git_hook;
 pub mod handler;
+pub mod list;
+pub mod picker;
+pub mod thoughts;
+pub mod trash;
 pub mod types;
+pub mod update;
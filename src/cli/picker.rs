@@ -0,0 +1,51 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::service::svc::Picker;
+
+/// Returns an interactive picker if both stdin and stdout are terminals, so
+/// scripts and pipes keep getting a plain "not found" error.
+pub fn terminal_picker() -> Option<Picker> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+
+    Some(Box::new(pick))
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no. Returns `None`
+/// when there is no terminal to ask on.
+pub fn confirm(question: &str) -> Option<bool> {
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return None;
+    }
+
+    let mut stderr = io::stderr();
+    write!(stderr, "{} [y/N] ", question).ok()?;
+    stderr.flush().ok()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).ok()?;
+
+    Some(matches!(line.trim(), "y" | "Y" | "yes"))
+}
+
+fn pick(entity: &str, name: &str, suggestions: &[String]) -> Option<String> {
+    let mut stderr = io::stderr();
+    writeln!(
+        stderr,
+        "{} '{}' does not exist. Did you mean:",
+        entity, name
+    )
+    .ok()?;
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        writeln!(stderr, "  {}) {}", i + 1, suggestion).ok()?;
+    }
+    write!(stderr, "Pick a number (enter to cancel): ").ok()?;
+    stderr.flush().ok()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).ok()?;
+    let choice: usize = line.trim().parse().ok()?;
+
+    suggestions.get(choice.checked_sub(1)?).cloned()
+}
@@ -0,0 +1,19 @@
+use clap::Subcommand;
+
+use crate::service::vault::Side;
+
+// `doit thoughts sync <vault-dir> [--prefer doit|vault]`
+
+#[derive(Subcommand)]
+pub enum ThoughtsCommands {
+    /// Sync thoughts with a directory of Markdown files, such as an
+    /// Obsidian vault, both ways
+    Sync {
+        /// The directory, a file per thought
+        dir: String,
+        /// Settle thoughts changed on both sides since the last sync this
+        /// way, instead of leaving them alone
+        #[arg(long, value_enum)]
+        prefer: Option<Side>,
+    },
+}
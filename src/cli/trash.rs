@@ -0,0 +1,22 @@
+use clap::Subcommand;
+
+// `doit trash list`
+// `doit trash restore <item>`
+// `doit trash empty [--older-than 30d]`
+
+#[derive(Subcommand)]
+pub enum TrashCommands {
+    /// Show deleted items, most recent first
+    List,
+    /// Bring a deleted item back, along with anything deleted with it
+    Restore {
+        /// Short id, path (e.g. `backend/deploy`) or name
+        item: String,
+    },
+    /// Permanently remove deleted items
+    Empty {
+        /// Only items deleted longer ago than this (e.g. 30d, 2w)
+        #[arg(long)]
+        older_than: Option<String>,
+    },
+}
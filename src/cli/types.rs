@@ -1,4 +1,13 @@
 use super::add::AddCommands;
+use super::archive::ArchiveCommands;
+use super::db::DbCommands;
+use super::delete::DeleteCommands;
+use super::git_hook::GitHookCommands;
+use super::list::ListCommands;
+use super::thoughts::ThoughtsCommands;
+use super::trash::TrashCommands;
+use super::update::UpdateCommands;
+use crate::service::formats::Format;
 use clap::Parser;
 
 #[derive(Parser)]
@@ -6,6 +15,30 @@ use clap::Parser;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// Show what would change without changing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Don't ask for confirmation before deleting or restoring
+    #[arg(long, short, global = true)]
+    pub yes: bool,
+}
+
+impl Cli {
+    /// Whether the database should be opened without running its pending
+    /// migrations. `db check --repair` runs them itself once the orphans
+    /// that could stop them are gone; a dry run needs the current schema
+    /// to track what it changed, so it migrates up front as usual.
+    pub fn defers_migrations(&self) -> bool {
+        !self.dry_run
+            && matches!(
+                self.command,
+                Commands::Db {
+                    subcmd: DbCommands::Check { repair: true }
+                }
+            )
+    }
 }
 
 #[derive(clap::Subcommand)]
@@ -16,6 +49,162 @@ pub enum Commands {
         subcmd: AddCommands,
     },
 
+    /// List existing stuff
+    List {
+        #[command(subcommand)]
+        subcmd: ListCommands,
+        /// Write the listing as CSV
+        #[arg(long, global = true)]
+        csv: bool,
+    },
+
     /// Update existing stuff
-    Update {},
+    Update {
+        #[command(subcommand)]
+        subcmd: UpdateCommands,
+    },
+
+    /// Delete existing stuff
+    Delete {
+        #[command(subcommand)]
+        subcmd: DeleteCommands,
+    },
+
+    /// Hide a finished project from listings, keeping it for reference
+    Archive {
+        #[command(subcommand)]
+        subcmd: ArchiveCommands,
+    },
+
+    /// Bring an archived project back
+    Unarchive {
+        #[command(subcommand)]
+        subcmd: ArchiveCommands,
+    },
+
+    /// Copy the database to a file, safe to run while doit is in use
+    Backup {
+        /// Where to write the backup (default: doit-backup-<timestamp>.db)
+        path: Option<String>,
+    },
+
+    /// Replace the database with a backup. The current database is
+    /// snapshotted first
+    Restore { file: String },
+
+    /// Write out the whole database, or one project
+    Export {
+        #[arg(long, value_enum, default_value_t = Format::Json)]
+        format: Format,
+        /// Only this project's tasks, leaving out thoughts
+        #[arg(long)]
+        project: Option<String>,
+        /// Write to this file instead of standard output
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+
+    /// Read in a file written by `doit export` or another tool, all or
+    /// nothing
+    Import {
+        /// The file to import, or `-` for standard input
+        file: String,
+        /// Guessed from the file's extension when not given
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Remove everything first instead of merging, the trash included
+        #[arg(long)]
+        replace: bool,
+        /// Put Markdown checklist items and Org tasks in this project
+        /// instead of the one named by their heading, and tasks of other
+        /// formats that don't name one
+        #[arg(long)]
+        project: Option<String>,
+        /// Leave out items whose names are taken instead of failing
+        #[arg(long)]
+        skip_conflicts: bool,
+        /// Which CSV column each field comes from, e.g.
+        /// `name=Title,project=Area,done=Status`
+        #[arg(long, value_name = "FIELD=COLUMN,...")]
+        map: Option<String>,
+        /// Show how the first N rows of a CSV file read and which rows have
+        /// errors, without importing anything
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+        preview: Option<usize>,
+    },
+
+    /// Check, vacuum or inspect the database
+    Db {
+        #[command(subcommand)]
+        subcmd: DbCommands,
+    },
+
+    /// Inspect, restore or empty deleted items
+    Trash {
+        #[command(subcommand)]
+        subcmd: TrashCommands,
+    },
+
+    /// Keep thoughts in sync with Markdown notes
+    Thoughts {
+        #[command(subcommand)]
+        subcmd: ThoughtsCommands,
+    },
+
+    /// Keep a project's tasks in line with the TODO, FIXME and HACK
+    /// comments in a source tree, closing tasks whose comment is gone
+    Scan {
+        /// The directory to scan, respecting its .gitignore files
+        dir: String,
+        #[arg(long)]
+        project: String,
+    },
+
+    /// Mark tasks named in `Closes:` trailers of the commits made since the
+    /// last sync done, and attach commits to tasks named in `Refs:`
+    GitSync {
+        #[arg(long, default_value = ".")]
+        repo: String,
+        /// Read the commits after this one instead (a hash, tag or branch)
+        #[arg(long)]
+        since: Option<String>,
+    },
+
+    /// Sync tasks with a repository's commits as they're made
+    GitHook {
+        #[command(subcommand)]
+        subcmd: GitHookCommands,
+    },
+
+    /// Show recent changes, newest first
+    Log {
+        /// Only changes at or after this date (YYYY-MM-DD, today, 7d, ...)
+        #[arg(long)]
+        since: Option<String>,
+        /// Only changes up to and including this date
+        #[arg(long)]
+        until: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+
+    /// Show the changes made to a task and its subtasks
+    History {
+        /// Task name, `project/task` path or short id (e.g. `t-4f2a`)
+        task: String,
+        #[arg(long)]
+        since: Option<String>,
+        #[arg(long)]
+        until: Option<String>,
+    },
+
+    /// Revert the last change
+    Undo {
+        /// Show the changes that would be reverted instead
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Reapply the last undone change
+    Redo,
 }
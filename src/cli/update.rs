@@ -0,0 +1,20 @@
+use clap::Subcommand;
+
+// `doit update task --task <task-ref> --name <new-name>`
+// `doit update task --task <task-ref> --done`
+// `doit update task --task <task-ref> --not-done`
+
+#[derive(Subcommand)]
+pub enum UpdateCommands {
+    Task {
+        /// Task name, `project/task` path or short id (e.g. `t-4f2a`)
+        #[arg(long)]
+        task: String,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, conflicts_with = "not_done")]
+        done: bool,
+        #[arg(long)]
+        not_done: bool,
+    },
+}
@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Local;
+use diesel::migration::MigrationSource;
+use diesel::sqlite::Sqlite;
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+
+use super::errors::DatabaseError;
+use super::operations::MIGRATIONS;
+
+/// How many snapshots to keep when `DOIT_SNAPSHOT_KEEP` isn't set.
+pub const DEFAULT_SNAPSHOT_KEEP: usize = 7;
+
+const PAGES_PER_STEP: i32 = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Copies the database at `database_url` into `dest` with SQLite's online
+/// backup API, so it's consistent even while another process is writing.
+pub fn backup(database_url: &str, dest: &Path) -> Result<(), DatabaseError> {
+    if dest.exists() {
+        return Err(DatabaseError::BackupError(format!(
+            "'{}' already exists",
+            dest.display()
+        )));
+    }
+
+    let src = open(database_url, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut dst = open(dest, OpenFlags::default())?;
+
+    copy(&src, &mut dst)?;
+    // The copy inherits WAL mode from the live database. A backup should be
+    // a single self-contained file, and one opened read-only to validate it
+    // would otherwise leave -wal and -shm files behind.
+    dst.pragma_update(None, "journal_mode", "DELETE")
+        .map_err(backup_error)
+}
+
+/// Replaces the database at `database_url` with the contents of `src`
+/// after checking it is a doit database this build can read. The current
+/// database is snapshotted first.
+pub fn restore(database_url: &str, src: &Path) -> Result<(), DatabaseError> {
+    let src = open_backup(src)?;
+
+    snapshot(database_url, "pre-restore")?;
+
+    let mut dst = open(database_url, OpenFlags::default())?;
+    copy(&src, &mut dst)
+}
+
+/// Opens a backup file and checks it can be restored.
+pub fn open_backup(path: &Path) -> Result<Connection, DatabaseError> {
+    if !path.is_file() {
+        return Err(DatabaseError::BackupError(format!(
+            "'{}' does not exist",
+            path.display()
+        )));
+    }
+
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(backup_error)?;
+    validate(&conn)?;
+
+    Ok(conn)
+}
+
+/// `doit-backup-20241104-093000.db` in the current directory.
+pub fn default_backup_path(database_url: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}-backup-{}.db",
+        file_stem(database_url),
+        Local::now().format("%Y%m%d-%H%M%S")
+    ))
+}
+
+/// Takes the day's snapshot if there isn't one yet, or a fresh one when
+/// `force` is set (before migrating), then prunes down to `keep`. Does
+/// nothing for in-memory or empty databases, or when `keep` is 0.
+pub fn daily_snapshot(database_url: &str, keep: usize, force: bool) -> Result<(), DatabaseError> {
+    let exists = fs::metadata(database_url)
+        .map(|meta| meta.is_file() && meta.len() > 0)
+        .unwrap_or(false);
+    if keep == 0 || !exists {
+        return Ok(());
+    }
+
+    let today = Local::now().format("%Y%m%d").to_string();
+    let taken_today = list_snapshots(database_url)?
+        .iter()
+        .any(|path| snapshot_date(database_url, path) == Some(today.as_str()));
+
+    if force || !taken_today {
+        snapshot(database_url, if force { "pre-migration" } else { "daily" })?;
+    }
+
+    prune_snapshots(database_url, keep)
+}
+
+/// Reads the retention from `DOIT_SNAPSHOT_KEEP`, falling back to
+/// [`DEFAULT_SNAPSHOT_KEEP`].
+pub fn snapshot_keep() -> usize {
+    std::env::var("DOIT_SNAPSHOT_KEEP")
+        .ok()
+        .and_then(|keep| keep.trim().parse().ok())
+        .unwrap_or(DEFAULT_SNAPSHOT_KEEP)
+}
+
+/// Snapshots live next to the database, e.g. `doit.db.snapshots/`.
+pub fn snapshot_dir(database_url: &str) -> PathBuf {
+    PathBuf::from(format!("{}.snapshots", database_url))
+}
+
+/// Existing snapshots, oldest first.
+pub fn list_snapshots(database_url: &str) -> Result<Vec<PathBuf>, DatabaseError> {
+    let dir = snapshot_dir(database_url);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(&dir)
+        .map_err(|e| DatabaseError::BackupError(e.to_string()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| snapshot_date(database_url, path).is_some())
+        .collect();
+    // names start with a sortable timestamp
+    snapshots.sort();
+
+    Ok(snapshots)
+}
+
+fn snapshot(database_url: &str, reason: &str) -> Result<PathBuf, DatabaseError> {
+    let dir = snapshot_dir(database_url);
+    fs::create_dir_all(&dir).map_err(|e| DatabaseError::BackupError(e.to_string()))?;
+
+    let path = dir.join(format!(
+        "{}-{}-{}.db",
+        file_stem(database_url),
+        Local::now().format("%Y%m%d-%H%M%S"),
+        reason
+    ));
+    backup(database_url, &path)?;
+
+    Ok(path)
+}
+
+fn prune_snapshots(database_url: &str, keep: usize) -> Result<(), DatabaseError> {
+    let snapshots = list_snapshots(database_url)?;
+    let excess = snapshots.len().saturating_sub(keep);
+
+    for path in &snapshots[..excess] {
+        fs::remove_file(path).map_err(|e| DatabaseError::BackupError(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// The `YYYYMMDD` part of a snapshot's file name, if it is one.
+fn snapshot_date<'a>(database_url: &str, path: &'a Path) -> Option<&'a str> {
+    let name = path.file_name()?.to_str()?;
+    let rest = name.strip_prefix(file_stem(database_url).as_str())?;
+    let date = rest.strip_prefix('-')?.get(..8)?;
+
+    date.bytes().all(|b| b.is_ascii_digit()).then_some(date)
+}
+
+fn file_stem(database_url: &str) -> String {
+    Path::new(database_url)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("doit")
+        .to_string()
+}
+
+/// Checks that `conn` holds an intact doit database whose migrations are
+/// all known to this build. Older ones are fine, they get migrated on the
+/// next start.
+fn validate(conn: &Connection) -> Result<(), DatabaseError> {
+    let check: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| DatabaseError::BackupError(format!("Not a SQLite database: {}", e)))?;
+    if check != "ok" {
+        return Err(DatabaseError::BackupError(format!(
+            "Backup is corrupt: {}",
+            check
+        )));
+    }
+
+    let mut statement = conn
+        .prepare("SELECT version FROM __diesel_schema_migrations")
+        .map_err(|_| DatabaseError::BackupError("Not a doit database".to_string()))?;
+    let applied = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(backup_error)?;
+
+    let known: HashSet<String> = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+        .map_err(|e| DatabaseError::MigrationError(e.to_string()))?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+
+    match applied.iter().find(|version| !known.contains(*version)) {
+        Some(version) => Err(DatabaseError::BackupError(format!(
+            "Backup has schema version {}, which is newer than this version of doit",
+            version
+        ))),
+        None if applied.is_empty() => Err(DatabaseError::BackupError(
+            "Not a doit database".to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Opens a connection that waits on other processes' locks like
+/// [`super::operations::Database`] does.
+fn open<P: AsRef<Path>>(path: P, flags: OpenFlags) -> Result<Connection, DatabaseError> {
+    let conn = Connection::open_with_flags(path, flags).map_err(backup_error)?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(backup_error)?;
+
+    Ok(conn)
+}
+
+fn copy(src: &Connection, dst: &mut Connection) -> Result<(), DatabaseError> {
+    Backup::new(src, dst)
+        .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None))
+        .map_err(backup_error)
+}
+
+fn backup_error(error: rusqlite::Error) -> DatabaseError {
+    DatabaseError::BackupError(error.to_string())
+}
@@ -1,21 +1,101 @@
-use diesel::result::Error as DieselError;
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
 use std::fmt;
 
 #[derive(Debug)]
 pub enum DatabaseError {
     NotFound(String),
     AlreadyExists(String),
+    UniqueViolation(ConstraintViolation),
+    ForeignKeyViolation(ConstraintViolation),
+    NotNullViolation(ConstraintViolation),
+    CheckViolation(ConstraintViolation),
+    Busy(String),
     ConnectionError(String),
+    MigrationError(String),
+    BackupError(String),
+    /// Paths of imported items whose names are already taken.
+    ImportConflict(Vec<String>),
     TransactionError(String),
     Unknown(String),
 }
 
+/// Table and column context extracted from a failed SQLite constraint.
+///
+/// SQLite only reports this through the error message
+/// (e.g. `UNIQUE constraint failed: tasks.project_id, tasks.name`), so
+/// `table` and `columns` are empty when the message carries no detail, as is
+/// the case for foreign key failures.
+#[derive(Debug)]
+pub struct ConstraintViolation {
+    pub table: Option<String>,
+    pub columns: Vec<String>,
+    pub message: String,
+}
+
+impl ConstraintViolation {
+    fn from_info(info: &dyn DatabaseErrorInformation) -> Self {
+        let message = info.message().to_string();
+        let mut table = info.table_name().map(str::to_string);
+        let mut columns: Vec<String> = info.column_name().map(str::to_string).into_iter().collect();
+
+        if let Some((_, detail)) = message.split_once("constraint failed: ") {
+            for target in detail.split(", ") {
+                match target.split_once('.') {
+                    Some((tbl, col)) => {
+                        table.get_or_insert_with(|| tbl.to_string());
+                        columns.push(col.to_string());
+                    }
+                    None => columns.push(target.to_string()),
+                }
+            }
+        }
+
+        ConstraintViolation {
+            table,
+            columns,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.table, self.columns.is_empty()) {
+            (Some(table), false) => write!(f, "{}({})", table, self.columns.join(", ")),
+            (Some(table), true) => write!(f, "{}", table),
+            (None, false) => write!(f, "{}", self.columns.join(", ")),
+            (None, true) => write!(f, "{}", self.message),
+        }
+    }
+}
+
 impl fmt::Display for DatabaseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             DatabaseError::NotFound(entity) => write!(f, "Entity not found: {}", entity),
             DatabaseError::AlreadyExists(entity) => write!(f, "Entity already exists: {}", entity),
+            DatabaseError::UniqueViolation(c) => write!(f, "Unique constraint violated on {}", c),
+            DatabaseError::ForeignKeyViolation(c) => {
+                write!(f, "Foreign key constraint violated: {}", c)
+            }
+            DatabaseError::NotNullViolation(c) => write!(f, "Missing required value for {}", c),
+            DatabaseError::CheckViolation(c) => write!(f, "Check constraint violated on {}", c),
+            DatabaseError::Busy(msg) => write!(
+                f,
+                "Database is in use by another process, try again: {}",
+                msg
+            ),
             DatabaseError::ConnectionError(msg) => write!(f, "Database connection error: {}", msg),
+            DatabaseError::MigrationError(msg) => write!(f, "Database migration error: {}", msg),
+            DatabaseError::BackupError(msg) => write!(f, "Database backup error: {}", msg),
+            DatabaseError::ImportConflict(paths) => {
+                write!(
+                    f,
+                    "{} items already exist: {}",
+                    paths.len(),
+                    paths.join(", ")
+                )
+            }
             DatabaseError::TransactionError(msg) => {
                 write!(f, "Database transaction error: {}", msg)
             }
@@ -28,8 +108,27 @@ impl From<DieselError> for DatabaseError {
     fn from(error: DieselError) -> Self {
         match error {
             DieselError::NotFound => DatabaseError::NotFound("Entity".to_string()),
-            DieselError::DatabaseError(_, info) => {
-                DatabaseError::Unknown(info.message().to_string())
+            DieselError::DatabaseError(kind, info) => {
+                let info = info.as_ref();
+                match kind {
+                    DatabaseErrorKind::UniqueViolation => {
+                        DatabaseError::UniqueViolation(ConstraintViolation::from_info(info))
+                    }
+                    DatabaseErrorKind::ForeignKeyViolation => {
+                        DatabaseError::ForeignKeyViolation(ConstraintViolation::from_info(info))
+                    }
+                    DatabaseErrorKind::NotNullViolation => {
+                        DatabaseError::NotNullViolation(ConstraintViolation::from_info(info))
+                    }
+                    DatabaseErrorKind::CheckViolation => {
+                        DatabaseError::CheckViolation(ConstraintViolation::from_info(info))
+                    }
+                    // diesel has no dedicated kind for SQLITE_BUSY / SQLITE_LOCKED
+                    _ if is_busy_message(info.message()) => {
+                        DatabaseError::Busy(info.message().to_string())
+                    }
+                    _ => DatabaseError::Unknown(info.message().to_string()),
+                }
             }
             DieselError::RollbackTransaction => {
                 DatabaseError::TransactionError("Transaction rollback".to_string())
@@ -42,4 +141,10 @@ impl From<DieselError> for DatabaseError {
     }
 }
 
+fn is_busy_message(message: &str) -> bool {
+    message.contains("database is locked")
+        || message.contains("database table is locked")
+        || message.contains("database is busy")
+}
+
 impl std::error::Error for DatabaseError {}
@@ -0,0 +1,79 @@
+use rand::Rng;
+
+use super::errors::DatabaseError;
+
+pub const TASK_PREFIX: &str = "t";
+pub const SUBTASK_PREFIX: &str = "s";
+pub const THOUGHT_PREFIX: &str = "th";
+
+const INITIAL_LEN: usize = 4;
+const ATTEMPTS_PER_LEN: usize = 8;
+const MAX_LEN: usize = 12;
+
+/// Generates a random short id such as `t-4f2a` with `len` hex digits.
+pub fn generate(prefix: &str, len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    let digits: String = (0..len)
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect();
+    format!("{}-{}", prefix, digits)
+}
+
+/// Returns true if `reference` looks like a short id with the given prefix,
+/// in either case. Stored ids are lowercase, see [`normalize`].
+pub fn matches(prefix: &str, reference: &str) -> bool {
+    normalize(reference)
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The stored form of a short id typed in any case.
+pub fn normalize(reference: &str) -> String {
+    reference.to_ascii_lowercase()
+}
+
+/// Like [`with_unique`], but tries `wanted` first so an imported row keeps
+/// the short id it had, unless that's malformed or taken.
+pub fn keep_or_unique<T>(
+    prefix: &str,
+    wanted: &str,
+    mut insert: impl FnMut(&str) -> Result<T, DatabaseError>,
+) -> Result<T, DatabaseError> {
+    if matches(prefix, wanted) {
+        match insert(&normalize(wanted)) {
+            Err(DatabaseError::UniqueViolation(c))
+                if c.columns.iter().any(|col| col == "short_id") => {}
+            result => return result,
+        }
+    }
+
+    with_unique(prefix, insert)
+}
+
+/// Runs `insert` with freshly generated short ids until one doesn't collide
+/// with an existing row, growing the id once the short space gets crowded.
+pub fn with_unique<T>(
+    prefix: &str,
+    mut insert: impl FnMut(&str) -> Result<T, DatabaseError>,
+) -> Result<T, DatabaseError> {
+    let mut len = INITIAL_LEN;
+    while len <= MAX_LEN {
+        for _ in 0..ATTEMPTS_PER_LEN {
+            match insert(&generate(prefix, len)) {
+                Err(DatabaseError::UniqueViolation(c))
+                    if c.columns.iter().any(|col| col == "short_id") =>
+                {
+                    continue
+                }
+                result => return result,
+            }
+        }
+        len += 2;
+    }
+
+    Err(DatabaseError::Unknown(format!(
+        "Could not allocate a unique '{}-' short id",
+        prefix
+    )))
+}
@@ -0,0 +1,558 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::{Deserialize, Serialize};
+
+use super::errors::DatabaseError;
+use super::models::*;
+use super::operations::{
+    record_event, restore_project, restore_subtask, restore_task, trash_project, trash_subtask,
+    trash_task,
+};
+
+/// A single reversible change. Every mutating `Database` call journals the
+/// operations that replay it and the ones that revert it, so `doit undo`
+/// and `doit redo` work across separate invocations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    InsertProject(Project),
+    InsertTask(Task),
+    InsertSubTask(SubTask),
+    InsertThought(Thoughts),
+    DeleteProject {
+        id: i32,
+    },
+    DeleteTask {
+        id: i32,
+    },
+    DeleteSubTask {
+        id: i32,
+    },
+    DeleteThought {
+        id: i32,
+    },
+    TrashProject {
+        id: i32,
+    },
+    TrashTask {
+        id: i32,
+    },
+    TrashSubTask {
+        id: i32,
+    },
+    RestoreProject {
+        id: i32,
+    },
+    RestoreTask {
+        id: i32,
+    },
+    RestoreSubTask {
+        id: i32,
+    },
+    RenameProject {
+        id: i32,
+        name: String,
+    },
+    RenameTask {
+        id: i32,
+        name: String,
+    },
+    RenameSubTask {
+        id: i32,
+        name: String,
+    },
+    SetProjectArchived {
+        id: i32,
+        archived_on: Option<NaiveDateTime>,
+    },
+    SetTaskStatus {
+        id: i32,
+        completed: bool,
+        completed_on: Option<NaiveDateTime>,
+    },
+    SetSubTaskStatus {
+        id: i32,
+        completed: bool,
+        completed_on: Option<NaiveDateTime>,
+    },
+    SetTaskDetails {
+        id: i32,
+        priority: Option<String>,
+        due_on: Option<NaiveDate>,
+        tags: String,
+        metadata: String,
+    },
+    SetThoughtNote {
+        id: i32,
+        note: String,
+    },
+    SetThought {
+        id: i32,
+        heading: String,
+        note: String,
+        tags: String,
+    },
+}
+
+/// Stores a journal entry for a command that just ran. Anything previously
+/// undone can no longer be redone once a new change is made.
+pub fn record(
+    conn: &mut SqliteConnection,
+    description: String,
+    undo: &[Operation],
+    redo: &[Operation],
+) -> Result<(), DatabaseError> {
+    use crate::db::schema::journal::dsl::{journal, undone};
+
+    diesel::delete(journal)
+        .filter(undone.eq(true))
+        .execute(conn)
+        .map_err(DatabaseError::from)?;
+
+    let entry = NewJournalEntry {
+        description,
+        undo_ops: encode(undo)?,
+        redo_ops: encode(redo)?,
+    };
+
+    diesel::insert_into(journal)
+        .values(&entry)
+        .execute(conn)
+        .map(|_| ())
+        .map_err(DatabaseError::from)
+}
+
+/// Rows removed for good, which undo and redo can no longer bring back.
+#[derive(Debug, Default)]
+pub struct Removed {
+    pub projects: HashSet<i32>,
+    pub tasks: HashSet<i32>,
+    pub subtasks: HashSet<i32>,
+}
+
+impl Removed {
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty() && self.tasks.is_empty() && self.subtasks.is_empty()
+    }
+}
+
+impl Operation {
+    /// Whether the operation acts on one of the removed rows, or inserts a
+    /// row under one.
+    fn touches(&self, removed: &Removed) -> bool {
+        match self {
+            Operation::InsertProject(project) => removed.projects.contains(&project.id),
+            Operation::InsertTask(task) => {
+                removed.tasks.contains(&task.id) || removed.projects.contains(&task.project_id)
+            }
+            Operation::InsertSubTask(subtask) => {
+                removed.subtasks.contains(&subtask.id) || removed.tasks.contains(&subtask.task_id)
+            }
+            Operation::DeleteProject { id }
+            | Operation::TrashProject { id }
+            | Operation::RestoreProject { id }
+            | Operation::RenameProject { id, .. }
+            | Operation::SetProjectArchived { id, .. } => removed.projects.contains(id),
+            Operation::DeleteTask { id }
+            | Operation::TrashTask { id }
+            | Operation::RestoreTask { id }
+            | Operation::RenameTask { id, .. }
+            | Operation::SetTaskStatus { id, .. }
+            | Operation::SetTaskDetails { id, .. } => removed.tasks.contains(id),
+            Operation::DeleteSubTask { id }
+            | Operation::TrashSubTask { id }
+            | Operation::RestoreSubTask { id }
+            | Operation::RenameSubTask { id, .. }
+            | Operation::SetSubTaskStatus { id, .. } => removed.subtasks.contains(id),
+            Operation::InsertThought(_)
+            | Operation::DeleteThought { .. }
+            | Operation::SetThoughtNote { .. }
+            | Operation::SetThought { .. } => false,
+        }
+    }
+}
+
+/// Drops the entries that act on removed rows, which would otherwise fail
+/// or, once SQLite hands the ids out again, act on whatever took them.
+/// Every other change can still be undone and redone.
+pub fn forget(conn: &mut SqliteConnection, removed: &Removed) -> Result<(), DatabaseError> {
+    use crate::db::schema::journal::dsl::{id, journal};
+
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    let entries: Vec<JournalEntry> = journal
+        .select(JournalEntry::as_select())
+        .load(conn)
+        .map_err(DatabaseError::from)?;
+    let mut stale = Vec::new();
+    for entry in entries {
+        let ops = decode(&entry.undo_ops)?
+            .into_iter()
+            .chain(decode(&entry.redo_ops)?);
+        if ops.into_iter().any(|op| op.touches(removed)) {
+            stale.push(entry.id);
+        }
+    }
+
+    diesel::delete(journal.filter(id.eq_any(&stale)))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(DatabaseError::from)
+}
+
+pub fn encode(ops: &[Operation]) -> Result<String, DatabaseError> {
+    serde_json::to_string(ops)
+        .map_err(|e| DatabaseError::Unknown(format!("Failed to encode journal entry: {}", e)))
+}
+
+pub fn decode(ops: &str) -> Result<Vec<Operation>, DatabaseError> {
+    serde_json::from_str(ops)
+        .map_err(|e| DatabaseError::Unknown(format!("Failed to decode journal entry: {}", e)))
+}
+
+/// Applies an operation and records the matching audit event.
+pub fn apply(conn: &mut SqliteConnection, op: &Operation) -> Result<(), DatabaseError> {
+    use crate::db::schema::{projects, subtasks, tasks, thoughts};
+
+    let now = Utc::now().naive_utc();
+
+    match op {
+        Operation::InsertProject(project) => {
+            diesel::insert_into(projects::table)
+                .values(project)
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::create(EntityType::Project, project.id, &project.name, None),
+            )
+        }
+        Operation::InsertTask(task) => {
+            diesel::insert_into(tasks::table)
+                .values(task)
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::create(EntityType::Task, task.id, &task.name, Some(task.id)),
+            )
+        }
+        Operation::InsertSubTask(subtask) => {
+            diesel::insert_into(subtasks::table)
+                .values(subtask)
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::create(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    Some(subtask.task_id),
+                ),
+            )
+        }
+        Operation::InsertThought(thought) => {
+            diesel::insert_into(thoughts::table)
+                .values(thought)
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::create(EntityType::Thought, thought.id, &thought.heading, None),
+            )
+        }
+        // Children are normally gone already, their creation having been
+        // undone first; anything left goes with the parent via ON DELETE
+        // CASCADE.
+        Operation::DeleteProject { id } => {
+            let project = find_project(conn, *id)?;
+            diesel::delete(projects::table.find(id))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::delete(EntityType::Project, project.id, &project.name, None),
+            )
+        }
+        Operation::DeleteTask { id } => {
+            let task = find_task(conn, *id)?;
+            diesel::delete(tasks::table.find(id))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::delete(EntityType::Task, task.id, &task.name, Some(task.id)),
+            )
+        }
+        Operation::DeleteSubTask { id } => {
+            let subtask = find_subtask(conn, *id)?;
+            diesel::delete(subtasks::table.find(id))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::delete(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    Some(subtask.task_id),
+                ),
+            )
+        }
+        Operation::DeleteThought { id } => {
+            let thought = find_thought(conn, *id)?;
+            diesel::delete(thoughts::table.find(id))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::delete(EntityType::Thought, thought.id, &thought.heading, None),
+            )
+        }
+        Operation::TrashProject { id } => {
+            let project = find_project(conn, *id)?;
+            trash_project(conn, &project, now).map(|_| ())
+        }
+        Operation::TrashTask { id } => {
+            let task = find_task(conn, *id)?;
+            trash_task(conn, &task, now).map(|_| ())
+        }
+        Operation::TrashSubTask { id } => {
+            let subtask = find_subtask(conn, *id)?;
+            trash_subtask(conn, &subtask, now)
+        }
+        Operation::RestoreProject { id } => {
+            let project = find_project(conn, *id)?;
+            restore_project(conn, &project)
+        }
+        Operation::RestoreTask { id } => {
+            let task = find_task(conn, *id)?;
+            restore_task(conn, &task)
+        }
+        Operation::RestoreSubTask { id } => {
+            let subtask = find_subtask(conn, *id)?;
+            restore_subtask(conn, &subtask)
+        }
+        Operation::RenameProject { id, name } => {
+            let project = find_project(conn, *id)?;
+            diesel::update(projects::table.find(id))
+                .set((projects::name.eq(name), projects::updated_on.eq(now)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::rename(EntityType::Project, project.id, &project.name, name, None),
+            )
+        }
+        Operation::RenameTask { id, name } => {
+            let task = find_task(conn, *id)?;
+            diesel::update(tasks::table.find(id))
+                .set((tasks::name.eq(name), tasks::updated_on.eq(now)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::rename(EntityType::Task, task.id, &task.name, name, Some(task.id)),
+            )
+        }
+        Operation::RenameSubTask { id, name } => {
+            let subtask = find_subtask(conn, *id)?;
+            diesel::update(subtasks::table.find(id))
+                .set((subtasks::name.eq(name), subtasks::updated_on.eq(now)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::rename(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    name,
+                    Some(subtask.task_id),
+                ),
+            )
+        }
+        Operation::SetProjectArchived { id, archived_on } => {
+            let project = find_project(conn, *id)?;
+            diesel::update(projects::table.find(id))
+                .set((
+                    projects::archived_on.eq(archived_on),
+                    projects::updated_on.eq(now),
+                ))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::archive(project.id, &project.name, archived_on.is_some()),
+            )
+        }
+        Operation::SetTaskStatus {
+            id,
+            completed,
+            completed_on,
+        } => {
+            let task = find_task(conn, *id)?;
+            diesel::update(tasks::table.find(id))
+                .set((
+                    tasks::completed.eq(completed),
+                    tasks::completed_on.eq(completed_on),
+                    tasks::updated_on.eq(now),
+                ))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::status(
+                    EntityType::Task,
+                    task.id,
+                    &task.name,
+                    task.completed,
+                    *completed,
+                    Some(task.id),
+                ),
+            )
+        }
+        Operation::SetSubTaskStatus {
+            id,
+            completed,
+            completed_on,
+        } => {
+            let subtask = find_subtask(conn, *id)?;
+            diesel::update(subtasks::table.find(id))
+                .set((
+                    subtasks::completed.eq(completed),
+                    subtasks::completed_on.eq(completed_on),
+                    subtasks::updated_on.eq(now),
+                ))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::status(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    subtask.completed,
+                    *completed,
+                    Some(subtask.task_id),
+                ),
+            )
+        }
+        Operation::SetTaskDetails {
+            id,
+            priority,
+            due_on,
+            tags,
+            metadata,
+        } => {
+            let task = find_task(conn, *id)?;
+            diesel::update(tasks::table.find(id))
+                .set((
+                    tasks::priority.eq(priority),
+                    tasks::due_on.eq(due_on),
+                    tasks::tags.eq(tags),
+                    tasks::metadata.eq(metadata),
+                    tasks::updated_on.eq(now),
+                ))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::edit(EntityType::Task, task.id, &task.name, Some(task.id)),
+            )
+        }
+        Operation::SetThoughtNote { id, note } => {
+            let thought = find_thought(conn, *id)?;
+            diesel::update(thoughts::table.find(id))
+                .set((thoughts::note.eq(note), thoughts::updated_on.eq(now)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::edit(EntityType::Thought, thought.id, &thought.heading, None),
+            )
+        }
+        Operation::SetThought {
+            id,
+            heading,
+            note,
+            tags,
+        } => {
+            diesel::update(thoughts::table.find(id))
+                .set((
+                    thoughts::heading.eq(heading),
+                    thoughts::note.eq(note),
+                    thoughts::tags.eq(tags),
+                    thoughts::updated_on.eq(now),
+                ))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            record_event(
+                conn,
+                NewEvent::edit(EntityType::Thought, *id, heading, None),
+            )
+        }
+    }
+}
+
+fn find_project(conn: &mut SqliteConnection, id: i32) -> Result<Project, DatabaseError> {
+    use crate::db::schema::projects;
+
+    projects::table
+        .find(id)
+        .select(Project::as_select())
+        .first(conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => {
+                DatabaseError::NotFound(format!("Project with id {} not found", id))
+            }
+            e => DatabaseError::from(e),
+        })
+}
+
+fn find_task(conn: &mut SqliteConnection, id: i32) -> Result<Task, DatabaseError> {
+    use crate::db::schema::tasks;
+
+    tasks::table
+        .find(id)
+        .select(Task::as_select())
+        .first(conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => {
+                DatabaseError::NotFound(format!("Task with id {} not found", id))
+            }
+            e => DatabaseError::from(e),
+        })
+}
+
+fn find_subtask(conn: &mut SqliteConnection, id: i32) -> Result<SubTask, DatabaseError> {
+    use crate::db::schema::subtasks;
+
+    subtasks::table
+        .find(id)
+        .select(SubTask::as_select())
+        .first(conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => DatabaseError::NotFound(format!("Subtask {} not found", id)),
+            e => DatabaseError::from(e),
+        })
+}
+
+fn find_thought(conn: &mut SqliteConnection, id: i32) -> Result<Thoughts, DatabaseError> {
+    use crate::db::schema::thoughts;
+
+    thoughts::table
+        .find(id)
+        .select(Thoughts::as_select())
+        .first(conn)
+        .map_err(|e| match e {
+            DieselError::NotFound => DatabaseError::NotFound(format!("Thought {} not found", id)),
+            e => DatabaseError::from(e),
+        })
+}
@@ -1,4 +1,7 @@
+pub mod backup;
 pub mod errors;
+pub mod ids;
+pub mod journal;
 pub mod models;
 pub mod operations;
 pub mod schema;
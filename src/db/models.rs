@@ -1,14 +1,29 @@
+use std::collections::BTreeMap;
+
 use crate::db::schema::*;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Queryable, QueryableByName, Identifiable, Selectable)]
+#[derive(
+    Debug,
+    Clone,
+    Serialize,
+    Deserialize,
+    Queryable,
+    QueryableByName,
+    Identifiable,
+    Selectable,
+    Insertable,
+)]
 #[diesel(table_name = projects)]
 pub struct Project {
     pub id: i32,
     pub name: String,
     pub created_on: NaiveDateTime,
+    pub updated_on: Option<NaiveDateTime>,
+    pub deleted_on: Option<NaiveDateTime>,
+    pub archived_on: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -17,7 +32,7 @@ pub struct NewProject {
     pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Selectable, Insertable)]
 #[diesel(belongs_to(Project))]
 #[diesel(table_name = tasks)]
 pub struct Task {
@@ -26,6 +41,53 @@ pub struct Task {
     pub name: String,
     pub completed: bool,
     pub created_on: NaiveDateTime,
+    pub short_id: String,
+    pub updated_on: Option<NaiveDateTime>,
+    pub completed_on: Option<NaiveDateTime>,
+    pub deleted_on: Option<NaiveDateTime>,
+    /// `A` (highest) to `Z`, as in todo.txt.
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub due_on: Option<NaiveDate>,
+    /// Space separated.
+    #[serde(default)]
+    pub tags: String,
+    /// A JSON object of string values, see [`Task::metadata`].
+    #[serde(default = "empty_metadata")]
+    pub metadata: String,
+}
+
+/// The [`Task::metadata`] key holding the id another tool knows a task by,
+/// e.g. a Taskwarrior UUID. Syncing imports match on it before the name.
+pub const EXTERNAL_ID_KEY: &str = "uuid";
+
+/// The [`Task::metadata`] key holding the fingerprint of the code comment
+/// `doit scan` made the task from.
+pub const SCAN_KEY: &str = "scan";
+
+/// The [`Task::metadata`] key holding the comment's `path:line` as of the
+/// last scan.
+pub const SOURCE_KEY: &str = "source";
+
+/// The [`Task::metadata`] key holding the hashes of the commits that
+/// mention a task, comma separated, oldest first.
+pub const COMMITS_KEY: &str = "commits";
+
+impl Task {
+    /// Key/value pairs other tools attach to a task that doit has no
+    /// column for, kept so they survive a round trip.
+    pub fn metadata(&self) -> BTreeMap<String, String> {
+        serde_json::from_str(&self.metadata).unwrap_or_default()
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.split_whitespace()
+    }
+}
+
+fn empty_metadata() -> String {
+    "{}".to_string()
 }
 
 #[derive(Insertable)]
@@ -36,7 +98,7 @@ pub struct NewTask {
     pub completed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Selectable, Insertable)]
 #[diesel(belongs_to(Task))]
 #[diesel(table_name = subtasks)]
 pub struct SubTask {
@@ -45,6 +107,10 @@ pub struct SubTask {
     pub name: String,
     pub completed: bool,
     pub created_on: NaiveDateTime,
+    pub short_id: String,
+    pub updated_on: Option<NaiveDateTime>,
+    pub completed_on: Option<NaiveDateTime>,
+    pub deleted_on: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -55,13 +121,24 @@ pub struct NewSubTask {
     pub completed: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Identifiable, Selectable, Insertable)]
 #[diesel(table_name = thoughts)]
 pub struct Thoughts {
     pub id: i32,
     pub heading: String,
     pub note: String,
     pub created_on: NaiveDateTime,
+    pub short_id: String,
+    pub updated_on: Option<NaiveDateTime>,
+    /// Space separated, like [`Task::tags`].
+    #[serde(default)]
+    pub tags: String,
+}
+
+impl Thoughts {
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.split_whitespace()
+    }
 }
 
 #[derive(Insertable)]
@@ -69,4 +146,411 @@ pub struct Thoughts {
 pub struct NewThought {
     pub heading: String,
     pub note: String,
+    pub tags: String,
+}
+
+/// One of the changes [`Database::change_thoughts`] makes together.
+///
+/// [`Database::change_thoughts`]: crate::db::operations::Database::change_thoughts
+pub enum ThoughtChange {
+    Add(NewThought),
+    Edit {
+        id: i32,
+        heading: String,
+        note: String,
+        tags: String,
+    },
+    Delete {
+        id: i32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable)]
+#[diesel(table_name = events)]
+pub struct Event {
+    pub id: i32,
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub entity_name: String,
+    pub task_id: Option<i32>,
+    pub action: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub created_on: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityType {
+    Project,
+    Task,
+    SubTask,
+    Thought,
+}
+
+impl EntityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityType::Project => "project",
+            EntityType::Task => "task",
+            EntityType::SubTask => "subtask",
+            EntityType::Thought => "thought",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventAction {
+    Create,
+    Rename,
+    Status,
+    Delete,
+    Restore,
+    Purge,
+    Archive,
+    Unarchive,
+    Edit,
+}
+
+impl EventAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventAction::Create => "create",
+            EventAction::Rename => "rename",
+            EventAction::Status => "status",
+            EventAction::Delete => "delete",
+            EventAction::Restore => "restore",
+            EventAction::Purge => "purge",
+            EventAction::Archive => "archive",
+            EventAction::Unarchive => "unarchive",
+            EventAction::Edit => "edit",
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = events)]
+pub struct NewEvent {
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub entity_name: String,
+    pub task_id: Option<i32>,
+    pub action: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+impl NewEvent {
+    fn new(
+        entity: EntityType,
+        entity_id: i32,
+        entity_name: &str,
+        task_id: Option<i32>,
+        action: EventAction,
+    ) -> Self {
+        NewEvent {
+            entity_type: entity.as_str().to_string(),
+            entity_id,
+            entity_name: entity_name.to_string(),
+            task_id,
+            action: action.as_str().to_string(),
+            old_value: None,
+            new_value: None,
+        }
+    }
+
+    pub fn create(entity: EntityType, entity_id: i32, name: &str, task_id: Option<i32>) -> Self {
+        NewEvent {
+            new_value: Some(name.to_string()),
+            ..Self::new(entity, entity_id, name, task_id, EventAction::Create)
+        }
+    }
+
+    pub fn rename(
+        entity: EntityType,
+        entity_id: i32,
+        old_name: &str,
+        new_name: &str,
+        task_id: Option<i32>,
+    ) -> Self {
+        NewEvent {
+            old_value: Some(old_name.to_string()),
+            new_value: Some(new_name.to_string()),
+            ..Self::new(entity, entity_id, new_name, task_id, EventAction::Rename)
+        }
+    }
+
+    pub fn status(
+        entity: EntityType,
+        entity_id: i32,
+        name: &str,
+        was_completed: bool,
+        completed: bool,
+        task_id: Option<i32>,
+    ) -> Self {
+        let status = |completed: bool| if completed { "done" } else { "open" }.to_string();
+        NewEvent {
+            old_value: Some(status(was_completed)),
+            new_value: Some(status(completed)),
+            ..Self::new(entity, entity_id, name, task_id, EventAction::Status)
+        }
+    }
+
+    pub fn delete(entity: EntityType, entity_id: i32, name: &str, task_id: Option<i32>) -> Self {
+        NewEvent {
+            old_value: Some(name.to_string()),
+            ..Self::new(entity, entity_id, name, task_id, EventAction::Delete)
+        }
+    }
+
+    pub fn restore(entity: EntityType, entity_id: i32, name: &str, task_id: Option<i32>) -> Self {
+        NewEvent {
+            new_value: Some(name.to_string()),
+            ..Self::new(entity, entity_id, name, task_id, EventAction::Restore)
+        }
+    }
+
+    pub fn archive(project_id: i32, name: &str, archived: bool) -> Self {
+        let action = if archived {
+            EventAction::Archive
+        } else {
+            EventAction::Unarchive
+        };
+        Self::new(EntityType::Project, project_id, name, None, action)
+    }
+
+    /// Priority, due date, tags or metadata changed.
+    pub fn edit(entity: EntityType, entity_id: i32, name: &str, task_id: Option<i32>) -> Self {
+        Self::new(entity, entity_id, name, task_id, EventAction::Edit)
+    }
+
+    pub fn purge(entity: EntityType, entity_id: i32, name: &str, task_id: Option<i32>) -> Self {
+        NewEvent {
+            old_value: Some(name.to_string()),
+            ..Self::new(entity, entity_id, name, task_id, EventAction::Purge)
+        }
+    }
+}
+
+/// Something sitting in the trash, together with the parents needed to
+/// show where it came from.
+#[derive(Debug)]
+pub enum TrashedItem {
+    Project(Project),
+    Task(Task, Project),
+    SubTask(SubTask, Task, Project),
+}
+
+impl TrashedItem {
+    pub fn deleted_on(&self) -> Option<NaiveDateTime> {
+        match self {
+            TrashedItem::Project(project) => project.deleted_on,
+            TrashedItem::Task(task, _) => task.deleted_on,
+            TrashedItem::SubTask(subtask, _, _) => subtask.deleted_on,
+        }
+    }
+
+    pub fn kind(&self) -> EntityType {
+        match self {
+            TrashedItem::Project(_) => EntityType::Project,
+            TrashedItem::Task(..) => EntityType::Task,
+            TrashedItem::SubTask(..) => EntityType::SubTask,
+        }
+    }
+
+    pub fn short_id(&self) -> Option<&str> {
+        match self {
+            TrashedItem::Project(_) => None,
+            TrashedItem::Task(task, _) => Some(&task.short_id),
+            TrashedItem::SubTask(subtask, _, _) => Some(&subtask.short_id),
+        }
+    }
+
+    /// `project`, `project/task` or `project/task/subtask`.
+    pub fn path(&self) -> String {
+        match self {
+            TrashedItem::Project(project) => project.name.clone(),
+            TrashedItem::Task(task, project) => format!("{}/{}", project.name, task.name),
+            TrashedItem::SubTask(subtask, task, project) => {
+                format!("{}/{}/{}", project.name, task.name, subtask.name)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Selectable)]
+#[diesel(table_name = journal)]
+pub struct JournalEntry {
+    pub id: i32,
+    pub description: String,
+    pub undo_ops: String,
+    pub redo_ops: String,
+    pub undone: bool,
+    pub created_on: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = journal)]
+pub struct NewJournalEntry {
+    pub description: String,
+    pub undo_ops: String,
+    pub redo_ops: String,
+}
+
+/// Result of `doit db check`.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Problems reported by `PRAGMA integrity_check`; empty when it says ok.
+    pub integrity: Vec<String>,
+    /// Rows reported by `PRAGMA foreign_key_check`.
+    pub foreign_keys: Vec<String>,
+    /// Tasks whose project doesn't exist.
+    pub orphaned_tasks: Vec<Task>,
+    /// Subtasks whose task doesn't exist or is itself orphaned.
+    pub orphaned_subtasks: Vec<SubTask>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.integrity.is_empty()
+            && self.foreign_keys.is_empty()
+            && self.orphaned_tasks.is_empty()
+            && self.orphaned_subtasks.is_empty()
+    }
+}
+
+/// A task or subtask whose parent is gone, reduced to the columns every
+/// version of the schema has so it can be found before migrating.
+#[derive(Debug, QueryableByName)]
+pub struct Orphan {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub id: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub name: String,
+    /// The missing project of a task, or the missing or orphaned task of a
+    /// subtask.
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub parent_id: i32,
+}
+
+/// What `doit db check --repair` removed.
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub tasks: Vec<Orphan>,
+    pub subtasks: Vec<Orphan>,
+}
+
+impl RepairReport {
+    pub fn removed(&self) -> usize {
+        self.tasks.len() + self.subtasks.len()
+    }
+}
+
+/// Row counts shown by `doit db stats`.
+#[derive(Debug, Default)]
+pub struct DbStats {
+    pub projects: i64,
+    pub archived_projects: i64,
+    pub trashed_projects: i64,
+    pub tasks: i64,
+    pub completed_tasks: i64,
+    pub trashed_tasks: i64,
+    pub subtasks: i64,
+    pub trashed_subtasks: i64,
+    pub thoughts: i64,
+    pub events: i64,
+    pub journal_entries: i64,
+}
+
+/// The contents of the database as `doit export` writes them and
+/// `doit import` reads them back: live projects, archived ones included,
+/// their live tasks and subtasks, and all thoughts. Parents are referenced
+/// by the ids within the dump; importing assigns new ones.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Dump {
+    pub projects: Vec<Project>,
+    pub tasks: Vec<Task>,
+    pub subtasks: Vec<SubTask>,
+    pub thoughts: Vec<Thoughts>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    /// Add to what's there. Projects with the same name are merged, tasks,
+    /// subtasks and thoughts with the same name are conflicts.
+    Merge,
+    /// Like `Merge`, but tasks and subtasks that exist take their
+    /// completion from the import instead of being conflicts, and thoughts
+    /// their note.
+    Update,
+    /// Like `Update`, but existing tasks also take their priority, due
+    /// date, tags and metadata from the import, and are matched by their
+    /// [`EXTERNAL_ID_KEY`] first, taking its name too. Subtasks are matched
+    /// by their short id within their task first, likewise. For formats
+    /// that carry all that.
+    Sync,
+    /// Remove everything first, the trash included.
+    Replace,
+}
+
+/// What an import added.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub projects: usize,
+    pub tasks: usize,
+    pub subtasks: usize,
+    pub thoughts: usize,
+    /// Existing tasks and subtasks that changed.
+    pub updated: usize,
+    /// Paths of items left out because one with the same name exists.
+    pub conflicts: Vec<String>,
+}
+
+/// A `TODO`, `FIXME` or `HACK` comment found by `doit scan`.
+pub struct ScannedComment {
+    /// Stays the same while the comment only moves up or down its file.
+    pub fingerprint: String,
+    /// The name a task made from it gets, unless that's taken.
+    pub name: String,
+    /// `path:line`.
+    pub source: String,
+}
+
+/// What a scan changed, by task name.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub added: Vec<String>,
+    /// Comments that came back after their task was closed.
+    pub reopened: Vec<String>,
+    /// Comments that are gone.
+    pub closed: Vec<String>,
+    /// Tasks whose comment moved to another line.
+    pub moved: usize,
+}
+
+/// One of the changes [`Database::apply_commits`] makes together.
+///
+/// [`Database::apply_commits`]: crate::db::operations::Database::apply_commits
+pub enum CommitChange {
+    /// Marks the task done and attaches the commit to it.
+    CloseTask {
+        id: i32,
+        commit: String,
+    },
+    CloseSubTask {
+        id: i32,
+    },
+    /// Only attaches the commit to the task.
+    Reference {
+        id: i32,
+        commit: String,
+    },
+}
+
+/// What [`CommitChange`]s changed, by task or subtask name.
+#[derive(Debug, Default)]
+pub struct CommitReport {
+    pub closed: Vec<String>,
+    /// Tasks that had a commit attached.
+    pub referenced: Vec<String>,
 }
@@ -1,53 +1,154 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::connection::{AnsiTransactionManager, SimpleConnection, TransactionManager};
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
+use super::backup;
 use super::errors::DatabaseError;
+use super::ids;
+use super::journal::{self, Operation};
 use super::models::*;
 
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// How long SQLite itself waits on a lock before giving up with SQLITE_BUSY.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Retries after SQLite has given up, doubling the pause each time.
+const BUSY_RETRIES: u32 = 4;
+const BUSY_BACKOFF: Duration = Duration::from_millis(100);
+
 pub struct Database {
     conn: SqliteConnection,
+    in_dry_run: bool,
+}
+
+/// An open dry run, see [`Database::begin_dry_run`].
+pub struct DryRun {
+    last_entry: i32,
 }
 
 impl Database {
     pub fn new(database_url: &str) -> Result<Self, DatabaseError> {
-        SqliteConnection::establish(database_url)
-            .map(|conn| Database { conn })
-            .map_err(|e| DatabaseError::ConnectionError(e.to_string()))
+        Self::open(database_url, true)
+    }
+
+    /// Opens the database but leaves any pending migrations alone, for
+    /// `doit db check --repair`: orphans can keep them from running, and
+    /// [`Database::remove_orphans`] runs them once they're gone.
+    pub fn without_migrating(database_url: &str) -> Result<Self, DatabaseError> {
+        Self::open(database_url, false)
+    }
+
+    fn open(database_url: &str, migrate: bool) -> Result<Self, DatabaseError> {
+        let existed = Path::new(database_url).is_file();
+        let mut conn = SqliteConnection::establish(database_url)
+            .map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
+
+        // Deletes rely on ON DELETE CASCADE. The bundled SQLite is built with
+        // SQLITE_DEFAULT_FOREIGN_KEYS=1, but a build against the system
+        // library gets SQLite's default of off, so it's set here either way.
+        diesel::sql_query("PRAGMA foreign_keys = ON")
+            .execute(&mut conn)
+            .map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
+
+        // WAL lets readers carry on while another process writes, and the
+        // busy timeout makes writers queue up instead of failing outright.
+        // The timeout goes first since switching to WAL needs the lock.
+        // In-memory databases silently stay in "memory" mode.
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;",
+            BUSY_TIMEOUT.as_millis()
+        ))
+        .map_err(DatabaseError::from)?;
+
+        // Snapshot before touching the schema, so a migration that goes wrong
+        // can be rolled back with `doit restore`.
+        let pending = conn
+            .has_pending_migration(MIGRATIONS)
+            .map_err(|e| DatabaseError::MigrationError(e.to_string()))?;
+        if existed {
+            backup::daily_snapshot(database_url, backup::snapshot_keep(), pending)?;
+        }
+
+        // Uniqueness of names is enforced by the indexes these create, so
+        // they have to be in place before anything is written. They run as
+        // one transaction, so an upgrade that fails leaves the schema as it
+        // was rather than half way.
+        if migrate {
+            conn.immediate_transaction(run_migrations)?;
+        }
+
+        Ok(Database {
+            conn,
+            in_dry_run: false,
+        })
+    }
+
+    /// Runs `f` in a `BEGIN IMMEDIATE` transaction, so the write lock is
+    /// taken up front rather than on the first write, where SQLite can't
+    /// wait for it. If another process still holds the lock after the busy
+    /// timeout, the whole transaction is retried with backoff before
+    /// [`DatabaseError::Busy`] is returned.
+    ///
+    /// Inside a dry run the outer transaction already holds the lock, so
+    /// this is a plain savepoint.
+    fn transaction<T, F>(&mut self, mut f: F) -> Result<T, DatabaseError>
+    where
+        F: FnMut(&mut SqliteConnection) -> Result<T, DatabaseError>,
+    {
+        if self.in_dry_run {
+            return self.conn.transaction(f);
+        }
+
+        let mut pause = BUSY_BACKOFF;
+        for _ in 0..BUSY_RETRIES {
+            match self.conn.immediate_transaction(&mut f) {
+                Err(DatabaseError::Busy(_)) => {
+                    thread::sleep(pause);
+                    pause *= 2;
+                }
+                result => return result,
+            }
+        }
+
+        self.conn.immediate_transaction(f)
     }
 
-    pub fn create_project(&mut self, new_project: NewProject) -> Result<i32, DatabaseError> {
+    pub fn create_project(&mut self, new_project: NewProject) -> Result<Project, DatabaseError> {
         use crate::db::schema::projects::dsl::*;
 
-        self.conn
-            .transaction(|conn| {
-                if let Ok(_) = projects
-                    .filter(name.eq(&new_project.name))
-                    .select(Project::as_select())
-                    .first::<Project>(conn)
-                {
-                    return Err(DatabaseError::AlreadyExists(format!(
+        self.transaction(|conn| {
+            let project = diesel::insert_into(projects)
+                .values(&new_project)
+                .returning(Project::as_returning())
+                .get_result(conn)
+                .map_err(|e| match DatabaseError::from(e) {
+                    DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
                         "Project with name '{}' already exists",
                         new_project.name
-                    )));
-                }
-
-                diesel::insert_into(projects)
-                    .values(&new_project)
-                    .execute(conn)
-                    .map_err(DatabaseError::from)?;
+                    )),
+                    e => e,
+                })?;
 
-                projects
-                    .select(id)
-                    .order(id.desc())
-                    .first(conn)
-                    .map_err(DatabaseError::from)
-            })
-            .map_err(|e: DatabaseError| match e {
-                DatabaseError::Unknown(_) => {
-                    DatabaseError::TransactionError("Failed to create project".to_string())
-                }
-                e => e,
-            })
+            record_event(
+                conn,
+                NewEvent::create(EntityType::Project, project.id, &project.name, None),
+            )?;
+            journal::record(
+                conn,
+                format!("add project '{}'", project.name),
+                &[Operation::DeleteProject { id: project.id }],
+                &[Operation::InsertProject(project.clone())],
+            )?;
+
+            Ok(project)
+        })
     }
 
     pub fn get_project_by_id(&mut self, project_id: i32) -> Result<Project, DatabaseError> {
@@ -55,6 +156,7 @@ impl Database {
 
         projects
             .filter(id.eq(project_id))
+            .filter(deleted_on.is_null())
             .select(Project::as_select())
             .first(&mut self.conn)
             .map_err(|e| match e {
@@ -70,6 +172,7 @@ impl Database {
 
         projects
             .filter(name.eq(project_name.clone()))
+            .filter(deleted_on.is_null())
             .select(Project::as_select())
             .first(&mut self.conn)
             .map_err(|e| match e {
@@ -87,9 +190,10 @@ impl Database {
     ) -> Result<i32, DatabaseError> {
         use crate::db::schema::projects::dsl::*;
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let project = projects
                 .filter(name.eq(&project_name))
+                .filter(deleted_on.is_null())
                 .select(Project::as_select())
                 .first(conn)
                 .map_err(|e| match e {
@@ -99,35 +203,59 @@ impl Database {
                     e => DatabaseError::from(e),
                 })?;
 
-            if let Ok(_) = projects
-                .filter(name.eq(&updated_project_name))
-                .select(Project::as_select())
-                .first::<Project>(conn)
-            {
-                return Err(DatabaseError::AlreadyExists(format!(
-                    "Project with name '{}' already exists",
-                    updated_project_name
-                )));
-            }
-
             diesel::update(projects)
                 .filter(id.eq(project.id))
-                .set(name.eq(updated_project_name))
+                .set((
+                    name.eq(&updated_project_name),
+                    updated_on.eq(Utc::now().naive_utc()),
+                ))
                 .execute(conn)
-                .map_err(DatabaseError::from)?;
+                .map_err(|e| match DatabaseError::from(e) {
+                    DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                        "Project with name '{}' already exists",
+                        updated_project_name
+                    )),
+                    e => e,
+                })?;
+
+            record_event(
+                conn,
+                NewEvent::rename(
+                    EntityType::Project,
+                    project.id,
+                    &project.name,
+                    &updated_project_name,
+                    None,
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!(
+                    "rename project '{}' to '{}'",
+                    project.name, updated_project_name
+                ),
+                &[Operation::RenameProject {
+                    id: project.id,
+                    name: project.name.clone(),
+                }],
+                &[Operation::RenameProject {
+                    id: project.id,
+                    name: updated_project_name.clone(),
+                }],
+            )?;
 
             Ok(project.id)
         })
     }
 
+    /// Moves a project and everything in it to the trash.
     pub fn delete_project(&mut self, project_name: String) -> Result<i32, DatabaseError> {
-        use crate::db::schema::projects::dsl::{id as project_id, name, projects};
-        use crate::db::schema::subtasks::dsl::{subtasks, task_id as subtask_task_id};
-        use crate::db::schema::tasks::dsl::{id as task_id, project_id as task_project_id, tasks};
+        use crate::db::schema::projects::dsl::{deleted_on, name, projects};
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let project = projects
                 .filter(name.eq(project_name.clone()))
+                .filter(deleted_on.is_null())
                 .select(Project::as_select())
                 .first(conn)
                 .map_err(|e| match e {
@@ -137,96 +265,123 @@ impl Database {
                     e => DatabaseError::from(e),
                 })?;
 
-            let project_tasks = tasks
-                .filter(task_project_id.eq(project.id))
-                .select(task_id)
-                .load::<i32>(conn)
-                .map_err(DatabaseError::from)?;
-
-            diesel::delete(subtasks)
-                .filter(subtask_task_id.eq_any(project_tasks))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
-
-            diesel::delete(tasks)
-                .filter(task_project_id.eq(project.id))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
-
-            diesel::delete(projects)
-                .filter(project_id.eq(project.id))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
+            let (task_count, subtask_count) =
+                trash_project(conn, &project, Utc::now().naive_utc())?;
+
+            journal::record(
+                conn,
+                format!(
+                    "delete project '{}' ({}, {})",
+                    project.name,
+                    plural(task_count, "task"),
+                    plural(subtask_count, "subtask")
+                ),
+                &[Operation::RestoreProject { id: project.id }],
+                &[Operation::TrashProject { id: project.id }],
+            )?;
 
             Ok(project.id)
         })
     }
 
-    pub fn add_new_task(&mut self, new_task: NewTask) -> Result<i32, DatabaseError> {
+    pub fn add_new_task(&mut self, new_task: NewTask) -> Result<Task, DatabaseError> {
         use crate::db::schema::tasks::dsl::*;
         self.get_project_by_id(new_task.project_id)?;
 
-        self.conn.transaction(|conn| {
-            if let Ok(_) = tasks
-                .filter(name.eq(&new_task.name))
-                .filter(project_id.eq(new_task.project_id))
-                .select(Task::as_select())
-                .first::<Task>(conn)
-            {
-                return Err(DatabaseError::AlreadyExists(format!(
+        self.transaction(|conn| {
+            let task = ids::with_unique(ids::TASK_PREFIX, |sid| {
+                diesel::insert_into(tasks)
+                    .values((&new_task, short_id.eq(sid)))
+                    .returning(Task::as_returning())
+                    .get_result(conn)
+                    .map_err(DatabaseError::from)
+            })
+            .map_err(|e| match e {
+                DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
                     "Task with name '{}' already exists",
                     new_task.name
-                )));
-            }
-
-            diesel::insert_into(tasks)
-                .values(new_task)
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
-
-            tasks
-                .select(id)
-                .order(id.desc())
-                .first(conn)
-                .map_err(DatabaseError::from)
+                )),
+                e => e,
+            })?;
+
+            record_event(
+                conn,
+                NewEvent::create(EntityType::Task, task.id, &task.name, Some(task.id)),
+            )?;
+            journal::record(
+                conn,
+                format!("add task '{}'", task.name),
+                &[Operation::DeleteTask { id: task.id }],
+                &[Operation::InsertTask(task.clone())],
+            )?;
+
+            Ok(task)
         })
     }
 
     pub fn update_task(
         &mut self,
+        req_project_id: i32,
         req_task_name: String,
         update_task_name: String,
     ) -> Result<i32, DatabaseError> {
-        use crate::db::schema::tasks::dsl::{id as task_id, name as task_name, tasks};
+        use crate::db::schema::tasks::dsl::{
+            deleted_on as task_deleted_on, id as task_id, name as task_name,
+            project_id as task_project_id, tasks, updated_on as task_updated_on,
+        };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let task = tasks
+                .filter(task_project_id.eq(req_project_id))
                 .filter(task_name.eq(&req_task_name))
+                .filter(task_deleted_on.is_null())
                 .select(Task::as_select())
                 .first(conn)
                 .map_err(|e| match e {
-                    DieselError::NotFound => {
-                        DatabaseError::NotFound(format!("Task '{}' not found", req_task_name))
-                    }
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Task '{}' not found in this project",
+                        req_task_name
+                    )),
                     e => DatabaseError::from(e),
                 })?;
 
-            if let Ok(_) = tasks
-                .filter(task_name.eq(&update_task_name))
-                .select(Task::as_select())
-                .first::<Task>(conn)
-            {
-                return Err(DatabaseError::AlreadyExists(format!(
-                    "Task with name '{}' already exists",
-                    update_task_name
-                )));
-            }
-
             diesel::update(tasks)
                 .filter(task_id.eq(task.id))
-                .set(task_name.eq(update_task_name))
+                .set((
+                    task_name.eq(&update_task_name),
+                    task_updated_on.eq(Utc::now().naive_utc()),
+                ))
                 .execute(conn)
-                .map_err(DatabaseError::from)?;
+                .map_err(|e| match DatabaseError::from(e) {
+                    DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                        "Task with name '{}' already exists",
+                        update_task_name
+                    )),
+                    e => e,
+                })?;
+
+            record_event(
+                conn,
+                NewEvent::rename(
+                    EntityType::Task,
+                    task.id,
+                    &task.name,
+                    &update_task_name,
+                    Some(task.id),
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!("rename task '{}' to '{}'", task.name, update_task_name),
+                &[Operation::RenameTask {
+                    id: task.id,
+                    name: task.name.clone(),
+                }],
+                &[Operation::RenameTask {
+                    id: task.id,
+                    name: update_task_name.clone(),
+                }],
+            )?;
 
             Ok(task.id)
         })
@@ -234,85 +389,125 @@ impl Database {
 
     pub fn update_task_status(
         &mut self,
+        req_project_id: i32,
         req_task_name: String,
         completed: bool,
     ) -> Result<i32, DatabaseError> {
         use crate::db::schema::tasks::dsl::{
-            completed as task_completed, name as task_name, tasks,
+            completed as task_completed, completed_on as task_completed_on,
+            deleted_on as task_deleted_on, id as task_id, name as task_name,
+            project_id as task_project_id, tasks, updated_on as task_updated_on,
         };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let task = tasks
+                .filter(task_project_id.eq(req_project_id))
                 .filter(task_name.eq(&req_task_name))
+                .filter(task_deleted_on.is_null())
                 .select(Task::as_select())
                 .first(conn)
                 .map_err(|e| match e {
-                    DieselError::NotFound => {
-                        DatabaseError::NotFound(format!("Task '{}' not found", req_task_name))
-                    }
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Task '{}' not found in this project",
+                        req_task_name
+                    )),
                     e => DatabaseError::from(e),
                 })?;
 
+            let new_completed_on = completed_on(task.completed, task.completed_on, completed);
+
             diesel::update(tasks)
-                .filter(task_name.eq(task.name))
-                .set(task_completed.eq(completed))
+                .filter(task_id.eq(task.id))
+                .set((
+                    task_completed.eq(completed),
+                    task_completed_on.eq(new_completed_on),
+                    task_updated_on.eq(Utc::now().naive_utc()),
+                ))
                 .execute(conn)
                 .map_err(DatabaseError::from)?;
 
+            record_event(
+                conn,
+                NewEvent::status(
+                    EntityType::Task,
+                    task.id,
+                    &task.name,
+                    task.completed,
+                    completed,
+                    Some(task.id),
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!(
+                    "mark task '{}' as {}",
+                    task.name,
+                    if completed { "done" } else { "not done" }
+                ),
+                &[Operation::SetTaskStatus {
+                    id: task.id,
+                    completed: task.completed,
+                    completed_on: task.completed_on,
+                }],
+                &[Operation::SetTaskStatus {
+                    id: task.id,
+                    completed,
+                    completed_on: new_completed_on,
+                }],
+            )?;
+
             Ok(task.id)
         })
     }
 
-    pub fn delete_task(&mut self, req_task_name: String) -> Result<i32, DatabaseError> {
-        use crate::db::schema::subtasks::dsl::{subtasks, task_id as subtask_task_id};
-        use crate::db::schema::tasks::dsl::{id as task_id, name as task_name, tasks};
+    /// Moves a task and its subtasks to the trash.
+    pub fn delete_task(
+        &mut self,
+        req_project_id: i32,
+        req_task_name: String,
+    ) -> Result<i32, DatabaseError> {
+        use crate::db::schema::tasks::dsl::{
+            deleted_on as task_deleted_on, name as task_name, project_id as task_project_id, tasks,
+        };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let task = tasks
+                .filter(task_project_id.eq(req_project_id))
                 .filter(task_name.eq(&req_task_name))
+                .filter(task_deleted_on.is_null())
                 .select(Task::as_select())
                 .first(conn)
                 .map_err(|e| match e {
-                    DieselError::NotFound => {
-                        DatabaseError::NotFound(format!("Task '{}' not found", req_task_name))
-                    }
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Task '{}' not found in this project",
+                        req_task_name
+                    )),
                     e => DatabaseError::from(e),
                 })?;
 
-            diesel::delete(subtasks)
-                .filter(subtask_task_id.eq(task.id))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
+            let subtask_count = trash_task(conn, &task, Utc::now().naive_utc())?;
 
-            diesel::delete(tasks)
-                .filter(task_id.eq(task.id))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
+            journal::record(
+                conn,
+                format!(
+                    "delete task '{}' ({})",
+                    task.name,
+                    plural(subtask_count, "subtask")
+                ),
+                &[Operation::RestoreTask { id: task.id }],
+                &[Operation::TrashTask { id: task.id }],
+            )?;
 
             Ok(task.id)
         })
     }
 
-    pub fn get_tasks_by_name(&mut self, req_task_name: String) -> Result<Task, DatabaseError> {
-        use crate::db::schema::tasks::dsl::{name as task_name, tasks};
-
-        tasks
-            .filter(task_name.eq(&req_task_name))
-            .select(Task::as_select())
-            .first(&mut self.conn)
-            .map_err(|e| match e {
-                DieselError::NotFound => {
-                    DatabaseError::NotFound(format!("Task '{}' not found", req_task_name))
-                }
-                e => DatabaseError::from(e),
-            })
-    }
-
     pub fn get_tasks_by_id(&mut self, req_task_id: i32) -> Result<Task, DatabaseError> {
-        use crate::db::schema::tasks::dsl::{id as task_id, tasks};
+        use crate::db::schema::tasks::dsl::{deleted_on as task_deleted_on, id as task_id, tasks};
 
         tasks
             .filter(task_id.eq(&req_task_id))
+            .filter(task_deleted_on.is_null())
             .select(Task::as_select())
             .first(&mut self.conn)
             .map_err(|e| match e {
@@ -323,34 +518,42 @@ impl Database {
             })
     }
 
-    pub fn add_new_subtask(&mut self, new_subtask: NewSubTask) -> Result<i32, DatabaseError> {
+    pub fn add_new_subtask(&mut self, new_subtask: NewSubTask) -> Result<SubTask, DatabaseError> {
         use crate::db::schema::subtasks::dsl::*;
 
-        self.conn.transaction(|conn| {
-            // First check if subtask with this name exists under the task
-            if let Ok(_) = subtasks
-                .filter(name.eq(&new_subtask.name))
-                .filter(task_id.eq(new_subtask.task_id))
-                .select(SubTask::as_select())
-                .first::<SubTask>(conn)
-            {
-                return Err(DatabaseError::AlreadyExists(format!(
+        self.transaction(|conn| {
+            let subtask = ids::with_unique(ids::SUBTASK_PREFIX, |sid| {
+                diesel::insert_into(subtasks)
+                    .values((&new_subtask, short_id.eq(sid)))
+                    .returning(SubTask::as_returning())
+                    .get_result(conn)
+                    .map_err(DatabaseError::from)
+            })
+            .map_err(|e| match e {
+                DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
                     "Subtask with name '{}' already exists for this task",
                     new_subtask.name
-                )));
-            }
-
-            // If not, create the new subtask
-            diesel::insert_into(subtasks)
-                .values(new_subtask)
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
-
-            subtasks
-                .select(id)
-                .order(id.desc())
-                .first(conn)
-                .map_err(DatabaseError::from)
+                )),
+                e => e,
+            })?;
+
+            record_event(
+                conn,
+                NewEvent::create(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    Some(subtask.task_id),
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!("add subtask '{}'", subtask.name),
+                &[Operation::DeleteSubTask { id: subtask.id }],
+                &[Operation::InsertSubTask(subtask.clone())],
+            )?;
+
+            Ok(subtask)
         })
     }
 
@@ -361,13 +564,15 @@ impl Database {
         req_subtask_name: String,
     ) -> Result<i32, DatabaseError> {
         use crate::db::schema::subtasks::dsl::{
-            id as subtask_id, name as subtask_name, subtasks, task_id as subtask_task_id,
+            deleted_on as subtask_deleted_on, id as subtask_id, name as subtask_name, subtasks,
+            task_id as subtask_task_id, updated_on as subtask_updated_on,
         };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let subtask = subtasks
                 .filter(subtask_id.eq(&req_subtask_id))
                 .filter(subtask_task_id.eq(&req_task_id))
+                .filter(subtask_deleted_on.is_null())
                 .select(SubTask::as_select())
                 .first(conn)
                 .map_err(|e| match e {
@@ -378,34 +583,60 @@ impl Database {
                     e => DatabaseError::from(e),
                 })?;
 
-            if let Ok(_) = subtasks
-                .filter(subtask_name.eq(&req_subtask_name))
-                .filter(subtask_task_id.eq(&req_task_id))
-                .select(SubTask::as_select())
-                .first::<SubTask>(conn)
-            {
-                return Err(DatabaseError::AlreadyExists(format!(
-                    "Subtask with name '{}' already exists for this task",
-                    req_subtask_name
-                )));
-            }
-
             diesel::update(subtasks)
                 .filter(subtask_task_id.eq(&req_task_id))
                 .filter(subtask_id.eq(&req_subtask_id))
-                .set(subtask_name.eq(req_subtask_name))
+                .set((
+                    subtask_name.eq(&req_subtask_name),
+                    subtask_updated_on.eq(Utc::now().naive_utc()),
+                ))
                 .execute(conn)
-                .map_err(DatabaseError::from)?;
+                .map_err(|e| match DatabaseError::from(e) {
+                    DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                        "Subtask with name '{}' already exists for this task",
+                        req_subtask_name
+                    )),
+                    e => e,
+                })?;
+
+            record_event(
+                conn,
+                NewEvent::rename(
+                    EntityType::SubTask,
+                    subtask.id,
+                    &subtask.name,
+                    &req_subtask_name,
+                    Some(subtask.task_id),
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!(
+                    "rename subtask '{}' to '{}'",
+                    subtask.name, req_subtask_name
+                ),
+                &[Operation::RenameSubTask {
+                    id: subtask.id,
+                    name: subtask.name.clone(),
+                }],
+                &[Operation::RenameSubTask {
+                    id: subtask.id,
+                    name: req_subtask_name.clone(),
+                }],
+            )?;
 
             Ok(subtask.id)
         })
     }
 
     pub fn get_subtask_by_id(&mut self, req_subtask_id: i32) -> Result<SubTask, DatabaseError> {
-        use crate::db::schema::subtasks::dsl::{id as subtask_id, subtasks};
+        use crate::db::schema::subtasks::dsl::{
+            deleted_on as subtask_deleted_on, id as subtask_id, subtasks,
+        };
 
         subtasks
             .filter(subtask_id.eq(&req_subtask_id))
+            .filter(subtask_deleted_on.is_null())
             .select(SubTask::as_select())
             .first(&mut self.conn)
             .map_err(|e| match e {
@@ -416,19 +647,22 @@ impl Database {
             })
     }
 
+    /// Moves a subtask to the trash.
     pub fn delete_subtask(
         &mut self,
         req_subtask_id: i32,
         req_task_id: i32,
     ) -> Result<i32, DatabaseError> {
         use crate::db::schema::subtasks::dsl::{
-            id as subtask_id, subtasks, task_id as task_subtask_id,
+            deleted_on as subtask_deleted_on, id as subtask_id, subtasks,
+            task_id as task_subtask_id,
         };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let subtask = subtasks
                 .filter(task_subtask_id.eq(&req_task_id))
                 .filter(subtask_id.eq(&req_subtask_id))
+                .filter(subtask_deleted_on.is_null())
                 .select(SubTask::as_select())
                 .first(conn)
                 .map_err(|e| match e {
@@ -439,11 +673,14 @@ impl Database {
                     e => DatabaseError::from(e),
                 })?;
 
-            diesel::delete(subtasks)
-                .filter(task_subtask_id.eq(&req_task_id))
-                .filter(subtask_id.eq(&req_subtask_id))
-                .execute(conn)
-                .map_err(DatabaseError::from)?;
+            trash_subtask(conn, &subtask, Utc::now().naive_utc())?;
+
+            journal::record(
+                conn,
+                format!("delete subtask '{}'", subtask.name),
+                &[Operation::RestoreSubTask { id: subtask.id }],
+                &[Operation::TrashSubTask { id: subtask.id }],
+            )?;
 
             Ok(subtask.id)
         })
@@ -456,13 +693,16 @@ impl Database {
         req_task_id: i32,
     ) -> Result<i32, DatabaseError> {
         use crate::db::schema::subtasks::dsl::{
-            completed as subtask_completed, id as subtask_id, subtasks, task_id as subtask_task_id,
+            completed as subtask_completed, completed_on as subtask_completed_on,
+            deleted_on as subtask_deleted_on, id as subtask_id, subtasks,
+            task_id as subtask_task_id, updated_on as subtask_updated_on,
         };
 
-        self.conn.transaction(|conn| {
+        self.transaction(|conn| {
             let subtask_result = subtasks
                 .filter(subtask_task_id.eq(&req_task_id))
                 .filter(subtask_id.eq(&req_subtask_id))
+                .filter(subtask_deleted_on.is_null())
                 .select(SubTask::as_select())
                 .first(conn)
                 .map_err(|e| match e {
@@ -473,13 +713,53 @@ impl Database {
                     e => DatabaseError::from(e),
                 })?;
 
+            let new_completed_on = completed_on(
+                subtask_result.completed,
+                subtask_result.completed_on,
+                completed,
+            );
+
             diesel::update(subtasks)
                 .filter(subtask_task_id.eq(&req_task_id))
                 .filter(subtask_id.eq(&req_subtask_id))
-                .set(subtask_completed.eq(&completed))
+                .set((
+                    subtask_completed.eq(&completed),
+                    subtask_completed_on.eq(new_completed_on),
+                    subtask_updated_on.eq(Utc::now().naive_utc()),
+                ))
                 .execute(conn)
                 .map_err(DatabaseError::from)?;
 
+            record_event(
+                conn,
+                NewEvent::status(
+                    EntityType::SubTask,
+                    subtask_result.id,
+                    &subtask_result.name,
+                    subtask_result.completed,
+                    completed,
+                    Some(subtask_result.task_id),
+                ),
+            )?;
+            journal::record(
+                conn,
+                format!(
+                    "mark subtask '{}' as {}",
+                    subtask_result.name,
+                    if completed { "done" } else { "not done" }
+                ),
+                &[Operation::SetSubTaskStatus {
+                    id: subtask_result.id,
+                    completed: subtask_result.completed,
+                    completed_on: subtask_result.completed_on,
+                }],
+                &[Operation::SetSubTaskStatus {
+                    id: subtask_result.id,
+                    completed,
+                    completed_on: new_completed_on,
+                }],
+            )?;
+
             Ok(subtask_result.id)
         })
     }
@@ -489,12 +769,15 @@ impl Database {
         task_name: String,
         project_id: i32,
     ) -> Result<Task, DatabaseError> {
-        use crate::db::schema::tasks::dsl::{name, project_id as task_project_id, tasks};
+        use crate::db::schema::tasks::dsl::{
+            deleted_on as task_deleted_on, name, project_id as task_project_id, tasks,
+        };
 
         tasks
             .into_boxed()
             .filter(name.eq(task_name.clone()))
             .filter(task_project_id.eq(project_id))
+            .filter(task_deleted_on.is_null())
             .select(Task::as_select())
             .first(&mut self.conn)
             .map_err(|e| match e {
@@ -506,13 +789,2993 @@ impl Database {
     }
 
     pub fn get_all_tasks(&mut self, req_project_id: i32) -> Result<Vec<Task>, DatabaseError> {
-        use crate::db::schema::tasks::dsl::{project_id as task_project_id, tasks};
+        use crate::db::schema::tasks::dsl::{
+            deleted_on as task_deleted_on, project_id as task_project_id, tasks,
+        };
 
         tasks
             .into_boxed()
             .filter(task_project_id.eq(req_project_id))
+            .filter(task_deleted_on.is_null())
+            .select(Task::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Returns live projects by name. Archived ones are left out unless
+    /// `include_archived` is set.
+    pub fn get_all_projects(
+        &mut self,
+        include_archived: bool,
+    ) -> Result<Vec<Project>, DatabaseError> {
+        use crate::db::schema::projects::dsl::{archived_on, deleted_on, name, projects};
+
+        let mut query = projects.filter(deleted_on.is_null()).into_boxed();
+        if !include_archived {
+            query = query.filter(archived_on.is_null());
+        }
+
+        query
+            .order(name.asc())
+            .select(Project::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Archives or unarchives a project, returning it as it was before.
+    pub fn set_project_archived(
+        &mut self,
+        project_name: String,
+        archived: bool,
+    ) -> Result<Project, DatabaseError> {
+        use crate::db::schema::projects::dsl::{
+            archived_on, deleted_on, id, name, projects, updated_on,
+        };
+
+        self.transaction(|conn| {
+            let project = projects
+                .filter(name.eq(&project_name))
+                .filter(deleted_on.is_null())
+                .select(Project::as_select())
+                .first(conn)
+                .map_err(|e| match e {
+                    DieselError::NotFound => {
+                        DatabaseError::NotFound(format!("Project '{}' not found", project_name))
+                    }
+                    e => DatabaseError::from(e),
+                })?;
+
+            let now = Utc::now().naive_utc();
+            let new_archived_on = archived.then_some(now);
+
+            diesel::update(projects)
+                .filter(id.eq(project.id))
+                .set((archived_on.eq(new_archived_on), updated_on.eq(now)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+
+            record_event(conn, NewEvent::archive(project.id, &project.name, archived))?;
+            journal::record(
+                conn,
+                format!(
+                    "{} project '{}'",
+                    if archived { "archive" } else { "unarchive" },
+                    project.name
+                ),
+                &[Operation::SetProjectArchived {
+                    id: project.id,
+                    archived_on: project.archived_on,
+                }],
+                &[Operation::SetProjectArchived {
+                    id: project.id,
+                    archived_on: new_archived_on,
+                }],
+            )?;
+
+            Ok(project)
+        })
+    }
+
+    pub fn get_all_subtasks(&mut self, req_task_id: i32) -> Result<Vec<SubTask>, DatabaseError> {
+        use crate::db::schema::subtasks::dsl::{
+            deleted_on as subtask_deleted_on, subtasks, task_id as subtask_task_id,
+        };
+
+        subtasks
+            .filter(subtask_task_id.eq(req_task_id))
+            .filter(subtask_deleted_on.is_null())
+            .select(SubTask::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    pub fn get_task_by_short_id(&mut self, req_short_id: String) -> Result<Task, DatabaseError> {
+        use crate::db::schema::tasks::dsl::{deleted_on, short_id, tasks};
+
+        tasks
+            .filter(short_id.eq(&req_short_id))
+            .filter(deleted_on.is_null())
             .select(Task::as_select())
+            .first(&mut self.conn)
+            .map_err(|e| match e {
+                DieselError::NotFound => {
+                    DatabaseError::NotFound(format!("Task '{}' not found", req_short_id))
+                }
+                e => DatabaseError::from(e),
+            })
+    }
+
+    /// Returns every task called `req_task_name` across all projects, along
+    /// with the project each one belongs to.
+    pub fn find_tasks_by_name(
+        &mut self,
+        req_task_name: String,
+    ) -> Result<Vec<(Task, Project)>, DatabaseError> {
+        use crate::db::schema::projects;
+        use crate::db::schema::tasks::dsl::{
+            deleted_on as task_deleted_on, name as task_name, tasks,
+        };
+
+        tasks
+            .inner_join(projects::table)
+            .filter(task_name.eq(&req_task_name))
+            .filter(task_deleted_on.is_null())
+            .order(projects::name.asc())
+            .select((Task::as_select(), Project::as_select()))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    pub fn get_subtask_by_short_id(
+        &mut self,
+        req_short_id: String,
+    ) -> Result<SubTask, DatabaseError> {
+        use crate::db::schema::subtasks::dsl::{deleted_on, short_id, subtasks};
+
+        subtasks
+            .filter(short_id.eq(&req_short_id))
+            .filter(deleted_on.is_null())
+            .select(SubTask::as_select())
+            .first(&mut self.conn)
+            .map_err(|e| match e {
+                DieselError::NotFound => {
+                    DatabaseError::NotFound(format!("Subtask '{}' not found", req_short_id))
+                }
+                e => DatabaseError::from(e),
+            })
+    }
+
+    pub fn get_subtask_by_name_and_task_id(
+        &mut self,
+        req_subtask_name: String,
+        req_task_id: i32,
+    ) -> Result<SubTask, DatabaseError> {
+        use crate::db::schema::subtasks::dsl::{
+            deleted_on, name, subtasks, task_id as subtask_task_id,
+        };
+
+        subtasks
+            .filter(name.eq(&req_subtask_name))
+            .filter(subtask_task_id.eq(req_task_id))
+            .filter(deleted_on.is_null())
+            .select(SubTask::as_select())
+            .first(&mut self.conn)
+            .map_err(|e| match e {
+                DieselError::NotFound => DatabaseError::NotFound(format!(
+                    "Subtask '{}' not found in this task",
+                    req_subtask_name
+                )),
+                e => DatabaseError::from(e),
+            })
+    }
+
+    /// Returns every subtask called `req_subtask_name` along with its task
+    /// and project.
+    pub fn find_subtasks_by_name(
+        &mut self,
+        req_subtask_name: String,
+    ) -> Result<Vec<(SubTask, Task, Project)>, DatabaseError> {
+        use crate::db::schema::subtasks::dsl::{
+            deleted_on as subtask_deleted_on, name as subtask_name, subtasks,
+        };
+        use crate::db::schema::{projects, tasks};
+
+        subtasks
+            .inner_join(tasks::table.inner_join(projects::table))
+            .filter(subtask_name.eq(&req_subtask_name))
+            .filter(subtask_deleted_on.is_null())
+            .order((projects::name.asc(), tasks::name.asc()))
+            .select((
+                SubTask::as_select(),
+                Task::as_select(),
+                Project::as_select(),
+            ))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Returns every task together with its project, for building
+    /// suggestions when a reference doesn't match anything.
+    pub fn get_all_tasks_with_projects(&mut self) -> Result<Vec<(Task, Project)>, DatabaseError> {
+        use crate::db::schema::{projects, tasks};
+
+        tasks::table
+            .inner_join(projects::table)
+            .filter(tasks::deleted_on.is_null())
+            .select((Task::as_select(), Project::as_select()))
             .load(&mut self.conn)
             .map_err(DatabaseError::from)
     }
+
+    /// Returns every subtask together with its task and project.
+    pub fn get_all_subtasks_with_parents(
+        &mut self,
+    ) -> Result<Vec<(SubTask, Task, Project)>, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks};
+
+        subtasks::table
+            .inner_join(tasks::table.inner_join(projects::table))
+            .filter(subtasks::deleted_on.is_null())
+            .select((
+                SubTask::as_select(),
+                Task::as_select(),
+                Project::as_select(),
+            ))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Returns every thought, oldest first.
+    pub fn get_all_thoughts(&mut self) -> Result<Vec<Thoughts>, DatabaseError> {
+        use crate::db::schema::thoughts::dsl::{id, thoughts};
+
+        thoughts
+            .order(id.asc())
+            .select(Thoughts::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Returns audit events newest first, optionally bounded by time and
+    /// restricted to a single task (including its subtasks).
+    pub fn get_events(
+        &mut self,
+        req_task_id: Option<i32>,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Event>, DatabaseError> {
+        use crate::db::schema::events::dsl::{created_on, events, id, task_id};
+
+        let mut query = events.into_boxed();
+        if let Some(req_task_id) = req_task_id {
+            query = query.filter(task_id.eq(req_task_id));
+        }
+        if let Some(since) = since {
+            query = query.filter(created_on.ge(since));
+        }
+        if let Some(until) = until {
+            query = query.filter(created_on.lt(until));
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+
+        query
+            .order(id.desc())
+            .select(Event::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Every task in the trash with its project, including those that went
+    /// with their project, which [`Database::get_trash`] leaves out.
+    pub fn get_trashed_tasks(&mut self) -> Result<Vec<(Task, Project)>, DatabaseError> {
+        use crate::db::schema::{projects, tasks};
+
+        tasks::table
+            .inner_join(projects::table)
+            .filter(tasks::deleted_on.is_not_null())
+            .order(tasks::deleted_on.desc())
+            .select((Task::as_select(), Project::as_select()))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+
+    /// Returns what's in the trash, most recently deleted first. Children
+    /// trashed along with their parent are left out, since restoring the
+    /// parent brings them back.
+    pub fn get_trash(&mut self) -> Result<Vec<TrashedItem>, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks};
+
+        let mut items: Vec<TrashedItem> = projects::table
+            .filter(projects::deleted_on.is_not_null())
+            .select(Project::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)?
+            .into_iter()
+            .map(TrashedItem::Project)
+            .collect();
+
+        let trashed_tasks: Vec<(Task, Project)> = tasks::table
+            .inner_join(projects::table)
+            .filter(tasks::deleted_on.is_not_null())
+            .select((Task::as_select(), Project::as_select()))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+        items.extend(
+            trashed_tasks
+                .into_iter()
+                .filter(|(task, project)| task.deleted_on != project.deleted_on)
+                .map(|(task, project)| TrashedItem::Task(task, project)),
+        );
+
+        let trashed_subtasks: Vec<(SubTask, Task, Project)> = subtasks::table
+            .inner_join(tasks::table.inner_join(projects::table))
+            .filter(subtasks::deleted_on.is_not_null())
+            .select((
+                SubTask::as_select(),
+                Task::as_select(),
+                Project::as_select(),
+            ))
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+        items.extend(
+            trashed_subtasks
+                .into_iter()
+                .filter(|(subtask, task, _)| subtask.deleted_on != task.deleted_on)
+                .map(|(subtask, task, project)| TrashedItem::SubTask(subtask, task, project)),
+        );
+
+        items.sort_by_key(|item| std::cmp::Reverse(item.deleted_on()));
+        Ok(items)
+    }
+
+    /// Takes a project out of the trash along with the tasks and subtasks
+    /// that were deleted with it.
+    pub fn restore_project(&mut self, req_project_id: i32) -> Result<Project, DatabaseError> {
+        use crate::db::schema::projects::dsl::{deleted_on, id, projects};
+
+        self.transaction(|conn| {
+            let project = projects
+                .filter(id.eq(req_project_id))
+                .filter(deleted_on.is_not_null())
+                .select(Project::as_select())
+                .first(conn)
+                .map_err(|e| match e {
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Project with id {} is not in the trash",
+                        req_project_id
+                    )),
+                    e => DatabaseError::from(e),
+                })?;
+
+            restore_project(conn, &project)?;
+            journal::record(
+                conn,
+                format!("restore project '{}'", project.name),
+                &[Operation::TrashProject { id: project.id }],
+                &[Operation::RestoreProject { id: project.id }],
+            )?;
+
+            Ok(project)
+        })
+    }
+
+    /// Takes a task out of the trash along with the subtasks that were
+    /// deleted with it. Its project has to be live.
+    pub fn restore_task(&mut self, req_task_id: i32) -> Result<Task, DatabaseError> {
+        use crate::db::schema::tasks::dsl::{deleted_on, id, tasks};
+
+        self.transaction(|conn| {
+            let task = tasks
+                .filter(id.eq(req_task_id))
+                .filter(deleted_on.is_not_null())
+                .select(Task::as_select())
+                .first(conn)
+                .map_err(|e| match e {
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Task with id {} is not in the trash",
+                        req_task_id
+                    )),
+                    e => DatabaseError::from(e),
+                })?;
+
+            restore_task(conn, &task)?;
+            journal::record(
+                conn,
+                format!("restore task '{}'", task.name),
+                &[Operation::TrashTask { id: task.id }],
+                &[Operation::RestoreTask { id: task.id }],
+            )?;
+
+            Ok(task)
+        })
+    }
+
+    /// Takes a subtask out of the trash. Its task has to be live.
+    pub fn restore_subtask(&mut self, req_subtask_id: i32) -> Result<SubTask, DatabaseError> {
+        use crate::db::schema::subtasks::dsl::{deleted_on, id, subtasks};
+
+        self.transaction(|conn| {
+            let subtask = subtasks
+                .filter(id.eq(req_subtask_id))
+                .filter(deleted_on.is_not_null())
+                .select(SubTask::as_select())
+                .first(conn)
+                .map_err(|e| match e {
+                    DieselError::NotFound => DatabaseError::NotFound(format!(
+                        "Subtask {} is not in the trash",
+                        req_subtask_id
+                    )),
+                    e => DatabaseError::from(e),
+                })?;
+
+            restore_subtask(conn, &subtask)?;
+            journal::record(
+                conn,
+                format!("restore subtask '{}'", subtask.name),
+                &[Operation::TrashSubTask { id: subtask.id }],
+                &[Operation::RestoreSubTask { id: subtask.id }],
+            )?;
+
+            Ok(subtask)
+        })
+    }
+
+    /// Permanently removes everything trashed before `before` (or all of
+    /// the trash), returning how many rows went. This can't be undone, and
+    /// the journal entries acting on purged rows go with them, see
+    /// [`journal::forget`].
+    pub fn empty_trash(&mut self, before: Option<NaiveDateTime>) -> Result<usize, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks};
+
+        self.transaction(|conn| {
+            // Everything in the trash was deleted at or before now.
+            let cutoff = before.unwrap_or_else(|| Utc::now().naive_utc());
+
+            let purged_projects: Vec<Project> = projects::table
+                .filter(projects::deleted_on.le(cutoff))
+                .select(Project::as_select())
+                .load(conn)
+                .map_err(DatabaseError::from)?;
+            let project_ids: Vec<i32> = purged_projects.iter().map(|p| p.id).collect();
+
+            let purged_tasks: Vec<Task> = tasks::table
+                .filter(
+                    tasks::deleted_on
+                        .le(cutoff)
+                        .or(tasks::project_id.eq_any(&project_ids)),
+                )
+                .select(Task::as_select())
+                .load(conn)
+                .map_err(DatabaseError::from)?;
+            let task_ids: Vec<i32> = purged_tasks.iter().map(|t| t.id).collect();
+
+            let purged_subtasks: Vec<SubTask> = subtasks::table
+                .filter(
+                    subtasks::deleted_on
+                        .le(cutoff)
+                        .or(subtasks::task_id.eq_any(&task_ids)),
+                )
+                .select(SubTask::as_select())
+                .load(conn)
+                .map_err(DatabaseError::from)?;
+            // children of purged parents go with them through ON DELETE CASCADE
+            diesel::delete(projects::table.filter(projects::deleted_on.le(cutoff)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            diesel::delete(tasks::table.filter(tasks::deleted_on.le(cutoff)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            diesel::delete(subtasks::table.filter(subtasks::deleted_on.le(cutoff)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+
+            for subtask in &purged_subtasks {
+                record_event(
+                    conn,
+                    NewEvent::purge(
+                        EntityType::SubTask,
+                        subtask.id,
+                        &subtask.name,
+                        Some(subtask.task_id),
+                    ),
+                )?;
+            }
+            for task in &purged_tasks {
+                record_event(
+                    conn,
+                    NewEvent::purge(EntityType::Task, task.id, &task.name, Some(task.id)),
+                )?;
+            }
+            for project in &purged_projects {
+                record_event(
+                    conn,
+                    NewEvent::purge(EntityType::Project, project.id, &project.name, None),
+                )?;
+            }
+
+            journal::forget(
+                conn,
+                &journal::Removed {
+                    projects: project_ids.iter().copied().collect(),
+                    tasks: task_ids.iter().copied().collect(),
+                    subtasks: purged_subtasks.iter().map(|s| s.id).collect(),
+                },
+            )?;
+
+            let purged = purged_projects.len() + purged_tasks.len() + purged_subtasks.len();
+
+            Ok(purged)
+        })
+    }
+
+    /// Runs SQLite's own integrity and foreign key checks and looks for
+    /// tasks and subtasks whose parent is gone.
+    pub fn check(&mut self) -> Result<CheckReport, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks};
+
+        let integrity: Vec<String> = diesel::sql_query("PRAGMA integrity_check")
+            .load::<IntegrityCheckRow>(&mut self.conn)
+            .map_err(DatabaseError::from)?
+            .into_iter()
+            .map(|row| row.integrity_check)
+            .filter(|message| message != "ok")
+            .collect();
+
+        let foreign_keys = diesel::sql_query("PRAGMA foreign_key_check")
+            .load::<ForeignKeyCheckRow>(&mut self.conn)
+            .map_err(DatabaseError::from)?
+            .into_iter()
+            .map(|row| match row.rowid {
+                Some(rowid) => format!(
+                    "{} row {} points at a missing {}",
+                    row.table, rowid, row.parent
+                ),
+                None => format!("{} points at a missing {}", row.table, row.parent),
+            })
+            .collect();
+
+        let orphaned_tasks: Vec<Task> = tasks::table
+            .filter(diesel::dsl::not(
+                tasks::project_id.eq_any(projects::table.select(projects::id)),
+            ))
+            .select(Task::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+        let orphaned_task_ids: Vec<i32> = orphaned_tasks.iter().map(|t| t.id).collect();
+
+        let orphaned_subtasks: Vec<SubTask> = subtasks::table
+            .filter(
+                diesel::dsl::not(subtasks::task_id.eq_any(tasks::table.select(tasks::id)))
+                    .or(subtasks::task_id.eq_any(&orphaned_task_ids)),
+            )
+            .select(SubTask::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+
+        Ok(CheckReport {
+            integrity,
+            foreign_keys,
+            orphaned_tasks,
+            orphaned_subtasks,
+        })
+    }
+
+    /// Permanently deletes tasks whose project is gone and subtasks whose
+    /// task is gone or orphaned, then runs any pending migrations. The
+    /// orphans are looked up by columns every schema version has, so this
+    /// works on a database opened with [`Database::without_migrating`]
+    /// whose migrations they would otherwise get in the way of. Like
+    /// emptying the trash this forgets the journal entries acting on them
+    /// or on their missing parents.
+    pub fn remove_orphans(&mut self) -> Result<RepairReport, DatabaseError> {
+        use crate::db::schema::{subtasks, tasks};
+
+        self.transaction(|conn| {
+            let report = RepairReport {
+                tasks: diesel::sql_query(
+                    "SELECT id, name, project_id AS parent_id FROM tasks \
+                     WHERE project_id NOT IN (SELECT id FROM projects)",
+                )
+                .load::<Orphan>(conn)
+                .map_err(DatabaseError::from)?,
+                subtasks: diesel::sql_query(
+                    "SELECT id, name, task_id AS parent_id FROM subtasks \
+                     WHERE task_id NOT IN (SELECT t.id FROM tasks t \
+                     JOIN projects p ON p.id = t.project_id)",
+                )
+                .load::<Orphan>(conn)
+                .map_err(DatabaseError::from)?,
+            };
+            let task_ids: Vec<i32> = report.tasks.iter().map(|t| t.id).collect();
+            let subtask_ids: Vec<i32> = report.subtasks.iter().map(|s| s.id).collect();
+
+            diesel::delete(subtasks::table.filter(subtasks::id.eq_any(&subtask_ids)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+            diesel::delete(tasks::table.filter(tasks::id.eq_any(&task_ids)))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+
+            // the events and journal tables may only exist from here on
+            run_migrations(conn)?;
+
+            for subtask in &report.subtasks {
+                record_event(
+                    conn,
+                    NewEvent::purge(
+                        EntityType::SubTask,
+                        subtask.id,
+                        &subtask.name,
+                        Some(subtask.parent_id),
+                    ),
+                )?;
+            }
+            for task in &report.tasks {
+                record_event(
+                    conn,
+                    NewEvent::purge(EntityType::Task, task.id, &task.name, Some(task.id)),
+                )?;
+            }
+
+            // the parents that are already gone can't be brought back either
+            journal::forget(
+                conn,
+                &journal::Removed {
+                    projects: report.tasks.iter().map(|t| t.parent_id).collect(),
+                    tasks: task_ids
+                        .iter()
+                        .copied()
+                        .chain(report.subtasks.iter().map(|s| s.parent_id))
+                        .collect(),
+                    subtasks: subtask_ids.iter().copied().collect(),
+                },
+            )?;
+
+            Ok(report)
+        })
+    }
+
+    /// Rebuilds the database file, reclaiming space left by deleted rows.
+    pub fn vacuum(&mut self) -> Result<(), DatabaseError> {
+        // In WAL mode the rewritten pages land in the -wal file; the
+        // checkpoint moves them back and truncates it so the space is
+        // actually returned.
+        self.conn
+            .batch_execute("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(DatabaseError::from)
+    }
+
+    pub fn get_stats(&mut self) -> Result<DbStats, DatabaseError> {
+        use crate::db::schema::{events, journal, projects, subtasks, tasks, thoughts};
+
+        let conn = &mut self.conn;
+        let count = |result: QueryResult<i64>| result.map_err(DatabaseError::from);
+
+        Ok(DbStats {
+            projects: count(
+                projects::table
+                    .filter(projects::deleted_on.is_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            archived_projects: count(
+                projects::table
+                    .filter(projects::deleted_on.is_null())
+                    .filter(projects::archived_on.is_not_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            trashed_projects: count(
+                projects::table
+                    .filter(projects::deleted_on.is_not_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            tasks: count(
+                tasks::table
+                    .filter(tasks::deleted_on.is_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            completed_tasks: count(
+                tasks::table
+                    .filter(tasks::deleted_on.is_null())
+                    .filter(tasks::completed.eq(true))
+                    .count()
+                    .get_result(conn),
+            )?,
+            trashed_tasks: count(
+                tasks::table
+                    .filter(tasks::deleted_on.is_not_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            subtasks: count(
+                subtasks::table
+                    .filter(subtasks::deleted_on.is_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            trashed_subtasks: count(
+                subtasks::table
+                    .filter(subtasks::deleted_on.is_not_null())
+                    .count()
+                    .get_result(conn),
+            )?,
+            thoughts: count(thoughts::table.count().get_result(conn))?,
+            events: count(events::table.count().get_result(conn))?,
+            journal_entries: count(journal::table.count().get_result(conn))?,
+        })
+    }
+
+    /// Adds, edits and deletes thoughts as one change, undone together, and
+    /// returns the thoughts added in the order they were given.
+    pub fn change_thoughts(
+        &mut self,
+        description: String,
+        changes: Vec<ThoughtChange>,
+    ) -> Result<Vec<Thoughts>, DatabaseError> {
+        use crate::db::schema::thoughts;
+
+        self.transaction(|conn| {
+            let mut added = Vec::new();
+            let mut undo = Vec::new();
+            let mut redo = Vec::new();
+
+            for change in &changes {
+                match change {
+                    ThoughtChange::Add(new_thought) => {
+                        let thought = ids::with_unique(ids::THOUGHT_PREFIX, |sid| {
+                            diesel::insert_into(thoughts::table)
+                                .values((new_thought, thoughts::short_id.eq(sid)))
+                                .returning(Thoughts::as_returning())
+                                .get_result(conn)
+                                .map_err(DatabaseError::from)
+                        })?;
+                        record_event(
+                            conn,
+                            NewEvent::create(
+                                EntityType::Thought,
+                                thought.id,
+                                &thought.heading,
+                                None,
+                            ),
+                        )?;
+                        undo.push(Operation::DeleteThought { id: thought.id });
+                        redo.push(Operation::InsertThought(thought.clone()));
+                        added.push(thought);
+                    }
+                    ThoughtChange::Edit {
+                        id,
+                        heading,
+                        note,
+                        tags,
+                    } => {
+                        let thought = thoughts::table
+                            .find(id)
+                            .select(Thoughts::as_select())
+                            .first(conn)
+                            .map_err(DatabaseError::from)?;
+                        let redo_op = Operation::SetThought {
+                            id: *id,
+                            heading: heading.clone(),
+                            note: note.clone(),
+                            tags: tags.clone(),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(Operation::SetThought {
+                            id: thought.id,
+                            heading: thought.heading,
+                            note: thought.note,
+                            tags: thought.tags,
+                        });
+                        redo.push(redo_op);
+                    }
+                    ThoughtChange::Delete { id } => {
+                        let thought = thoughts::table
+                            .find(id)
+                            .select(Thoughts::as_select())
+                            .first(conn)
+                            .map_err(DatabaseError::from)?;
+                        let redo_op = Operation::DeleteThought { id: *id };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(Operation::InsertThought(thought));
+                        redo.push(redo_op);
+                    }
+                }
+            }
+
+            if !redo.is_empty() {
+                // an added thought can take the id of one deleted before it,
+                // so they're reverted last to first
+                undo.reverse();
+                journal::record(conn, description.clone(), &undo, &redo)?;
+            }
+            Ok(added)
+        })
+    }
+
+    /// Brings a project's tasks in line with the comments a scan found, as
+    /// one change. A comment without a task gets one, and a task follows its
+    /// comment: it's open while the comment is there, even if it was marked
+    /// done by hand, and done once it's gone. Tasks are matched by
+    /// fingerprint, see [`SCAN_KEY`], so tasks not made by a scan are left
+    /// alone.
+    pub fn scan(
+        &mut self,
+        req_project_id: i32,
+        description: String,
+        comments: Vec<ScannedComment>,
+    ) -> Result<ScanReport, DatabaseError> {
+        use crate::db::schema::tasks;
+
+        self.get_project_by_id(req_project_id)?;
+
+        self.transaction(|conn| {
+            let mut report = ScanReport::default();
+            let mut undo = Vec::new();
+            let mut redo = Vec::new();
+
+            // narrowed down in SQL, the JSON itself is checked here
+            let mut scanned: HashMap<String, Task> = tasks::table
+                .filter(tasks::project_id.eq(req_project_id))
+                .filter(tasks::deleted_on.is_null())
+                .filter(tasks::metadata.like(format!("%\"{}\"%", SCAN_KEY)))
+                .select(Task::as_select())
+                .load(conn)?
+                .into_iter()
+                .filter_map(|task| Some((task.metadata().get(SCAN_KEY)?.clone(), task)))
+                .collect();
+
+            for comment in &comments {
+                let Some(existing) = scanned.remove(&comment.fingerprint) else {
+                    let name = free_task_name(conn, req_project_id, comment)?;
+                    let metadata = serde_json::json!({
+                        SCAN_KEY: comment.fingerprint,
+                        SOURCE_KEY: comment.source,
+                    })
+                    .to_string();
+
+                    let task = ids::with_unique(ids::TASK_PREFIX, |sid| {
+                        diesel::insert_into(tasks::table)
+                            .values((
+                                tasks::project_id.eq(req_project_id),
+                                tasks::name.eq(&name),
+                                tasks::completed.eq(false),
+                                tasks::short_id.eq(sid),
+                                tasks::metadata.eq(&metadata),
+                            ))
+                            .returning(Task::as_returning())
+                            .get_result(conn)
+                            .map_err(DatabaseError::from)
+                    })?;
+                    record_event(
+                        conn,
+                        NewEvent::create(EntityType::Task, task.id, &task.name, Some(task.id)),
+                    )?;
+                    undo.push(Operation::DeleteTask { id: task.id });
+                    redo.push(Operation::InsertTask(task));
+                    report.added.push(name);
+                    continue;
+                };
+
+                let mut metadata = existing.metadata();
+                if metadata.get(SOURCE_KEY) != Some(&comment.source) {
+                    metadata.insert(SOURCE_KEY.to_string(), comment.source.clone());
+                    let metadata = serde_json::to_string(&metadata).map_err(|e| {
+                        DatabaseError::Unknown(format!("Failed to encode metadata: {}", e))
+                    })?;
+                    let redo_op = Operation::SetTaskDetails {
+                        id: existing.id,
+                        priority: existing.priority.clone(),
+                        due_on: existing.due_on,
+                        tags: existing.tags.clone(),
+                        metadata,
+                    };
+                    journal::apply(conn, &redo_op)?;
+                    undo.push(Operation::SetTaskDetails {
+                        id: existing.id,
+                        priority: existing.priority.clone(),
+                        due_on: existing.due_on,
+                        tags: existing.tags.clone(),
+                        metadata: existing.metadata.clone(),
+                    });
+                    redo.push(redo_op);
+                    report.moved += 1;
+                }
+                if existing.completed {
+                    set_scanned_status(conn, &existing, false, &mut undo, &mut redo)?;
+                    report.reopened.push(existing.name);
+                }
+            }
+
+            let mut gone: Vec<Task> = scanned.into_values().filter(|t| !t.completed).collect();
+            gone.sort_by_key(|task| task.id);
+            for task in gone {
+                set_scanned_status(conn, &task, true, &mut undo, &mut redo)?;
+                report.closed.push(task.name);
+            }
+
+            if !redo.is_empty() {
+                journal::record(conn, description.clone(), &undo, &redo)?;
+            }
+            Ok(report)
+        })
+    }
+
+    /// Marks tasks and subtasks mentioned by commits done and attaches the
+    /// commits to tasks, see [`COMMITS_KEY`], as one change. Anything
+    /// already done or attached is left as it is, so the same commit can be
+    /// applied twice.
+    pub fn apply_commits(
+        &mut self,
+        description: String,
+        changes: Vec<CommitChange>,
+    ) -> Result<CommitReport, DatabaseError> {
+        use crate::db::schema::{subtasks, tasks};
+
+        self.transaction(|conn| {
+            let mut report = CommitReport::default();
+            let mut undo = Vec::new();
+            let mut redo = Vec::new();
+
+            for change in &changes {
+                let (id, commit, close) = match change {
+                    CommitChange::CloseTask { id, commit } => (*id, commit, true),
+                    CommitChange::Reference { id, commit } => (*id, commit, false),
+                    CommitChange::CloseSubTask { id } => {
+                        let subtask = subtasks::table
+                            .find(id)
+                            .filter(subtasks::deleted_on.is_null())
+                            .select(SubTask::as_select())
+                            .first(conn)
+                            .map_err(|e| match e {
+                                DieselError::NotFound => {
+                                    DatabaseError::NotFound(format!("Subtask {} not found", id))
+                                }
+                                e => DatabaseError::from(e),
+                            })?;
+                        if subtask.completed {
+                            continue;
+                        }
+                        let redo_op = Operation::SetSubTaskStatus {
+                            id: subtask.id,
+                            completed: true,
+                            completed_on: completed_on(false, None, true),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(Operation::SetSubTaskStatus {
+                            id: subtask.id,
+                            completed: false,
+                            completed_on: subtask.completed_on,
+                        });
+                        redo.push(redo_op);
+                        report.closed.push(subtask.name);
+                        continue;
+                    }
+                };
+
+                let task = tasks::table
+                    .find(id)
+                    .filter(tasks::deleted_on.is_null())
+                    .select(Task::as_select())
+                    .first(conn)
+                    .map_err(|e| match e {
+                        DieselError::NotFound => {
+                            DatabaseError::NotFound(format!("Task {} not found", id))
+                        }
+                        e => DatabaseError::from(e),
+                    })?;
+                let mut metadata = task.metadata();
+                let mut commits: Vec<&str> = metadata
+                    .get(COMMITS_KEY)
+                    .map(|value| value.split(',').collect())
+                    .unwrap_or_default();
+                if !commits.contains(&commit.as_str()) {
+                    commits.push(commit);
+                    let value = commits.join(",");
+                    metadata.insert(COMMITS_KEY.to_string(), value);
+                    let metadata = serde_json::to_string(&metadata).map_err(|e| {
+                        DatabaseError::Unknown(format!("Failed to encode metadata: {}", e))
+                    })?;
+                    let redo_op = Operation::SetTaskDetails {
+                        id: task.id,
+                        priority: task.priority.clone(),
+                        due_on: task.due_on,
+                        tags: task.tags.clone(),
+                        metadata,
+                    };
+                    journal::apply(conn, &redo_op)?;
+                    undo.push(Operation::SetTaskDetails {
+                        id: task.id,
+                        priority: task.priority.clone(),
+                        due_on: task.due_on,
+                        tags: task.tags.clone(),
+                        metadata: task.metadata.clone(),
+                    });
+                    redo.push(redo_op);
+                    if !close {
+                        report.referenced.push(task.name.clone());
+                    }
+                }
+                if close && !task.completed {
+                    let redo_op = Operation::SetTaskStatus {
+                        id: task.id,
+                        completed: true,
+                        completed_on: completed_on(false, None, true),
+                    };
+                    journal::apply(conn, &redo_op)?;
+                    undo.push(Operation::SetTaskStatus {
+                        id: task.id,
+                        completed: false,
+                        completed_on: task.completed_on,
+                    });
+                    redo.push(redo_op);
+                    report.closed.push(task.name);
+                }
+            }
+
+            if !redo.is_empty() {
+                // a task changed twice has to go back to how it was first
+                undo.reverse();
+                journal::record(conn, description.clone(), &undo, &redo)?;
+            }
+            Ok(report)
+        })
+    }
+
+    /// Reads everything `doit export` writes out, see [`Dump`]. Trashed
+    /// items are left out; a trashed parent takes its live children with it,
+    /// so each table only needs its own stamp checked.
+    pub fn export(&mut self) -> Result<Dump, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks, thoughts};
+
+        // a read transaction, so the four queries see the same data
+        self.conn.transaction(|conn| {
+            Ok(Dump {
+                projects: projects::table
+                    .filter(projects::deleted_on.is_null())
+                    .order(projects::id.asc())
+                    .select(Project::as_select())
+                    .load(conn)?,
+                tasks: tasks::table
+                    .filter(tasks::deleted_on.is_null())
+                    .order(tasks::id.asc())
+                    .select(Task::as_select())
+                    .load(conn)?,
+                subtasks: subtasks::table
+                    .filter(subtasks::deleted_on.is_null())
+                    .order(subtasks::id.asc())
+                    .select(SubTask::as_select())
+                    .load(conn)?,
+                thoughts: thoughts::table
+                    .order(thoughts::id.asc())
+                    .select(Thoughts::as_select())
+                    .load(conn)?,
+            })
+        })
+    }
+
+    /// Imports a dump in one transaction. Rows get new ids but keep their
+    /// timestamps, and their short ids where those are free. Items whose
+    /// name is taken are reported as conflicts, unless `mode` is
+    /// [`ImportMode::Update`] or [`ImportMode::Sync`], which update them
+    /// instead. Otherwise, with `skip_conflicts` they are left out (a
+    /// task along with its subtasks), otherwise nothing is imported and
+    /// [`DatabaseError::ImportConflict`] lists them.
+    ///
+    /// Both modes are journaled, so `doit undo` takes an import back, a
+    /// replace included.
+    pub fn import(
+        &mut self,
+        dump: &Dump,
+        mode: ImportMode,
+        skip_conflicts: bool,
+    ) -> Result<ImportReport, DatabaseError> {
+        use crate::db::schema::{projects, subtasks, tasks, thoughts};
+
+        self.transaction(|conn| {
+            let mut undo = Vec::new();
+            let mut redo = Vec::new();
+            // what replace removed, put back once the imported rows are gone
+            let mut reinsert = Vec::new();
+
+            if mode == ImportMode::Replace {
+                let old_projects: Vec<Project> = projects::table
+                    .order(projects::id.asc())
+                    .select(Project::as_select())
+                    .load(conn)?;
+                let old_tasks: Vec<Task> = tasks::table
+                    .order(tasks::id.asc())
+                    .select(Task::as_select())
+                    .load(conn)?;
+                let old_subtasks: Vec<SubTask> = subtasks::table
+                    .order(subtasks::id.asc())
+                    .select(SubTask::as_select())
+                    .load(conn)?;
+                let old_thoughts: Vec<Thoughts> = thoughts::table
+                    .order(thoughts::id.asc())
+                    .select(Thoughts::as_select())
+                    .load(conn)?;
+
+                // tasks and subtasks go with their projects
+                diesel::delete(projects::table).execute(conn)?;
+                diesel::delete(thoughts::table).execute(conn)?;
+
+                for project in &old_projects {
+                    record_event(
+                        conn,
+                        NewEvent::purge(EntityType::Project, project.id, &project.name, None),
+                    )?;
+                    redo.push(Operation::DeleteProject { id: project.id });
+                }
+                for thought in &old_thoughts {
+                    record_event(
+                        conn,
+                        NewEvent::purge(EntityType::Thought, thought.id, &thought.heading, None),
+                    )?;
+                    redo.push(Operation::DeleteThought { id: thought.id });
+                }
+
+                reinsert.extend(old_projects.into_iter().map(Operation::InsertProject));
+                reinsert.extend(old_tasks.into_iter().map(Operation::InsertTask));
+                reinsert.extend(old_subtasks.into_iter().map(Operation::InsertSubTask));
+                reinsert.extend(old_thoughts.into_iter().map(Operation::InsertThought));
+            }
+
+            let mut report = ImportReport::default();
+            // dump id -> (database id, created by this import)
+            let mut project_ids: HashMap<i32, (i32, bool)> = HashMap::new();
+            let mut task_ids: HashMap<i32, (i32, bool)> = HashMap::new();
+
+            for project in &dump.projects {
+                let existing = projects::table
+                    .filter(projects::name.eq(&project.name))
+                    .filter(projects::deleted_on.is_null())
+                    .select(projects::id)
+                    .first::<i32>(conn)
+                    .optional()?;
+                if let Some(existing) = existing {
+                    project_ids.insert(project.id, (existing, false));
+                    continue;
+                }
+
+                let created: Project = diesel::insert_into(projects::table)
+                    .values((
+                        projects::name.eq(&project.name),
+                        projects::created_on.eq(project.created_on),
+                        projects::updated_on.eq(project.updated_on),
+                        projects::archived_on.eq(project.archived_on),
+                    ))
+                    .returning(Project::as_returning())
+                    .get_result(conn)?;
+                record_event(
+                    conn,
+                    NewEvent::create(EntityType::Project, created.id, &created.name, None),
+                )?;
+
+                project_ids.insert(project.id, (created.id, true));
+                undo.push(Operation::DeleteProject { id: created.id });
+                redo.push(Operation::InsertProject(created));
+                report.projects += 1;
+            }
+
+            for task in &dump.tasks {
+                let (project_id, new_project) =
+                    *project_ids.get(&task.project_id).ok_or_else(|| {
+                        DatabaseError::NotFound(format!(
+                            "Project {} of imported task '{}' not found",
+                            task.project_id, task.name
+                        ))
+                    })?;
+
+                // another tool's id outlives renames, so it's matched first
+                let synced = match (mode, task.metadata().get(EXTERNAL_ID_KEY)) {
+                    (ImportMode::Sync, Some(external_id)) => {
+                        find_task_by_external_id(conn, external_id)?
+                    }
+                    _ => None,
+                };
+                let existing = match synced {
+                    Some(existing) => Some(existing),
+                    None => tasks::table
+                        .filter(tasks::project_id.eq(project_id))
+                        .filter(tasks::name.eq(&task.name))
+                        .filter(tasks::deleted_on.is_null())
+                        .select(Task::as_select())
+                        .first(conn)
+                        .optional()?,
+                };
+                if let (Some(existing), ImportMode::Update | ImportMode::Sync) = (&existing, mode) {
+                    let details_changed = mode == ImportMode::Sync && !same_details(existing, task);
+                    let status_changed = existing.completed != task.completed;
+                    let renamed = existing.name != task.name;
+                    if details_changed || status_changed || renamed {
+                        report.updated += 1;
+                    }
+
+                    if renamed {
+                        let undo_op = Operation::RenameTask {
+                            id: existing.id,
+                            name: existing.name.clone(),
+                        };
+                        let redo_op = Operation::RenameTask {
+                            id: existing.id,
+                            name: task.name.clone(),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                    }
+
+                    if details_changed {
+                        let undo_op = Operation::SetTaskDetails {
+                            id: existing.id,
+                            priority: existing.priority.clone(),
+                            due_on: existing.due_on,
+                            tags: existing.tags.clone(),
+                            metadata: existing.metadata.clone(),
+                        };
+                        let redo_op = Operation::SetTaskDetails {
+                            id: existing.id,
+                            priority: task.priority.clone(),
+                            due_on: task.due_on,
+                            tags: task.tags.clone(),
+                            metadata: task.metadata.clone(),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                    }
+                    if status_changed {
+                        let undo_op = Operation::SetTaskStatus {
+                            id: existing.id,
+                            completed: existing.completed,
+                            completed_on: existing.completed_on,
+                        };
+                        let redo_op = Operation::SetTaskStatus {
+                            id: existing.id,
+                            completed: task.completed,
+                            completed_on: completed_on(
+                                existing.completed,
+                                existing.completed_on,
+                                task.completed,
+                            ),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                    }
+                    task_ids.insert(task.id, (existing.id, false));
+                    continue;
+                }
+                if existing.is_some() {
+                    let project = projects::table
+                        .find(project_id)
+                        .select(projects::name)
+                        .first::<String>(conn)?;
+                    report.conflicts.push(format!("{}/{}", project, task.name));
+                    continue;
+                }
+
+                let created = ids::keep_or_unique(ids::TASK_PREFIX, &task.short_id, |sid| {
+                    diesel::insert_into(tasks::table)
+                        .values((
+                            tasks::project_id.eq(project_id),
+                            tasks::name.eq(&task.name),
+                            tasks::completed.eq(task.completed),
+                            tasks::created_on.eq(task.created_on),
+                            tasks::short_id.eq(sid),
+                            tasks::updated_on.eq(task.updated_on),
+                            tasks::completed_on.eq(task.completed_on),
+                            tasks::priority.eq(&task.priority),
+                            tasks::due_on.eq(task.due_on),
+                            tasks::tags.eq(&task.tags),
+                            tasks::metadata.eq(&task.metadata),
+                        ))
+                        .returning(Task::as_returning())
+                        .get_result(conn)
+                        .map_err(DatabaseError::from)
+                })?;
+                record_event(
+                    conn,
+                    NewEvent::create(
+                        EntityType::Task,
+                        created.id,
+                        &created.name,
+                        Some(created.id),
+                    ),
+                )?;
+
+                task_ids.insert(task.id, (created.id, true));
+                if !new_project {
+                    undo.push(Operation::DeleteTask { id: created.id });
+                }
+                redo.push(Operation::InsertTask(created));
+                report.tasks += 1;
+            }
+
+            for subtask in &dump.subtasks {
+                // subtasks of a conflicting task are left out with it
+                let Some(&(task_id, new_task)) = task_ids.get(&subtask.task_id) else {
+                    if dump.tasks.iter().any(|task| task.id == subtask.task_id) {
+                        continue;
+                    }
+                    return Err(DatabaseError::NotFound(format!(
+                        "Task {} of imported subtask '{}' not found",
+                        subtask.task_id, subtask.name
+                    )));
+                };
+
+                // within its task, a subtask's own short id outlives renames
+                let synced = match mode {
+                    ImportMode::Sync if !subtask.short_id.is_empty() => subtasks::table
+                        .filter(subtasks::task_id.eq(task_id))
+                        .filter(subtasks::short_id.eq(&subtask.short_id))
+                        .filter(subtasks::deleted_on.is_null())
+                        .select(SubTask::as_select())
+                        .first(conn)
+                        .optional()?,
+                    _ => None,
+                };
+                let existing = match synced {
+                    Some(existing) => Some(existing),
+                    None => subtasks::table
+                        .filter(subtasks::task_id.eq(task_id))
+                        .filter(subtasks::name.eq(&subtask.name))
+                        .filter(subtasks::deleted_on.is_null())
+                        .select(SubTask::as_select())
+                        .first(conn)
+                        .optional()?,
+                };
+                if let (Some(existing), ImportMode::Update | ImportMode::Sync) = (&existing, mode) {
+                    if existing.name != subtask.name {
+                        let undo_op = Operation::RenameSubTask {
+                            id: existing.id,
+                            name: existing.name.clone(),
+                        };
+                        let redo_op = Operation::RenameSubTask {
+                            id: existing.id,
+                            name: subtask.name.clone(),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                        if existing.completed == subtask.completed {
+                            report.updated += 1;
+                        }
+                    }
+                    if existing.completed != subtask.completed {
+                        let undo_op = Operation::SetSubTaskStatus {
+                            id: existing.id,
+                            completed: existing.completed,
+                            completed_on: existing.completed_on,
+                        };
+                        let redo_op = Operation::SetSubTaskStatus {
+                            id: existing.id,
+                            completed: subtask.completed,
+                            completed_on: completed_on(
+                                existing.completed,
+                                existing.completed_on,
+                                subtask.completed,
+                            ),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                        report.updated += 1;
+                    }
+                    continue;
+                }
+                if existing.is_some() {
+                    let (project, task) = tasks::table
+                        .inner_join(projects::table)
+                        .filter(tasks::id.eq(task_id))
+                        .select((projects::name, tasks::name))
+                        .first::<(String, String)>(conn)?;
+                    report
+                        .conflicts
+                        .push(format!("{}/{}/{}", project, task, subtask.name));
+                    continue;
+                }
+
+                let created = ids::keep_or_unique(ids::SUBTASK_PREFIX, &subtask.short_id, |sid| {
+                    diesel::insert_into(subtasks::table)
+                        .values((
+                            subtasks::task_id.eq(task_id),
+                            subtasks::name.eq(&subtask.name),
+                            subtasks::completed.eq(subtask.completed),
+                            subtasks::created_on.eq(subtask.created_on),
+                            subtasks::short_id.eq(sid),
+                            subtasks::updated_on.eq(subtask.updated_on),
+                            subtasks::completed_on.eq(subtask.completed_on),
+                        ))
+                        .returning(SubTask::as_returning())
+                        .get_result(conn)
+                        .map_err(DatabaseError::from)
+                })?;
+                record_event(
+                    conn,
+                    NewEvent::create(
+                        EntityType::SubTask,
+                        created.id,
+                        &created.name,
+                        Some(created.task_id),
+                    ),
+                )?;
+
+                if !new_task {
+                    undo.push(Operation::DeleteSubTask { id: created.id });
+                }
+                redo.push(Operation::InsertSubTask(created));
+                report.subtasks += 1;
+            }
+
+            for thought in &dump.thoughts {
+                let existing = thoughts::table
+                    .filter(thoughts::heading.eq(&thought.heading))
+                    .select(Thoughts::as_select())
+                    .first(conn)
+                    .optional()?;
+                if let (Some(existing), ImportMode::Update | ImportMode::Sync) = (&existing, mode) {
+                    if existing.note != thought.note {
+                        let undo_op = Operation::SetThoughtNote {
+                            id: existing.id,
+                            note: existing.note.clone(),
+                        };
+                        let redo_op = Operation::SetThoughtNote {
+                            id: existing.id,
+                            note: thought.note.clone(),
+                        };
+                        journal::apply(conn, &redo_op)?;
+                        undo.push(undo_op);
+                        redo.push(redo_op);
+                        report.updated += 1;
+                    }
+                    continue;
+                }
+                if existing.is_some() {
+                    report.conflicts.push(thought.heading.clone());
+                    continue;
+                }
+
+                let created = ids::keep_or_unique(ids::THOUGHT_PREFIX, &thought.short_id, |sid| {
+                    diesel::insert_into(thoughts::table)
+                        .values((
+                            thoughts::heading.eq(&thought.heading),
+                            thoughts::note.eq(&thought.note),
+                            thoughts::created_on.eq(thought.created_on),
+                            thoughts::short_id.eq(sid),
+                            thoughts::updated_on.eq(thought.updated_on),
+                            thoughts::tags.eq(&thought.tags),
+                        ))
+                        .returning(Thoughts::as_returning())
+                        .get_result(conn)
+                        .map_err(DatabaseError::from)
+                })?;
+                record_event(
+                    conn,
+                    NewEvent::create(EntityType::Thought, created.id, &created.heading, None),
+                )?;
+
+                undo.push(Operation::DeleteThought { id: created.id });
+                redo.push(Operation::InsertThought(created));
+                report.thoughts += 1;
+            }
+
+            if !report.conflicts.is_empty() && !skip_conflicts {
+                return Err(DatabaseError::ImportConflict(report.conflicts.clone()));
+            }
+
+            let imported = format!(
+                "{}, {}, {} and {}",
+                plural(report.projects, "project"),
+                plural(report.tasks, "task"),
+                plural(report.subtasks, "subtask"),
+                plural(report.thoughts, "thought")
+            );
+            let description = match mode {
+                ImportMode::Merge => format!("import {}", imported),
+                ImportMode::Update | ImportMode::Sync => format!(
+                    "import {}, updating {}",
+                    imported,
+                    plural(report.updated, "item")
+                ),
+                ImportMode::Replace => format!("replace everything with {}", imported),
+            };
+            // nothing to journal when every item was a conflict
+            if mode == ImportMode::Replace || !redo.is_empty() {
+                undo.append(&mut reinsert);
+                journal::record(conn, description, &undo, &redo)?;
+            }
+
+            Ok(report)
+        })
+    }
+
+    /// Opens a transaction that [`Database::rollback_dry_run`] throws away.
+    /// Commands run inside it exactly as they would for real, so what they
+    /// report is what would have happened.
+    pub fn begin_dry_run(&mut self) -> Result<DryRun, DatabaseError> {
+        use crate::db::schema::journal::dsl::{id, journal, undone};
+
+        // Take the write lock now, like any other write would.
+        AnsiTransactionManager::begin_transaction_sql(&mut self.conn, "BEGIN IMMEDIATE")
+            .map_err(DatabaseError::from)?;
+        self.in_dry_run = true;
+
+        // Recording a change first drops undone entries, so new ids are only
+        // guaranteed to be above the live ones.
+        let last_entry: Option<i32> = journal
+            .filter(undone.eq(false))
+            .select(diesel::dsl::max(id))
+            .first(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+
+        Ok(DryRun {
+            last_entry: last_entry.unwrap_or(0),
+        })
+    }
+
+    /// Rolls back a dry run, returning the journal descriptions of the
+    /// changes made inside it.
+    pub fn rollback_dry_run(&mut self, dry_run: DryRun) -> Result<Vec<String>, DatabaseError> {
+        use crate::db::schema::journal::dsl::{description, id, journal};
+
+        let changes = journal
+            .filter(id.gt(dry_run.last_entry))
+            .order(id.asc())
+            .select(description)
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from);
+
+        self.in_dry_run = false;
+        AnsiTransactionManager::rollback_transaction(&mut self.conn)
+            .map_err(DatabaseError::from)?;
+
+        changes
+    }
+
+    /// Reverts the most recent change that hasn't been undone yet, or
+    /// returns `None` if there's none.
+    pub fn undo(&mut self) -> Result<Option<JournalEntry>, DatabaseError> {
+        use crate::db::schema::journal::dsl::{id as entry_id, journal as entries, undone};
+
+        self.transaction(|conn| {
+            let entry = entries
+                .filter(undone.eq(false))
+                .order(entry_id.desc())
+                .select(JournalEntry::as_select())
+                .first(conn)
+                .optional()?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+
+            for op in journal::decode(&entry.undo_ops)? {
+                journal::apply(conn, &op)?;
+            }
+
+            diesel::update(entries.find(entry.id))
+                .set(undone.eq(true))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+
+            Ok(Some(entry))
+        })
+    }
+
+    /// Replays the most recently undone change, or returns `None` if
+    /// there's none.
+    pub fn redo(&mut self) -> Result<Option<JournalEntry>, DatabaseError> {
+        use crate::db::schema::journal::dsl::{id as entry_id, journal as entries, undone};
+
+        self.transaction(|conn| {
+            let entry = entries
+                .filter(undone.eq(true))
+                .order(entry_id.asc())
+                .select(JournalEntry::as_select())
+                .first(conn)
+                .optional()?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+
+            for op in journal::decode(&entry.redo_ops)? {
+                journal::apply(conn, &op)?;
+            }
+
+            diesel::update(entries.find(entry.id))
+                .set(undone.eq(false))
+                .execute(conn)
+                .map_err(DatabaseError::from)?;
+
+            Ok(Some(entry))
+        })
+    }
+
+    /// Returns the changes `undo` would revert, in the order it would revert
+    /// them.
+    pub fn get_undo_list(&mut self, limit: i64) -> Result<Vec<JournalEntry>, DatabaseError> {
+        use crate::db::schema::journal::dsl::{id as entry_id, journal as entries, undone};
+
+        entries
+            .filter(undone.eq(false))
+            .order(entry_id.desc())
+            .limit(limit)
+            .select(JournalEntry::as_select())
+            .load(&mut self.conn)
+            .map_err(DatabaseError::from)
+    }
+}
+
+fn run_migrations(conn: &mut SqliteConnection) -> Result<(), DatabaseError> {
+    conn.run_pending_migrations(MIGRATIONS)
+        .map(|_| ())
+        .map_err(|e| DatabaseError::MigrationError(e.to_string()))
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheckRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    integrity_check: String,
+}
+
+#[derive(QueryableByName)]
+struct ForeignKeyCheckRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    table: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::BigInt>)]
+    rowid: Option<i64>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    parent: String,
+}
+
+/// Writes an audit event. Callers run this inside the same transaction as
+/// the change it describes, so the log never disagrees with the data.
+pub(super) fn record_event(
+    conn: &mut SqliteConnection,
+    event: NewEvent,
+) -> Result<(), DatabaseError> {
+    use crate::db::schema::events;
+
+    diesel::insert_into(events::table)
+        .values(&event)
+        .execute(conn)
+        .map(|_| ())
+        .map_err(DatabaseError::from)
+}
+
+/// Stamps a project, and whichever of its tasks and subtasks are still live,
+/// with `at`. Sharing the stamp is what lets a restore bring back exactly
+/// the rows this delete took. Returns the number of tasks and subtasks.
+pub(super) fn trash_project(
+    conn: &mut SqliteConnection,
+    project: &Project,
+    at: NaiveDateTime,
+) -> Result<(usize, usize), DatabaseError> {
+    use crate::db::schema::{projects, tasks};
+
+    let project_tasks = tasks::table
+        .filter(tasks::project_id.eq(project.id))
+        .filter(tasks::deleted_on.is_null())
+        .select(Task::as_select())
+        .load(conn)
+        .map_err(DatabaseError::from)?;
+
+    let mut subtask_count = 0;
+    for task in &project_tasks {
+        subtask_count += trash_task(conn, task, at)?;
+    }
+
+    diesel::update(projects::table.find(project.id))
+        .set(projects::deleted_on.eq(at))
+        .execute(conn)
+        .map_err(DatabaseError::from)?;
+    record_event(
+        conn,
+        NewEvent::delete(EntityType::Project, project.id, &project.name, None),
+    )?;
+
+    Ok((project_tasks.len(), subtask_count))
+}
+
+/// Stamps a task and its live subtasks with `at`, returning how many
+/// subtasks went with it.
+pub(super) fn trash_task(
+    conn: &mut SqliteConnection,
+    task: &Task,
+    at: NaiveDateTime,
+) -> Result<usize, DatabaseError> {
+    use crate::db::schema::{subtasks, tasks};
+
+    let task_subtasks = subtasks::table
+        .filter(subtasks::task_id.eq(task.id))
+        .filter(subtasks::deleted_on.is_null())
+        .select(SubTask::as_select())
+        .load(conn)
+        .map_err(DatabaseError::from)?;
+
+    for subtask in &task_subtasks {
+        trash_subtask(conn, subtask, at)?;
+    }
+
+    diesel::update(tasks::table.find(task.id))
+        .set(tasks::deleted_on.eq(at))
+        .execute(conn)
+        .map_err(DatabaseError::from)?;
+    record_event(
+        conn,
+        NewEvent::delete(EntityType::Task, task.id, &task.name, Some(task.id)),
+    )?;
+
+    Ok(task_subtasks.len())
+}
+
+pub(super) fn trash_subtask(
+    conn: &mut SqliteConnection,
+    subtask: &SubTask,
+    at: NaiveDateTime,
+) -> Result<(), DatabaseError> {
+    use crate::db::schema::subtasks;
+
+    diesel::update(subtasks::table.find(subtask.id))
+        .set(subtasks::deleted_on.eq(at))
+        .execute(conn)
+        .map_err(DatabaseError::from)?;
+    record_event(
+        conn,
+        NewEvent::delete(
+            EntityType::SubTask,
+            subtask.id,
+            &subtask.name,
+            Some(subtask.task_id),
+        ),
+    )
+}
+
+/// Clears `deleted_on` on a project and on the tasks trashed at the same
+/// moment. Anything deleted separately beforehand stays in the trash.
+pub(super) fn restore_project(
+    conn: &mut SqliteConnection,
+    project: &Project,
+) -> Result<(), DatabaseError> {
+    use crate::db::schema::{projects, tasks};
+
+    let Some(stamp) = project.deleted_on else {
+        return Ok(());
+    };
+
+    diesel::update(projects::table.find(project.id))
+        .set(projects::deleted_on.eq(None::<NaiveDateTime>))
+        .execute(conn)
+        .map_err(|e| match DatabaseError::from(e) {
+            DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                "Project with name '{}' already exists",
+                project.name
+            )),
+            e => e,
+        })?;
+    record_event(
+        conn,
+        NewEvent::restore(EntityType::Project, project.id, &project.name, None),
+    )?;
+
+    let project_tasks = tasks::table
+        .filter(tasks::project_id.eq(project.id))
+        .filter(tasks::deleted_on.eq(stamp))
+        .select(Task::as_select())
+        .load(conn)
+        .map_err(DatabaseError::from)?;
+    for task in &project_tasks {
+        restore_task(conn, task)?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn restore_task(conn: &mut SqliteConnection, task: &Task) -> Result<(), DatabaseError> {
+    use crate::db::schema::{subtasks, tasks};
+
+    let Some(stamp) = task.deleted_on else {
+        return Ok(());
+    };
+
+    diesel::update(tasks::table.find(task.id))
+        .set(tasks::deleted_on.eq(None::<NaiveDateTime>))
+        .execute(conn)
+        .map_err(|e| match DatabaseError::from(e) {
+            DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                "Task with name '{}' already exists",
+                task.name
+            )),
+            e => e,
+        })?;
+    record_event(
+        conn,
+        NewEvent::restore(EntityType::Task, task.id, &task.name, Some(task.id)),
+    )?;
+
+    let task_subtasks = subtasks::table
+        .filter(subtasks::task_id.eq(task.id))
+        .filter(subtasks::deleted_on.eq(stamp))
+        .select(SubTask::as_select())
+        .load(conn)
+        .map_err(DatabaseError::from)?;
+    for subtask in &task_subtasks {
+        restore_subtask(conn, subtask)?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn restore_subtask(
+    conn: &mut SqliteConnection,
+    subtask: &SubTask,
+) -> Result<(), DatabaseError> {
+    use crate::db::schema::subtasks;
+
+    diesel::update(subtasks::table.find(subtask.id))
+        .set(subtasks::deleted_on.eq(None::<NaiveDateTime>))
+        .execute(conn)
+        .map_err(|e| match DatabaseError::from(e) {
+            DatabaseError::UniqueViolation(_) => DatabaseError::AlreadyExists(format!(
+                "Subtask with name '{}' already exists for this task",
+                subtask.name
+            )),
+            e => e,
+        })?;
+    record_event(
+        conn,
+        NewEvent::restore(
+            EntityType::SubTask,
+            subtask.id,
+            &subtask.name,
+            Some(subtask.task_id),
+        ),
+    )
+}
+
+/// The live task whose metadata carries `external_id` under
+/// [`EXTERNAL_ID_KEY`].
+fn find_task_by_external_id(
+    conn: &mut SqliteConnection,
+    external_id: &str,
+) -> Result<Option<Task>, DatabaseError> {
+    use crate::db::schema::tasks;
+
+    // narrowed down in SQL, the JSON itself is checked here
+    let candidates: Vec<Task> = tasks::table
+        .filter(tasks::deleted_on.is_null())
+        .filter(tasks::metadata.like(format!("%{}%", external_id)))
+        .select(Task::as_select())
+        .load(conn)?;
+
+    Ok(candidates
+        .into_iter()
+        .find(|task| task.metadata().get(EXTERNAL_ID_KEY).map(String::as_str) == Some(external_id)))
+}
+
+/// The comment's name, or if a task in the project already has it, the name
+/// followed by where the comment is, then by its fingerprint.
+fn free_task_name(
+    conn: &mut SqliteConnection,
+    req_project_id: i32,
+    comment: &ScannedComment,
+) -> Result<String, DatabaseError> {
+    use crate::db::schema::tasks;
+
+    let candidates = [
+        comment.name.clone(),
+        format!("{} ({})", comment.name, comment.source),
+    ];
+    for name in candidates {
+        let taken = tasks::table
+            .filter(tasks::project_id.eq(req_project_id))
+            .filter(tasks::name.eq(&name))
+            .filter(tasks::deleted_on.is_null())
+            .select(tasks::id)
+            .first::<i32>(conn)
+            .optional()?
+            .is_some();
+        if !taken {
+            return Ok(name);
+        }
+    }
+    Ok(format!(
+        "{} ({}, {})",
+        comment.name, comment.source, comment.fingerprint
+    ))
+}
+
+fn set_scanned_status(
+    conn: &mut SqliteConnection,
+    task: &Task,
+    completed: bool,
+    undo: &mut Vec<Operation>,
+    redo: &mut Vec<Operation>,
+) -> Result<(), DatabaseError> {
+    let redo_op = Operation::SetTaskStatus {
+        id: task.id,
+        completed,
+        completed_on: completed_on(task.completed, task.completed_on, completed),
+    };
+    journal::apply(conn, &redo_op)?;
+    undo.push(Operation::SetTaskStatus {
+        id: task.id,
+        completed: task.completed,
+        completed_on: task.completed_on,
+    });
+    redo.push(redo_op);
+    Ok(())
+}
+
+/// Whether an imported task carries the same priority, due date, tags and
+/// metadata as the existing one.
+fn same_details(existing: &Task, imported: &Task) -> bool {
+    existing.priority == imported.priority
+        && existing.due_on == imported.due_on
+        && existing.tags == imported.tags
+        && existing.metadata() == imported.metadata()
+}
+
+/// `1 task`, `2 tasks`. Shared with the command line's messages, so
+/// journal descriptions read the same.
+pub fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Keeps the original completion time when something already done is marked
+/// done again, and clears it when it's reopened.
+fn completed_on(
+    was_completed: bool,
+    previous: Option<NaiveDateTime>,
+    completed: bool,
+) -> Option<NaiveDateTime> {
+    match (was_completed, completed) {
+        (_, false) => None,
+        (true, true) => previous,
+        (false, true) => Some(Utc::now().naive_utc()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_db() -> (TempDir, Database) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doit.db");
+        let db = Database::new(path.to_str().unwrap()).unwrap();
+        (dir, db)
+    }
+
+    fn insert_project(db: &mut Database, project_name: &str) -> i32 {
+        db.create_project(NewProject {
+            name: project_name.to_string(),
+        })
+        .unwrap()
+        .id
+    }
+
+    fn insert_task(db: &mut Database, project: i32, task_name: &str) -> i32 {
+        db.add_new_task(NewTask {
+            project_id: project,
+            name: task_name.to_string(),
+            completed: false,
+        })
+        .unwrap()
+        .id
+    }
+
+    /// Two projects with a task of the same name, returning
+    /// `(backend, infra, backend_deploy, infra_deploy)`.
+    fn two_deploys(db: &mut Database) -> (i32, i32, i32, i32) {
+        let backend = insert_project(db, "backend");
+        let infra = insert_project(db, "infra");
+        let backend_deploy = insert_task(db, backend, "deploy");
+        let infra_deploy = insert_task(db, infra, "deploy");
+        (backend, infra, backend_deploy, infra_deploy)
+    }
+
+    #[test]
+    fn update_task_only_renames_task_in_given_project() {
+        let (_dir, mut db) = test_db();
+        let (_, infra, backend_deploy, infra_deploy) = two_deploys(&mut db);
+
+        let renamed = db
+            .update_task(infra, "deploy".to_string(), "ship".to_string())
+            .unwrap();
+
+        assert_eq!(renamed, infra_deploy);
+        assert_eq!(db.get_tasks_by_id(infra_deploy).unwrap().name, "ship");
+        assert_eq!(db.get_tasks_by_id(backend_deploy).unwrap().name, "deploy");
+    }
+
+    #[test]
+    fn update_task_rejects_duplicate_name_within_project_only() {
+        let (_dir, mut db) = test_db();
+        let (backend, infra, _, _) = two_deploys(&mut db);
+        insert_task(&mut db, backend, "build");
+
+        let err = db
+            .update_task(backend, "build".to_string(), "deploy".to_string())
+            .unwrap_err();
+        assert!(matches!(err, DatabaseError::AlreadyExists(_)));
+
+        db.update_task(infra, "deploy".to_string(), "build".to_string())
+            .unwrap();
+    }
+
+    #[test]
+    fn update_task_status_only_touches_task_in_given_project() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, infra_deploy) = two_deploys(&mut db);
+
+        db.update_task_status(backend, "deploy".to_string(), true)
+            .unwrap();
+
+        assert!(db.get_tasks_by_id(backend_deploy).unwrap().completed);
+        assert!(!db.get_tasks_by_id(infra_deploy).unwrap().completed);
+    }
+
+    #[test]
+    fn delete_task_only_removes_task_in_given_project() {
+        let (_dir, mut db) = test_db();
+        let (_, infra, backend_deploy, infra_deploy) = two_deploys(&mut db);
+
+        let deleted = db.delete_task(infra, "deploy".to_string()).unwrap();
+
+        assert_eq!(deleted, infra_deploy);
+        assert!(matches!(
+            db.get_tasks_by_id(infra_deploy),
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(db.get_tasks_by_id(backend_deploy).is_ok());
+    }
+
+    #[test]
+    fn task_operations_fail_for_task_in_another_project() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, _, _) = two_deploys(&mut db);
+        let empty = insert_project(&mut db, "empty");
+        insert_task(&mut db, backend, "build");
+
+        assert!(matches!(
+            db.update_task(empty, "build".to_string(), "x".to_string()),
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.update_task_status(empty, "build".to_string(), true),
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.delete_task(empty, "build".to_string()),
+            Err(DatabaseError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn find_tasks_by_name_returns_every_project() {
+        let (_dir, mut db) = test_db();
+        two_deploys(&mut db);
+
+        let found = db.find_tasks_by_name("deploy".to_string()).unwrap();
+        let projects: Vec<_> = found.iter().map(|(_, p)| p.name.as_str()).collect();
+
+        assert_eq!(projects, vec!["backend", "infra"]);
+    }
+
+    #[test]
+    fn inserts_default_created_on() {
+        let (_dir, mut db) = test_db();
+        let before = Utc::now().naive_utc() - chrono::Duration::seconds(1);
+
+        let project = db
+            .create_project(NewProject {
+                name: "backend".to_string(),
+            })
+            .unwrap();
+
+        assert!(project.created_on >= before);
+        assert!(project.updated_on.is_none());
+    }
+
+    #[test]
+    fn update_task_status_tracks_completed_on() {
+        let (_dir, mut db) = test_db();
+        let project = insert_project(&mut db, "backend");
+        let task = insert_task(&mut db, project, "deploy");
+
+        db.update_task_status(project, "deploy".to_string(), true)
+            .unwrap();
+        let done = db.get_tasks_by_id(task).unwrap();
+        assert!(done.completed_on.is_some());
+        assert!(done.updated_on.is_some());
+
+        db.update_task_status(project, "deploy".to_string(), true)
+            .unwrap();
+        let done_again = db.get_tasks_by_id(task).unwrap();
+        assert_eq!(done_again.completed_on, done.completed_on);
+
+        db.update_task_status(project, "deploy".to_string(), false)
+            .unwrap();
+        assert!(db.get_tasks_by_id(task).unwrap().completed_on.is_none());
+    }
+
+    #[test]
+    fn changes_are_recorded_as_events() {
+        let (_dir, mut db) = test_db();
+        let project = insert_project(&mut db, "backend");
+        let task = insert_task(&mut db, project, "deploy");
+        db.update_task(project, "deploy".to_string(), "ship".to_string())
+            .unwrap();
+        db.delete_task(project, "ship".to_string()).unwrap();
+
+        let actions: Vec<_> = db
+            .get_events(Some(task), None, None, None)
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.action, e.old_value, e.new_value))
+            .collect();
+
+        assert_eq!(
+            actions,
+            vec![
+                ("delete".to_string(), Some("ship".to_string()), None),
+                (
+                    "rename".to_string(),
+                    Some("deploy".to_string()),
+                    Some("ship".to_string())
+                ),
+                ("create".to_string(), None, Some("deploy".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_change_records_no_event() {
+        let (_dir, mut db) = test_db();
+        let project = insert_project(&mut db, "backend");
+        insert_task(&mut db, project, "deploy");
+        insert_task(&mut db, project, "build");
+        let before = db.get_events(None, None, None, None).unwrap().len();
+
+        assert!(db
+            .update_task(project, "build".to_string(), "deploy".to_string())
+            .is_err());
+
+        assert_eq!(db.get_events(None, None, None, None).unwrap().len(), before);
+    }
+
+    #[test]
+    fn undo_restores_deleted_project_and_redo_deletes_it_again() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, _) = two_deploys(&mut db);
+        let subtask = db
+            .add_new_subtask(NewSubTask {
+                task_id: backend_deploy,
+                name: "tag release".to_string(),
+                completed: false,
+            })
+            .unwrap();
+
+        db.delete_project("backend".to_string()).unwrap();
+        assert!(db.get_project_by_id(backend).is_err());
+
+        let entry = db.undo().unwrap().unwrap();
+        assert!(entry.description.contains("backend"));
+        assert_eq!(db.get_project_by_id(backend).unwrap().name, "backend");
+        let task = db.get_tasks_by_id(backend_deploy).unwrap();
+        assert_eq!(task.project_id, backend);
+        let restored = db.get_subtask_by_id(subtask.id).unwrap();
+        assert_eq!(restored.short_id, subtask.short_id);
+
+        db.redo().unwrap().unwrap();
+        assert!(db.get_project_by_id(backend).is_err());
+        assert!(db.get_subtask_by_id(subtask.id).is_err());
+        assert!(db.redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn new_change_discards_undone_entries() {
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let deploy = insert_task(&mut db, backend, "deploy");
+
+        db.update_task_status(backend, "deploy".to_string(), true)
+            .unwrap();
+        db.undo().unwrap().unwrap();
+        assert!(!db.get_tasks_by_id(deploy).unwrap().completed);
+
+        insert_task(&mut db, backend, "build");
+        assert!(db.redo().unwrap().is_none());
+        assert_eq!(db.get_undo_list(10).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn deleted_task_goes_to_trash_and_frees_its_name() {
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let deploy = insert_task(&mut db, backend, "deploy");
+
+        db.delete_task(backend, "deploy".to_string()).unwrap();
+
+        assert!(db.get_tasks_by_id(deploy).is_err());
+        assert!(db.get_all_tasks(backend).unwrap().is_empty());
+        let trash = db.get_trash().unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].path(), "backend/deploy");
+
+        let replacement = insert_task(&mut db, backend, "deploy");
+        assert_ne!(replacement, deploy);
+        let err = db.restore_task(deploy).unwrap_err();
+        assert!(matches!(err, DatabaseError::AlreadyExists(_)));
+    }
+
+    #[test]
+    fn restoring_project_brings_back_only_what_was_deleted_with_it() {
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let deploy = insert_task(&mut db, backend, "deploy");
+        let build = insert_task(&mut db, backend, "build");
+        let subtask = db
+            .add_new_subtask(NewSubTask {
+                task_id: deploy,
+                name: "tag release".to_string(),
+                completed: false,
+            })
+            .unwrap();
+
+        db.delete_task(backend, "build".to_string()).unwrap();
+        db.delete_project("backend".to_string()).unwrap();
+        // the project, plus `build` which was deleted on its own
+        assert_eq!(db.get_trash().unwrap().len(), 2);
+
+        db.restore_project(backend).unwrap();
+
+        assert_eq!(db.get_tasks_by_id(deploy).unwrap().name, "deploy");
+        assert_eq!(
+            db.get_subtask_by_id(subtask.id).unwrap().name,
+            "tag release"
+        );
+        assert!(db.get_tasks_by_id(build).is_err());
+        assert_eq!(db.get_trash().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn empty_trash_only_purges_items_older_than_cutoff() {
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let deploy = insert_task(&mut db, backend, "deploy");
+        db.delete_task(backend, "deploy".to_string()).unwrap();
+
+        let a_day_ago = Utc::now().naive_utc() - chrono::Duration::days(1);
+        assert_eq!(db.empty_trash(Some(a_day_ago)).unwrap(), 0);
+        assert_eq!(db.get_trash().unwrap().len(), 1);
+
+        assert_eq!(db.empty_trash(None).unwrap(), 1);
+        assert!(db.get_trash().unwrap().is_empty());
+        assert!(matches!(
+            db.restore_task(deploy),
+            Err(DatabaseError::NotFound(_))
+        ));
+        // adding and deleting the task can't be undone any more, adding
+        // the project still can
+        assert!(db.undo().unwrap().is_some());
+        assert!(db.undo().unwrap().is_none());
+    }
+
+    #[test]
+    fn unrelated_changes_can_be_undone_after_a_partial_purge() {
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let cache = insert_task(&mut db, backend, "cache");
+        let old = insert_task(&mut db, backend, "old");
+        db.update_task_status(backend, "cache".to_string(), true)
+            .unwrap();
+        db.delete_task(backend, "old".to_string()).unwrap();
+
+        assert_eq!(db.empty_trash(None).unwrap(), 1);
+        // SQLite hands the purged task's id out again
+        assert_eq!(insert_task(&mut db, backend, "next"), old);
+        db.undo().unwrap().unwrap();
+
+        let entry = db.undo().unwrap().unwrap();
+        assert_eq!(entry.description, "mark task 'cache' as done");
+        assert!(!db.get_tasks_by_id(cache).unwrap().completed);
+        db.redo().unwrap().unwrap();
+        assert!(db.get_tasks_by_id(cache).unwrap().completed);
+    }
+
+    #[test]
+    fn archived_projects_are_hidden_unless_asked_for() {
+        let (_dir, mut db) = test_db();
+        insert_project(&mut db, "backend");
+        insert_project(&mut db, "infra");
+
+        db.set_project_archived("infra".to_string(), true).unwrap();
+
+        let names = |projects: Vec<Project>| -> Vec<String> {
+            projects.into_iter().map(|p| p.name).collect()
+        };
+        assert_eq!(names(db.get_all_projects(false).unwrap()), ["backend"]);
+        assert_eq!(
+            names(db.get_all_projects(true).unwrap()),
+            ["backend", "infra"]
+        );
+
+        db.undo().unwrap().unwrap();
+        assert_eq!(db.get_all_projects(false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dry_run_reports_cascade_and_changes_nothing() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, _) = two_deploys(&mut db);
+        insert_task(&mut db, backend, "build");
+        db.add_new_subtask(NewSubTask {
+            task_id: backend_deploy,
+            name: "tag release".to_string(),
+            completed: false,
+        })
+        .unwrap();
+        db.undo().unwrap().unwrap();
+        db.redo().unwrap().unwrap();
+
+        let dry_run = db.begin_dry_run().unwrap();
+        db.delete_project("backend".to_string()).unwrap();
+        let changes = db.rollback_dry_run(dry_run).unwrap();
+
+        assert_eq!(changes, ["delete project 'backend' (2 tasks, 1 subtask)"]);
+        assert_eq!(db.get_all_tasks(backend).unwrap().len(), 2);
+        assert!(db.get_trash().unwrap().is_empty());
+        assert_eq!(
+            db.undo().unwrap().unwrap().description,
+            "add subtask 'tag release'"
+        );
+    }
+
+    #[test]
+    fn restore_brings_back_backup_and_snapshots_current_database() {
+        let (dir, mut db) = test_db();
+        let url = dir.path().join("doit.db");
+        let url = url.to_str().unwrap();
+        insert_project(&mut db, "backend");
+
+        let backup_path = dir.path().join("backup.db");
+        backup::backup(url, &backup_path).unwrap();
+        insert_project(&mut db, "infra");
+
+        backup::restore(url, &backup_path).unwrap();
+        let mut db = Database::new(url).unwrap();
+
+        let names: Vec<String> = db
+            .get_all_projects(true)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["backend"]);
+        assert_eq!(backup::list_snapshots(url).unwrap().len(), 1);
+
+        let not_a_backup = dir.path().join("notes.txt");
+        std::fs::write(&not_a_backup, "hello").unwrap();
+        assert!(matches!(
+            backup::restore(url, &not_a_backup),
+            Err(DatabaseError::BackupError(_))
+        ));
+    }
+
+    #[test]
+    fn check_finds_and_repair_removes_orphans() {
+        let (_dir, mut db) = test_db();
+        let (backend, infra, backend_deploy, infra_deploy) = two_deploys(&mut db);
+        db.add_new_subtask(NewSubTask {
+            task_id: backend_deploy,
+            name: "tag release".to_string(),
+            completed: false,
+        })
+        .unwrap();
+        assert!(db.check().unwrap().is_ok());
+        db.update_task_status(infra, "deploy".to_string(), true)
+            .unwrap();
+
+        // orphans can only appear when something wrote with enforcement off
+        diesel::sql_query("PRAGMA foreign_keys = OFF")
+            .execute(&mut db.conn)
+            .unwrap();
+        diesel::sql_query(format!("DELETE FROM projects WHERE id = {}", backend))
+            .execute(&mut db.conn)
+            .unwrap();
+
+        let report = db.check().unwrap();
+        assert_eq!(report.orphaned_tasks.len(), 1);
+        assert_eq!(report.orphaned_subtasks.len(), 1);
+        assert!(!report.foreign_keys.is_empty());
+
+        assert_eq!(db.remove_orphans().unwrap().removed(), 2);
+        assert!(db.check().unwrap().is_ok());
+        assert_eq!(db.get_tasks_by_id(infra_deploy).unwrap().name, "deploy");
+
+        // only the history of what was removed is forgotten
+        let entry = db.undo().unwrap().unwrap();
+        assert_eq!(entry.description, "mark task 'deploy' as done");
+        assert!(!db.get_tasks_by_id(infra_deploy).unwrap().completed);
+        while db.undo().unwrap().is_some() {}
+        assert!(db.get_all_projects(true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tasks_need_an_existing_project() {
+        let (_dir, mut db) = test_db();
+
+        let err = db
+            .add_new_task(NewTask {
+                project_id: 42,
+                name: "deploy".to_string(),
+                completed: false,
+            })
+            .unwrap_err();
+        assert!(matches!(err, DatabaseError::NotFound(_)));
+
+        // bypass the lookup `add_new_task` does first
+        let err = diesel::insert_into(crate::db::schema::tasks::table)
+            .values((
+                NewTask {
+                    project_id: 42,
+                    name: "deploy".to_string(),
+                    completed: false,
+                },
+                crate::db::schema::tasks::short_id.eq("t-dead"),
+            ))
+            .execute(&mut db.conn)
+            .map_err(DatabaseError::from)
+            .unwrap_err();
+        assert!(matches!(err, DatabaseError::ForeignKeyViolation(_)));
+    }
+
+    #[test]
+    fn removing_a_project_row_cascades_to_tasks_and_subtasks() {
+        use crate::db::schema::projects;
+
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, infra_deploy) = two_deploys(&mut db);
+        let subtask = db
+            .add_new_subtask(NewSubTask {
+                task_id: backend_deploy,
+                name: "tag release".to_string(),
+                completed: false,
+            })
+            .unwrap();
+
+        diesel::delete(projects::table.find(backend))
+            .execute(&mut db.conn)
+            .unwrap();
+
+        assert!(db.check().unwrap().is_ok());
+        assert!(matches!(
+            db.get_tasks_by_id(backend_deploy),
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.get_subtask_by_id(subtask.id),
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(db.get_tasks_by_id(infra_deploy).is_ok());
+    }
+
+    #[test]
+    fn writes_wait_for_another_connection_to_release_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doit.db");
+        let url = path.to_str().unwrap();
+        let mut db = Database::new(url).unwrap();
+        let mut other = Database::new(url).unwrap();
+
+        // A dry run holds the write lock until it's rolled back.
+        let dry_run = other.begin_dry_run().unwrap();
+        let holder = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            other.rollback_dry_run(dry_run).unwrap();
+        });
+
+        insert_project(&mut db, "backend");
+        holder.join().unwrap();
+
+        assert_eq!(db.get_all_projects(false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn locked_database_is_reported_as_busy() {
+        use diesel::result::DatabaseErrorKind;
+
+        let err = DatabaseError::from(DieselError::DatabaseError(
+            DatabaseErrorKind::Unknown,
+            Box::new("database is locked".to_string()),
+        ));
+        assert!(matches!(err, DatabaseError::Busy(_)));
+    }
+
+    #[test]
+    fn import_merges_into_existing_projects_and_keeps_short_ids() {
+        let (_dir, mut source) = test_db();
+        let (_, _, backend_deploy, _) = two_deploys(&mut source);
+        source
+            .add_new_subtask(NewSubTask {
+                task_id: backend_deploy,
+                name: "tag release".to_string(),
+                completed: false,
+            })
+            .unwrap();
+        let dump = source.export().unwrap();
+
+        let (_dir, mut db) = test_db();
+        let backend = insert_project(&mut db, "backend");
+        let report = db.import(&dump, ImportMode::Merge, false).unwrap();
+
+        assert_eq!((report.projects, report.tasks, report.subtasks), (1, 2, 1));
+        let tasks = db.get_all_tasks(backend).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].short_id, dump.tasks[0].short_id);
+        assert_eq!(db.get_all_subtasks(tasks[0].id).unwrap().len(), 1);
+
+        db.undo().unwrap().unwrap();
+        assert!(db.get_all_tasks(backend).unwrap().is_empty());
+        assert_eq!(db.get_all_projects(true).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn import_conflicts_import_nothing_unless_skipped() {
+        let (_dir, mut db) = test_db();
+        two_deploys(&mut db);
+        let mut dump = db.export().unwrap();
+        dump.projects.push(Project {
+            id: 99,
+            name: "docs".to_string(),
+            ..dump.projects[0].clone()
+        });
+
+        let err = db.import(&dump, ImportMode::Merge, false).unwrap_err();
+        assert!(
+            matches!(err, DatabaseError::ImportConflict(ref paths) if paths == &["backend/deploy", "infra/deploy"])
+        );
+        assert_eq!(db.get_all_projects(true).unwrap().len(), 2);
+
+        let report = db.import(&dump, ImportMode::Merge, true).unwrap();
+        assert_eq!(report.projects, 1);
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(db.get_all_projects(true).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn replace_import_can_be_undone() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, _) = two_deploys(&mut db);
+        db.delete_task(backend, "deploy".to_string()).unwrap();
+        let dump = Dump {
+            projects: vec![db.get_project_by_id(backend).unwrap()],
+            ..Dump::default()
+        };
+
+        db.import(&dump, ImportMode::Replace, false).unwrap();
+        assert_eq!(db.get_all_projects(true).unwrap().len(), 1);
+        assert!(db.get_trash().unwrap().is_empty());
+
+        db.undo().unwrap().unwrap();
+        assert_eq!(db.get_all_projects(true).unwrap().len(), 2);
+        assert_eq!(db.get_trash().unwrap().len(), 1);
+        db.restore_task(backend_deploy).unwrap();
+        assert_eq!(db.get_all_tasks(backend).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn update_import_sets_completion_of_existing_items() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, backend_deploy, _) = two_deploys(&mut db);
+        let mut dump = db.export().unwrap();
+        dump.tasks.retain(|task| task.id == backend_deploy);
+        dump.tasks[0].completed = true;
+        dump.subtasks.push(SubTask {
+            id: 1,
+            task_id: backend_deploy,
+            name: "tag release".to_string(),
+            completed: false,
+            created_on: dump.tasks[0].created_on,
+            short_id: String::new(),
+            updated_on: None,
+            completed_on: None,
+            deleted_on: None,
+        });
+
+        let report = db.import(&dump, ImportMode::Update, false).unwrap();
+        assert_eq!((report.tasks, report.subtasks, report.updated), (0, 1, 1));
+        assert!(report.conflicts.is_empty());
+        let deploy = db.get_tasks_by_id(backend_deploy).unwrap();
+        assert!(deploy.completed && deploy.completed_on.is_some());
+        assert_eq!(db.get_all_subtasks(backend_deploy).unwrap().len(), 1);
+
+        // importing the same again changes nothing
+        let report = db.import(&dump, ImportMode::Update, false).unwrap();
+        assert_eq!((report.subtasks, report.updated), (0, 0));
+
+        db.undo().unwrap().unwrap();
+        assert!(!db.get_tasks_by_id(backend_deploy).unwrap().completed);
+        assert!(db.get_all_subtasks(backend_deploy).unwrap().is_empty());
+        assert_eq!(db.get_all_tasks(backend).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn sync_import_updates_task_details() {
+        let (_dir, mut db) = test_db();
+        let (_, _, backend_deploy, _) = two_deploys(&mut db);
+        let mut dump = db.export().unwrap();
+        dump.tasks.retain(|task| task.id == backend_deploy);
+        dump.tasks[0].priority = Some("A".to_string());
+        dump.tasks[0].due_on = chrono::NaiveDate::from_ymd_opt(2024, 11, 8);
+        dump.tasks[0].tags = "work".to_string();
+        dump.tasks[0].metadata = r#"{"rec":"1w"}"#.to_string();
+
+        let report = db.import(&dump, ImportMode::Sync, false).unwrap();
+        assert_eq!((report.tasks, report.updated), (0, 1));
+        let deploy = db.get_tasks_by_id(backend_deploy).unwrap();
+        assert_eq!(deploy.priority.as_deref(), Some("A"));
+        assert_eq!(deploy.tags().collect::<Vec<_>>(), ["work"]);
+        assert_eq!(deploy.metadata()["rec"], "1w");
+
+        db.undo().unwrap().unwrap();
+        let deploy = db.get_tasks_by_id(backend_deploy).unwrap();
+        assert_eq!((deploy.priority.as_deref(), deploy.due_on), (None, None));
+        assert!(deploy.metadata().is_empty());
+    }
+
+    #[test]
+    fn sync_import_matches_external_id_before_name() {
+        let (_dir, mut db) = test_db();
+        let (_, _, backend_deploy, _) = two_deploys(&mut db);
+        let mut dump = db.export().unwrap();
+        dump.tasks.retain(|task| task.id == backend_deploy);
+        dump.tasks[0].metadata = r#"{"uuid":"a1"}"#.to_string();
+        db.import(&dump, ImportMode::Sync, false).unwrap();
+
+        dump.tasks[0].name = "Deploy to staging".to_string();
+        let report = db.import(&dump, ImportMode::Sync, false).unwrap();
+        assert_eq!((report.tasks, report.updated), (0, 1));
+        let deploy = db.get_tasks_by_id(backend_deploy).unwrap();
+        assert_eq!(deploy.name, "Deploy to staging");
+    }
+
+    #[test]
+    fn sync_import_renames_subtasks_with_the_same_short_id() {
+        let (_dir, mut db) = test_db();
+        let (_, _, backend_deploy, _) = two_deploys(&mut db);
+        db.add_new_subtask(NewSubTask {
+            task_id: backend_deploy,
+            name: "Tag release".to_string(),
+            completed: false,
+        })
+        .unwrap();
+        let mut dump = db.export().unwrap();
+        dump.subtasks[0].name = "Tag the release".to_string();
+
+        let report = db.import(&dump, ImportMode::Sync, false).unwrap();
+        assert_eq!((report.subtasks, report.updated), (0, 1));
+        let subtasks = db.get_all_subtasks(backend_deploy).unwrap();
+        assert_eq!(subtasks.len(), 1);
+        assert_eq!(subtasks[0].name, "Tag the release");
+    }
+
+    #[test]
+    fn thought_changes_are_undone_together() {
+        let (_dir, mut db) = test_db();
+        let new_thought = |heading: &str| {
+            ThoughtChange::Add(NewThought {
+                heading: heading.to_string(),
+                note: String::new(),
+                tags: String::new(),
+            })
+        };
+        let added = db
+            .change_thoughts(
+                "add".to_string(),
+                vec![new_thought("boat"), new_thought("plan")],
+            )
+            .unwrap();
+        let (boat, plan) = (added[0].id, added[1].id);
+
+        db.change_thoughts(
+            "sync".to_string(),
+            vec![
+                ThoughtChange::Edit {
+                    id: boat,
+                    heading: "Boat".to_string(),
+                    note: "build it".to_string(),
+                    tags: "idea".to_string(),
+                },
+                ThoughtChange::Delete { id: plan },
+                new_thought("kite"),
+            ],
+        )
+        .unwrap();
+        let headings = |db: &mut Database| {
+            let thoughts = db.get_all_thoughts().unwrap();
+            thoughts.into_iter().map(|t| t.heading).collect::<Vec<_>>()
+        };
+        assert_eq!(headings(&mut db), ["Boat", "kite"]);
+        assert_eq!(db.get_all_thoughts().unwrap()[0].tags, "idea");
+
+        db.undo().unwrap().unwrap();
+        assert_eq!(headings(&mut db), ["boat", "plan"]);
+    }
+
+    #[test]
+    fn scanned_tasks_follow_their_comments() {
+        let (_dir, mut db) = test_db();
+        let project_id = insert_project(&mut db, "code");
+        insert_task(&mut db, project_id, "TODO: retry");
+        let comment = |fingerprint: &str, name: &str, line: u32| ScannedComment {
+            fingerprint: fingerprint.to_string(),
+            name: name.to_string(),
+            source: format!("src/main.rs:{}", line),
+        };
+
+        let report = db
+            .scan(
+                project_id,
+                "scan".to_string(),
+                vec![
+                    comment("a", "TODO: retry", 3),
+                    comment("b", "FIXME: cache", 9),
+                ],
+            )
+            .unwrap();
+        // the hand-made task keeps its name and is left alone
+        assert_eq!(
+            report.added,
+            ["TODO: retry (src/main.rs:3)", "FIXME: cache"]
+        );
+
+        let report = db
+            .scan(
+                project_id,
+                "scan".to_string(),
+                vec![comment("a", "TODO: retry", 4)],
+            )
+            .unwrap();
+        assert_eq!(report.moved, 1);
+        assert_eq!(report.closed, ["FIXME: cache"]);
+        let cache = db
+            .get_task_by_name_and_project_id("FIXME: cache".to_string(), project_id)
+            .unwrap();
+        assert!(cache.completed);
+
+        db.undo().unwrap().unwrap();
+        let cache = db.get_tasks_by_id(cache.id).unwrap();
+        assert!(!cache.completed);
+        assert_eq!(cache.metadata()[SOURCE_KEY], "src/main.rs:9");
+
+        db.update_task_status(project_id, "FIXME: cache".to_string(), true)
+            .unwrap();
+        let report = db
+            .scan(
+                project_id,
+                "scan".to_string(),
+                vec![
+                    comment("a", "TODO: retry", 3),
+                    comment("b", "FIXME: cache", 9),
+                ],
+            )
+            .unwrap();
+        assert_eq!(report.reopened, ["FIXME: cache"]);
+        assert!(report.added.is_empty());
+        assert!(!db.get_tasks_by_id(cache.id).unwrap().completed);
+        assert!(
+            !db.get_task_by_name_and_project_id("TODO: retry".to_string(), project_id)
+                .unwrap()
+                .completed
+        );
+    }
+
+    #[test]
+    fn applying_a_commit_twice_changes_nothing() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, deploy, _) = two_deploys(&mut db);
+        let cache = insert_task(&mut db, backend, "cache");
+        let subtask = db
+            .add_new_subtask(NewSubTask {
+                task_id: cache,
+                name: "expire".to_string(),
+                completed: false,
+            })
+            .unwrap();
+        let changes = || {
+            vec![
+                CommitChange::CloseTask {
+                    id: deploy,
+                    commit: "abc".to_string(),
+                },
+                CommitChange::Reference {
+                    id: cache,
+                    commit: "abc".to_string(),
+                },
+                CommitChange::CloseSubTask { id: subtask.id },
+            ]
+        };
+
+        let report = db.apply_commits("sync".to_string(), changes()).unwrap();
+        assert_eq!(report.closed, ["deploy", "expire"]);
+        assert_eq!(report.referenced, ["cache"]);
+        let report = db.apply_commits("sync".to_string(), changes()).unwrap();
+        assert!(report.closed.is_empty() && report.referenced.is_empty());
+
+        db.apply_commits(
+            "sync".to_string(),
+            vec![CommitChange::Reference {
+                id: cache,
+                commit: "def".to_string(),
+            }],
+        )
+        .unwrap();
+        assert_eq!(
+            db.get_tasks_by_id(cache).unwrap().metadata()[COMMITS_KEY],
+            "abc,def"
+        );
+
+        db.undo().unwrap().unwrap();
+        db.undo().unwrap().unwrap();
+        let task = db.get_tasks_by_id(deploy).unwrap();
+        assert!(!task.completed);
+        assert!(task.metadata().is_empty());
+        assert!(!db.get_subtask_by_id(subtask.id).unwrap().completed);
+    }
+
+    #[test]
+    fn commits_leave_trashed_rows_alone() {
+        let (_dir, mut db) = test_db();
+        let (backend, _, deploy, _) = two_deploys(&mut db);
+        let subtask = db
+            .add_new_subtask(NewSubTask {
+                task_id: deploy,
+                name: "tag release".to_string(),
+                completed: false,
+            })
+            .unwrap();
+        db.delete_subtask(subtask.id, deploy).unwrap();
+
+        let result = db.apply_commits(
+            "sync".to_string(),
+            vec![CommitChange::CloseSubTask { id: subtask.id }],
+        );
+        assert!(matches!(result, Err(DatabaseError::NotFound(_))));
+
+        db.delete_task(backend, "deploy".to_string()).unwrap();
+        let result = db.apply_commits(
+            "sync".to_string(),
+            vec![CommitChange::CloseTask {
+                id: deploy,
+                commit: "abc".to_string(),
+            }],
+        );
+        assert!(matches!(result, Err(DatabaseError::NotFound(_))));
+
+        let task = db.get_trashed_tasks().unwrap().remove(0).0;
+        assert!(!task.completed);
+        assert!(task.metadata().is_empty());
+    }
+
+    #[test]
+    fn undo_reports_a_missing_row_instead_of_an_empty_journal() {
+        let (_dir, mut db) = test_db();
+        let project_id = insert_project(&mut db, "backend");
+        insert_task(&mut db, project_id, "deploy");
+        diesel::sql_query("DELETE FROM tasks")
+            .execute(&mut db.conn)
+            .unwrap();
+
+        assert!(matches!(db.undo(), Err(DatabaseError::NotFound(_))));
+    }
+
+    /// A database at the schema of the first release, written with foreign
+    /// keys off the way that release could: a task whose project is gone,
+    /// with a subtask of its own, and a subtask whose task is gone. The
+    /// missing task has the id the next task gets.
+    fn legacy_db_with_orphans() -> (TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doit.db");
+        let url = path.to_str().unwrap().to_string();
+
+        let mut conn = SqliteConnection::establish(&url).unwrap();
+        while conn
+            .applied_migrations()
+            .unwrap()
+            .iter()
+            .all(|version| version.to_string().as_str() < "20241104033215")
+        {
+            conn.run_next_migration(MIGRATIONS).unwrap();
+        }
+        conn.batch_execute(
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO projects VALUES (1, 'infra', '2024-11-01 09:00:00');
+             INSERT INTO tasks VALUES (1, 1, 'build', FALSE, '2024-11-01 09:00:00');
+             INSERT INTO tasks VALUES (2, 7, 'deploy', FALSE, '2024-11-01 09:00:00');
+             INSERT INTO subtasks VALUES (1, 2, 'tag release', TRUE, '2024-11-01 09:00:00');
+             INSERT INTO subtasks VALUES (2, 3, 'cache', FALSE, '2024-11-01 09:00:00');",
+        )
+        .unwrap();
+
+        (dir, url)
+    }
+
+    #[test]
+    fn upgrading_a_legacy_database_recovers_its_orphans() {
+        let (_dir, url) = legacy_db_with_orphans();
+
+        let mut db = Database::new(&url).unwrap();
+
+        assert!(!db.conn.has_pending_migration(MIGRATIONS).unwrap());
+        assert!(db.check().unwrap().is_ok());
+        let recovered = db
+            .get_project_by_name("Recovered orphans".to_string())
+            .unwrap();
+        let tasks = db.get_all_tasks(recovered.id).unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["deploy (t-0002)", "Recovered subtasks"]);
+        // a recovered task keeps its subtasks
+        let subtasks = db.get_all_subtasks(2).unwrap();
+        assert_eq!(subtasks.len(), 1);
+        assert_eq!(subtasks[0].name, "tag release");
+        let subtasks = db.get_all_subtasks(tasks[1].id).unwrap();
+        assert_eq!(subtasks.len(), 1);
+        assert_eq!(subtasks[0].name, "cache (s-0002)");
+        assert_eq!(db.get_all_tasks(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn recovered_orphans_cascade_like_any_other_rows() {
+        use crate::db::schema::projects;
+
+        let (_dir, url) = legacy_db_with_orphans();
+        let mut db = Database::new(&url).unwrap();
+        let recovered = db
+            .get_project_by_name("Recovered orphans".to_string())
+            .unwrap();
+
+        diesel::delete(projects::table.find(recovered.id))
+            .execute(&mut db.conn)
+            .unwrap();
+
+        let stats = db.get_stats().unwrap();
+        assert_eq!((stats.tasks, stats.subtasks), (1, 0));
+        assert!(db.check().unwrap().is_ok());
+    }
+
+    #[test]
+    fn repair_removes_orphans_before_migrating() {
+        let (_dir, url) = legacy_db_with_orphans();
+        let mut db = Database::without_migrating(&url).unwrap();
+        assert!(db.conn.has_pending_migration(MIGRATIONS).unwrap());
+
+        let report = db.remove_orphans().unwrap();
+
+        let names: Vec<&str> = report.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["deploy"]);
+        assert_eq!(report.tasks[0].parent_id, 7);
+        let names: Vec<&str> = report.subtasks.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["tag release", "cache"]);
+        assert!(!db.conn.has_pending_migration(MIGRATIONS).unwrap());
+        assert!(db.check().unwrap().is_ok());
+        assert!(db
+            .get_project_by_name("Recovered orphans".to_string())
+            .is_err());
+        let stats = db.get_stats().unwrap();
+        assert_eq!((stats.tasks, stats.subtasks), (1, 0));
+    }
 }
@@ -1,10 +1,38 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    events (id) {
+        id -> Integer,
+        entity_type -> Text,
+        entity_id -> Integer,
+        entity_name -> Text,
+        task_id -> Nullable<Integer>,
+        action -> Text,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        created_on -> Timestamp,
+    }
+}
+
+diesel::table! {
+    journal (id) {
+        id -> Integer,
+        description -> Text,
+        undo_ops -> Text,
+        redo_ops -> Text,
+        undone -> Bool,
+        created_on -> Timestamp,
+    }
+}
+
 diesel::table! {
     projects (id) {
         id -> Integer,
         name -> Text,
         created_on -> Timestamp,
+        updated_on -> Nullable<Timestamp>,
+        deleted_on -> Nullable<Timestamp>,
+        archived_on -> Nullable<Timestamp>,
     }
 }
 
@@ -15,6 +43,10 @@ diesel::table! {
         name -> Text,
         completed -> Bool,
         created_on -> Timestamp,
+        short_id -> Text,
+        updated_on -> Nullable<Timestamp>,
+        completed_on -> Nullable<Timestamp>,
+        deleted_on -> Nullable<Timestamp>,
     }
 }
 
@@ -25,6 +57,14 @@ diesel::table! {
         name -> Text,
         completed -> Bool,
         created_on -> Timestamp,
+        short_id -> Text,
+        updated_on -> Nullable<Timestamp>,
+        completed_on -> Nullable<Timestamp>,
+        deleted_on -> Nullable<Timestamp>,
+        priority -> Nullable<Text>,
+        due_on -> Nullable<Date>,
+        tags -> Text,
+        metadata -> Text,
     }
 }
 
@@ -34,10 +74,13 @@ diesel::table! {
         heading -> Text,
         note -> Text,
         created_on -> Timestamp,
+        short_id -> Text,
+        updated_on -> Nullable<Timestamp>,
+        tags -> Text,
     }
 }
 
 diesel::joinable!(subtasks -> tasks (task_id));
 diesel::joinable!(tasks -> projects (project_id));
 
-diesel::allow_tables_to_appear_in_same_query!(projects, subtasks, tasks, thoughts,);
+diesel::allow_tables_to_appear_in_same_query!(events, journal, projects, subtasks, tasks, thoughts,);
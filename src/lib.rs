@@ -0,0 +1,3 @@
+pub mod cli;
+pub mod db;
+pub mod service;
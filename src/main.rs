@@ -1,12 +1,8 @@
 use clap::Parser;
 use std::process;
 
-mod cli;
-mod db;
-mod service;
-
-use cli::handler::CommandHandler;
-use cli::types::Cli;
+use doit::cli::handler::CommandHandler;
+use doit::cli::types::Cli;
 
 fn main() {
     let cli = Cli::parse();
@@ -14,7 +10,12 @@ fn main() {
     // Make it configurable later on
     let database_url = "doit.db";
 
-    let mut handler = match CommandHandler::new(database_url) {
+    let handler = if cli.defers_migrations() {
+        CommandHandler::without_migrating(database_url)
+    } else {
+        CommandHandler::new(database_url)
+    };
+    let mut handler = match handler {
         Ok(handler) => handler,
         Err(e) => {
             eprintln!("Failed to initialize command handler: {}", e);
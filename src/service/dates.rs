@@ -0,0 +1,116 @@
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use super::errors::ServiceError;
+
+/// Parses a span such as `30d`, `12h`, `2w` or `45m`. Spans are always
+/// positive, so `--since 5d` can't point into the future.
+pub fn parse_duration(input: &str) -> Result<Duration, ServiceError> {
+    let input = input.trim();
+    let invalid = || {
+        ServiceError::InvalidInput(format!(
+            "Invalid duration '{}', expected a positive span such as 30d, 12h, 2w or 45m",
+            input
+        ))
+    };
+
+    let (split, _) = input.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = input.split_at(split);
+    if !amount.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    if amount == 0 {
+        return Err(invalid());
+    }
+
+    match unit {
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the lower bound of a date filter into a UTC timestamp, which is
+/// how they are stored. Accepts `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` (local
+/// time), `today`, `yesterday` or a span ago such as `7d`.
+pub fn parse_since(input: &str) -> Result<NaiveDateTime, ServiceError> {
+    parse_bound(input, false)
+}
+
+/// Parses the upper bound of a date filter. A bare date includes the whole
+/// day, so `--until 2024-11-04` covers everything up to midnight.
+pub fn parse_until(input: &str) -> Result<NaiveDateTime, ServiceError> {
+    parse_bound(input, true)
+}
+
+fn parse_bound(input: &str, end_of_day: bool) -> Result<NaiveDateTime, ServiceError> {
+    let input = input.trim();
+    let today = Local::now().date_naive();
+
+    let date = match input {
+        "today" => Some(today),
+        "yesterday" => today.pred_opt(),
+        _ => NaiveDate::parse_from_str(input, "%Y-%m-%d").ok(),
+    };
+
+    let local = match date {
+        Some(date) => {
+            let date = if end_of_day {
+                date.succ_opt()
+            } else {
+                Some(date)
+            };
+            date.and_then(|d| d.and_hms_opt(0, 0, 0))
+        }
+        None => NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M").ok(),
+    };
+
+    match local {
+        Some(local) => Local
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc).naive_utc())
+            .ok_or_else(|| ServiceError::InvalidInput(format!("Invalid date '{}'", input))),
+        None => parse_duration(input)
+            .map(|ago| Utc::now().naive_utc() - ago)
+            .map_err(|_| {
+                ServiceError::InvalidInput(format!(
+                    "Invalid date '{}', expected YYYY-MM-DD, 'YYYY-MM-DD HH:MM', today, \
+                     yesterday or a span such as 7d",
+                    input
+                ))
+            }),
+    }
+}
+
+/// Timestamps are stored in UTC; this shows them in local time.
+pub fn format_local(at: NaiveDateTime) -> String {
+    Utc.from_utc_datetime(&at)
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30d").unwrap(), Duration::days(30));
+        assert_eq!(parse_duration(" 2w ").unwrap(), Duration::weeks(2));
+        assert_eq!(parse_duration("45m").unwrap(), Duration::minutes(45));
+        for input in ["-5d", "+5d", "0d", "5", "d", "5y", ""] {
+            assert!(parse_duration(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn spans_ago_are_in_the_past() {
+        let now = Utc::now().naive_utc();
+        assert!(parse_since("7d").unwrap() < now);
+        assert!(parse_since("-7d").is_err());
+    }
+}
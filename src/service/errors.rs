@@ -5,6 +5,16 @@ use std::fmt;
 pub enum ServiceError {
     InvalidInput(String),
     OperationFailed(String),
+    NotFound {
+        entity: &'static str,
+        name: String,
+        suggestions: Vec<String>,
+    },
+    Ambiguous {
+        reference: String,
+        candidates: Vec<String>,
+    },
+    ProjectArchived(String),
     Database(DatabaseError),
 }
 
@@ -13,6 +23,33 @@ impl fmt::Display for ServiceError {
         match self {
             ServiceError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             ServiceError::OperationFailed(msg) => write!(f, "Operation failed: {}", msg),
+            ServiceError::NotFound {
+                entity,
+                name,
+                suggestions,
+            } => {
+                write!(f, "{} '{}' does not exist", entity, name)?;
+                if !suggestions.is_empty() {
+                    write!(f, ". Did you mean: {}?", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            ServiceError::Ambiguous {
+                reference,
+                candidates,
+            } => {
+                write!(f, "'{}' matches more than one item:", reference)?;
+                for candidate in candidates {
+                    write!(f, "\n  {}", candidate)?;
+                }
+                Ok(())
+            }
+            ServiceError::ProjectArchived(name) => write!(
+                f,
+                "Project '{}' is archived, run `doit unarchive project --project {}` first",
+                name,
+                shell_word(name)
+            ),
             ServiceError::Database(err) => write!(f, "Database error: {}", err),
         }
     }
@@ -32,3 +69,29 @@ impl From<DatabaseError> for ServiceError {
         ServiceError::Database(err)
     }
 }
+
+/// `text` as a single shell word, quoted only if it has to be.
+fn shell_word(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:@+".contains(c));
+    if plain {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archived_hint_quotes_names_that_need_it() {
+        let hint = |name: &str| ServiceError::ProjectArchived(name.to_string()).to_string();
+        assert!(hint("alpha").contains("`doit unarchive project --project alpha`"));
+        assert!(hint("My Project").contains("--project 'My Project'`"));
+        assert!(hint("Bob's").contains(r"--project 'Bob'\''s'`"));
+    }
+}
@@ -0,0 +1,18 @@
+//! Hashes that stay the same across builds and platforms, unlike `std`'s,
+//! for anything that gets stored or written out.
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// 64-bit FNV-1a of `text`, starting from `basis` so callers can derive
+/// independent hashes of the same text.
+pub fn fnv1a(text: &str, basis: u64) -> u64 {
+    text.bytes().fold(basis, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// A short hex digest of `text`.
+pub fn fingerprint(text: &str) -> String {
+    format!("{:016x}", fnv1a(text, FNV_OFFSET_BASIS))
+}
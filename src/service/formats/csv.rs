@@ -0,0 +1,404 @@
+//! Comma separated values, a task per row under a header row:
+//!
+//! ```text
+//! project,name,done,priority,due,tags
+//! backend,deploy,no,A,2024-11-08,work ops
+//! ```
+//!
+//! Importing reads each field from the column named after it, or the one
+//! given with `--map`, such as `name=Title,done=Status`, and ignores other
+//! columns. Exports leave out subtasks and thoughts.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, Utc};
+
+use crate::db::models::Dump;
+use crate::service::errors::ServiceError;
+
+/// What a column can be read into.
+pub const FIELDS: &[&str] = &["project", "name", "done", "priority", "due", "tags"];
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Which column each field is read from.
+#[derive(Debug, Default)]
+pub struct ColumnMap {
+    columns: BTreeMap<&'static str, String>,
+}
+
+impl ColumnMap {
+    /// Parses `field=Column,...`. Fields not given are read from a column
+    /// named after them, if there is one.
+    pub fn parse(spec: &str) -> Result<ColumnMap, ServiceError> {
+        let mut map = ColumnMap::default();
+        for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let Some((field, column)) = pair.split_once('=') else {
+                return Err(ServiceError::InvalidInput(format!(
+                    "'{}' isn't field=Column",
+                    pair
+                )));
+            };
+            let Some(&field) = FIELDS.iter().find(|f| f.eq_ignore_ascii_case(field.trim())) else {
+                return Err(ServiceError::InvalidInput(format!(
+                    "Unknown field '{}', expected one of {}",
+                    field.trim(),
+                    FIELDS.join(", ")
+                )));
+            };
+            map.columns.insert(field, column.trim().to_string());
+        }
+        Ok(map)
+    }
+}
+
+/// A row as read, or why it couldn't be. `line` is where it starts in the
+/// file, the header being line 1.
+#[derive(Debug)]
+pub struct Row {
+    pub line: u64,
+    pub values: Result<Values, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Values {
+    pub project: Option<String>,
+    pub name: String,
+    pub done: bool,
+    pub priority: Option<String>,
+    pub due_on: Option<NaiveDate>,
+    pub tags: Vec<String>,
+}
+
+/// Reads every row. A file that can't be read as CSV at all, or lacks a
+/// mapped column, is an error. Bad values only fail their own row.
+pub fn read(input: &str, map: &ColumnMap) -> Result<Vec<Row>, ServiceError> {
+    let invalid = |e: ::csv::Error| ServiceError::InvalidInput(format!("Not valid CSV: {}", e));
+    let mut reader = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(::csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let headers = reader.headers().map_err(invalid)?.clone();
+
+    let mut indexes: BTreeMap<&str, usize> = BTreeMap::new();
+    for &field in FIELDS {
+        let column = map.columns.get(field).map(String::as_str).unwrap_or(field);
+        match headers.iter().position(|h| h.eq_ignore_ascii_case(column)) {
+            Some(index) => {
+                indexes.insert(field, index);
+            }
+            None if map.columns.contains_key(field) => {
+                return Err(ServiceError::InvalidInput(format!(
+                    "No column '{}' in the header row",
+                    column
+                )))
+            }
+            None => {}
+        }
+    }
+    if !indexes.contains_key("name") {
+        return Err(ServiceError::InvalidInput(
+            "No 'name' column, pass --map name=<column>".to_string(),
+        ));
+    }
+
+    let mut rows = Vec::new();
+    let mut lines = LineCounter {
+        input,
+        offset: 0,
+        line: 1,
+    };
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let line = record
+            .position()
+            .map_or(0, |position| lines.line_at(position.byte() as usize));
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let get = |field: &str| {
+            indexes
+                .get(field)
+                .and_then(|&index| record.get(index))
+                .filter(|value| !value.is_empty())
+        };
+        rows.push(Row {
+            line,
+            values: parse_values(get),
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Line numbers for rows. The reader's own count falls behind on `\r\n`
+/// and blank lines, and it places a row at the line break before it, so
+/// lines are counted from byte offsets instead.
+struct LineCounter<'a> {
+    input: &'a str,
+    offset: usize,
+    line: u64,
+}
+
+impl LineCounter<'_> {
+    /// The line of the first row character at or after `offset`, which
+    /// mustn't be before the last one asked about.
+    fn line_at(&mut self, offset: usize) -> u64 {
+        let bytes = self.input.as_bytes();
+        let start = offset
+            + bytes[offset..]
+                .iter()
+                .take_while(|&&b| b == b'\r' || b == b'\n')
+                .count();
+        self.line += bytes[self.offset..start]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count() as u64;
+        self.offset = start;
+        self.line
+    }
+}
+
+fn parse_values<'a>(get: impl Fn(&str) -> Option<&'a str>) -> Result<Values, String> {
+    let done = match get("done") {
+        Some(value) => {
+            parse_done(value).ok_or_else(|| format!("can't tell whether '{}' means done", value))?
+        }
+        None => false,
+    };
+    let priority = get("priority").map(parse_priority).transpose()?;
+    let due_on = get("due")
+        .map(|value| {
+            NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .or_else(|_| NaiveDate::parse_from_str(value, "%Y/%m/%d"))
+                .map_err(|_| format!("invalid due date '{}', expected YYYY-MM-DD", value))
+        })
+        .transpose()?;
+
+    Ok(Values {
+        project: get("project").map(str::to_string),
+        name: get("name").unwrap_or_default().to_string(),
+        done,
+        priority,
+        due_on,
+        tags: get("tags")
+            .map(|tags| {
+                tags.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn parse_done(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "x" | "1" | "y" | "yes" | "true" | "done" | "completed" | "closed" => Some(true),
+        "0" | "n" | "no" | "false" | "todo" | "open" | "pending" | "not started"
+        | "in progress" => Some(false),
+        _ => None,
+    }
+}
+
+/// A letter, or high, medium and low as `A`, `B` and `C`.
+fn parse_priority(value: &str) -> Result<String, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "high" => Ok("A".to_string()),
+        "medium" => Ok("B".to_string()),
+        "low" => Ok("C".to_string()),
+        letter if letter.len() == 1 && letter.chars().all(|c| c.is_ascii_lowercase()) => {
+            Ok(letter.to_ascii_uppercase())
+        }
+        _ => Err(format!("invalid priority '{}'", value)),
+    }
+}
+
+/// Builds the rows into a dump, each with its project already settled.
+pub fn into_dump(values: Vec<Values>) -> Dump {
+    let now = Utc::now().naive_utc();
+    let mut dump = Dump::default();
+    for values in values {
+        let project_id = dump.project_named(values.project.as_deref().unwrap_or_default(), now);
+        let task = dump.push_task(project_id, values.name, values.done, now);
+        task.priority = values.priority;
+        task.due_on = values.due_on;
+        task.tags = values.tags.join(" ");
+    }
+    dump
+}
+
+pub fn encode(dump: &Dump) -> Result<String, ServiceError> {
+    let rows = dump.tasks.iter().filter_map(|task| {
+        let project = dump.projects.iter().find(|p| p.id == task.project_id)?;
+        Some(vec![
+            project.name.clone(),
+            task.name.clone(),
+            yes_no(task.completed),
+            task.priority.clone().unwrap_or_default(),
+            task.due_on
+                .map(|due_on| due_on.format(DATE_FORMAT).to_string())
+                .unwrap_or_default(),
+            task.tags().collect::<Vec<_>>().join(" "),
+            task.short_id.clone(),
+        ])
+    });
+
+    write(
+        &["project", "name", "done", "priority", "due", "tags", "id"],
+        rows,
+    )
+}
+
+/// Writes a header and rows, for exports and listings alike.
+pub fn write(
+    headers: &[&str],
+    rows: impl IntoIterator<Item = Vec<String>>,
+) -> Result<String, ServiceError> {
+    let failed =
+        |e: ::csv::Error| ServiceError::OperationFailed(format!("Failed to write CSV: {}", e));
+    let mut writer = ::csv::Writer::from_writer(Vec::new());
+    writer.write_record(headers).map_err(failed)?;
+    for row in rows {
+        writer.write_record(&row).map_err(failed)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| ServiceError::OperationFailed(format!("Failed to write CSV: {}", e)))?;
+
+    Ok(String::from_utf8_lossy(&bytes).trim_end().to_string())
+}
+
+pub fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(row: &Row) -> &Values {
+        row.values.as_ref().unwrap()
+    }
+
+    #[test]
+    fn quoting_round_trip() {
+        let now = Utc::now().naive_utc();
+        let mut dump = Dump::default();
+        let project_id = dump.push_project("ops, infra".to_string(), now);
+        let task = dump.push_task(project_id, "say \"hi\",\nthen leave".to_string(), true, now);
+        task.priority = Some("B".to_string());
+        task.due_on = NaiveDate::from_ymd_opt(2024, 11, 8);
+        task.tags = "work ops".to_string();
+        task.short_id = "t-4f2a".to_string();
+
+        let text = encode(&dump).unwrap();
+        assert_eq!(
+            text,
+            "project,name,done,priority,due,tags,id\n\
+             \"ops, infra\",\"say \"\"hi\"\",\nthen leave\",yes,B,2024-11-08,work ops,t-4f2a"
+        );
+
+        let rows = read(&text, &ColumnMap::default()).unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = values(&rows[0]);
+        assert_eq!(row.project.as_deref(), Some("ops, infra"));
+        assert_eq!(row.name, "say \"hi\",\nthen leave");
+        assert!(row.done);
+        assert_eq!(row.priority.as_deref(), Some("B"));
+        assert_eq!(row.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        assert_eq!(row.tags, ["work", "ops"]);
+    }
+
+    #[test]
+    fn mapped_columns_and_bad_rows() {
+        let input = "\
+Title,Status,Importance,Deadline,Labels,Notes
+\"multi
+line\",Completed,high,2024/11/08,\"a, b c\",ignored
+
+deploy,maybe,,,,
+build,open,urgent,,,
+test,,,tomorrow,,
+";
+        let map = ColumnMap::parse(
+            "name=Title, DONE=status,priority=Importance,due=Deadline,tags=Labels",
+        )
+        .unwrap();
+        let rows = read(input, &map).unwrap();
+
+        let lines: Vec<u64> = rows.iter().map(|row| row.line).collect();
+        // a quoted value spanning lines starts on the line it opens, and
+        // blank rows are skipped
+        assert_eq!(lines, [2, 5, 6, 7]);
+        let first = values(&rows[0]);
+        assert_eq!(first.name, "multi\nline");
+        assert!(first.done);
+        assert_eq!(first.priority.as_deref(), Some("A"));
+        assert_eq!(first.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        assert_eq!(first.tags, ["a", "b", "c"]);
+        assert_eq!(first.project, None);
+
+        let errors: Vec<&str> = rows[1..]
+            .iter()
+            .map(|row| row.values.as_ref().unwrap_err().as_str())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "can't tell whether 'maybe' means done",
+                "invalid priority 'urgent'",
+                "invalid due date 'tomorrow', expected YYYY-MM-DD",
+            ]
+        );
+    }
+
+    #[test]
+    fn line_numbers_count_every_line() {
+        let rows = read("name\r\na\r\n\r\n\"b\r\nc\"\n\n\nd", &ColumnMap::default()).unwrap();
+
+        let lines: Vec<u64> = rows.iter().map(|row| row.line).collect();
+        assert_eq!(lines, [2, 4, 8]);
+    }
+
+    #[test]
+    fn column_maps() {
+        let err = ColumnMap::parse("name").unwrap_err();
+        assert!(err.to_string().contains("'name' isn't field=Column"));
+        let err = ColumnMap::parse("title=Name").unwrap_err();
+        assert!(err.to_string().contains("Unknown field 'title'"));
+
+        let map = ColumnMap::parse("due=When").unwrap();
+        let err = read("name,due\ndeploy,2024-11-08", &map).unwrap_err();
+        assert!(err.to_string().contains("No column 'When'"));
+        let err = read("title\ndeploy", &ColumnMap::default()).unwrap_err();
+        assert!(err.to_string().contains("No 'name' column"));
+    }
+
+    #[test]
+    fn values_in_other_words() {
+        for done in ["x", "Yes", "TRUE", "closed"] {
+            assert_eq!(parse_done(done), Some(true), "{}", done);
+        }
+        for open in ["0", "No", "not started", "In Progress"] {
+            assert_eq!(parse_done(open), Some(false), "{}", open);
+        }
+        assert_eq!(parse_priority("c"), Ok("C".to_string()));
+        assert_eq!(parse_priority("Medium"), Ok("B".to_string()));
+        assert!(parse_priority("AA").is_err());
+    }
+
+    #[test]
+    fn rows_go_into_their_projects() {
+        let rows = read(
+            "project,name\nbackend,deploy\ninfra,build\nbackend,test",
+            &ColumnMap::default(),
+        )
+        .unwrap();
+        let dump = into_dump(rows.iter().map(|row| values(row).clone()).collect());
+
+        let projects: Vec<&str> = dump.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(projects, ["backend", "infra"]);
+        let tasks: Vec<i32> = dump.tasks.iter().map(|t| t.project_id).collect();
+        assert_eq!(tasks, [1, 2, 1]);
+    }
+}
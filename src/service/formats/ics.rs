@@ -0,0 +1,556 @@
+//! iCalendar ([RFC 5545](https://www.rfc-editor.org/rfc/rfc5545)) to-dos,
+//! one `VTODO` per task and subtask:
+//!
+//! ```text
+//! BEGIN:VTODO
+//! UID:t-4f2a@doit
+//! SUMMARY:deploy
+//! STATUS:NEEDS-ACTION
+//! DUE;VALUE=DATE:20241108
+//! PRIORITY:1
+//! CATEGORIES:work,ops
+//! X-DOIT-PROJECT:backend
+//! END:VTODO
+//! ```
+//!
+//! The `UID` is kept in [`EXTERNAL_ID_KEY`] metadata so importing again
+//! updates the task. Subtasks point at their task with `RELATED-TO`.
+//! Priorities `A`/`B`/`C` are written as 1, 5 and 9, and read back by the
+//! RFC's high, medium and low ranges. Other components, such as events,
+//! are ignored.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+use crate::db::models::{Dump, EXTERNAL_ID_KEY};
+use crate::service::errors::ServiceError;
+
+const DATE_FORMAT: &str = "%Y%m%d";
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const PROJECT: &str = "X-DOIT-PROJECT";
+const METADATA: &str = "X-DOIT-METADATA";
+/// Lines longer than this many bytes are folded.
+const LINE_LIMIT: usize = 75;
+
+pub fn encode(dump: &Dump) -> String {
+    let now = format_date_time(Utc::now().naive_utc());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//doit//doit//EN".to_string(),
+    ];
+    if let [project] = dump.projects.as_slice() {
+        lines.push(format!("X-WR-CALNAME:{}", escape(&project.name)));
+    }
+
+    for task in &dump.tasks {
+        let Some(project) = dump.projects.iter().find(|p| p.id == task.project_id) else {
+            continue;
+        };
+
+        let mut metadata = task.metadata();
+        let uid = metadata
+            .remove(EXTERNAL_ID_KEY)
+            .unwrap_or_else(|| own_uid(&task.short_id));
+
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", escape(&uid)));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("CREATED:{}", format_date_time(task.created_on)));
+        if let Some(updated_on) = task.updated_on {
+            lines.push(format!("LAST-MODIFIED:{}", format_date_time(updated_on)));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&task.name)));
+        push_status(&mut lines, task.completed, task.completed_on);
+        if let Some(due_on) = task.due_on {
+            lines.push(format!("DUE;VALUE=DATE:{}", due_on.format(DATE_FORMAT)));
+        }
+        if let Some(priority) = &task.priority {
+            lines.push(format!("PRIORITY:{}", to_ics_priority(priority)));
+        }
+        let tags: Vec<String> = task.tags().map(escape).collect();
+        if !tags.is_empty() {
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        lines.push(format!("{}:{}", PROJECT, escape(&project.name)));
+        if !metadata.is_empty() {
+            let json = serde_json::to_string(&metadata).unwrap_or_default();
+            lines.push(format!("{}:{}", METADATA, escape(&json)));
+        }
+        lines.push("END:VTODO".to_string());
+
+        for subtask in dump.subtasks.iter().filter(|s| s.task_id == task.id) {
+            lines.push("BEGIN:VTODO".to_string());
+            lines.push(format!("UID:{}", own_uid(&subtask.short_id)));
+            lines.push(format!("DTSTAMP:{}", now));
+            lines.push(format!("CREATED:{}", format_date_time(subtask.created_on)));
+            lines.push(format!("SUMMARY:{}", escape(&subtask.name)));
+            push_status(&mut lines, subtask.completed, subtask.completed_on);
+            lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", escape(&uid)));
+            lines.push(format!("{}:{}", PROJECT, escape(&project.name)));
+            lines.push("END:VTODO".to_string());
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// Parses the to-dos of a calendar. Those without a doit project go to
+/// `project`, or else a project named after the calendar, and are an error
+/// if neither is there. Cancelled ones are skipped.
+pub fn decode(input: &str, project: Option<&str>) -> Result<Dump, ServiceError> {
+    let now = Utc::now().naive_utc();
+    let (calendar_name, todos) = parse(input)?;
+    let todos: Vec<Todo> = todos
+        .into_iter()
+        .filter(|todo| todo.get("STATUS") != Some("CANCELLED"))
+        .collect();
+
+    let by_uid: HashMap<&str, &Todo> = todos
+        .iter()
+        .filter_map(|todo| Some((todo.get("UID")?, todo)))
+        .collect();
+
+    let mut dump = Dump::default();
+    let mut task_ids: HashMap<&str, i32> = HashMap::new();
+
+    for todo in todos
+        .iter()
+        .filter(|todo| parent_of(todo, &by_uid).is_none())
+    {
+        let invalid =
+            |msg: &str| ServiceError::InvalidInput(format!("line {}: {}", todo.line, msg));
+        let name = todo.get("SUMMARY").unwrap_or_default().trim().to_string();
+        if name.is_empty() {
+            return Err(invalid("to-do without a summary"));
+        }
+        let project_name = todo
+            .get(PROJECT)
+            .or(project)
+            .or(calendar_name.as_deref())
+            .ok_or_else(|| invalid("no project, pass --project"))?
+            .to_string();
+        let project_id = dump.project_named(&project_name, now);
+
+        let mut metadata: BTreeMap<String, String> = match todo.get(METADATA) {
+            Some(json) => serde_json::from_str(json)
+                .map_err(|_| invalid(&format!("{} isn't a JSON object", METADATA)))?,
+            None => BTreeMap::new(),
+        };
+        let uid = todo.get("UID");
+        if let Some(uid) = uid {
+            metadata.insert(EXTERNAL_ID_KEY.to_string(), uid.to_string());
+        }
+
+        let completed = todo.get("STATUS") == Some("COMPLETED");
+        let task = dump.push_task(project_id, name, completed, now);
+        task.created_on = todo.date_time("CREATED")?.unwrap_or(now);
+        task.updated_on = todo.date_time("LAST-MODIFIED")?;
+        task.completed_on = match completed {
+            true => Some(todo.date_time("COMPLETED")?.unwrap_or(now)),
+            false => None,
+        };
+        task.short_id = uid.and_then(short_id_of).unwrap_or_default().to_string();
+        task.due_on = todo.date_time("DUE")?.map(|due| due.date());
+        task.priority = match todo.get("PRIORITY") {
+            Some(priority) => from_ics_priority(priority)
+                .ok_or_else(|| invalid(&format!("invalid priority '{}'", priority)))?,
+            None => None,
+        };
+        task.tags = todo
+            .get("CATEGORIES")
+            .map(|categories| {
+                split_list(categories)
+                    .iter()
+                    .map(|tag| tag.trim().replace(' ', "-"))
+                    .filter(|tag| !tag.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+        task.metadata = serde_json::to_string(&metadata)
+            .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+        let task_id = task.id;
+        if let Some(uid) = uid {
+            task_ids.insert(uid, task_id);
+        }
+    }
+
+    for todo in &todos {
+        let Some(parent) = parent_of(todo, &by_uid) else {
+            continue;
+        };
+        let name = todo.get("SUMMARY").unwrap_or_default().trim().to_string();
+        if name.is_empty() {
+            return Err(ServiceError::InvalidInput(format!(
+                "line {}: to-do without a summary",
+                todo.line
+            )));
+        }
+        let completed = todo.get("STATUS") == Some("COMPLETED");
+        let subtask = dump.push_subtask(task_ids[parent], name, completed, now);
+        subtask.created_on = todo.date_time("CREATED")?.unwrap_or(now);
+        subtask.completed_on = match completed {
+            true => Some(todo.date_time("COMPLETED")?.unwrap_or(now)),
+            false => None,
+        };
+        subtask.short_id = todo
+            .get("UID")
+            .and_then(short_id_of)
+            .unwrap_or_default()
+            .to_string();
+    }
+
+    Ok(dump)
+}
+
+/// The `UID` of a to-do's parent if it's a subtask, which it is if its
+/// parent is a to-do without a parent.
+fn parent_of<'a>(todo: &'a Todo, by_uid: &HashMap<&str, &Todo>) -> Option<&'a str> {
+    todo.get("RELATED-TO").filter(|uid| {
+        by_uid
+            .get(uid)
+            .is_some_and(|p| p.get("RELATED-TO").is_none())
+    })
+}
+
+/// A `VTODO`'s properties, unescaped, with parameters dropped.
+struct Todo {
+    line: usize,
+    properties: HashMap<String, String>,
+}
+
+impl Todo {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    /// Reads a date, a UTC or floating date-time, or one with a `TZID`,
+    /// whose zone is ignored.
+    fn date_time(&self, name: &str) -> Result<Option<NaiveDateTime>, ServiceError> {
+        let Some(text) = self.get(name) else {
+            return Ok(None);
+        };
+        NaiveDateTime::parse_from_str(text.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+            .or_else(|_| {
+                NaiveDate::parse_from_str(text, DATE_FORMAT)
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            })
+            .map(Some)
+            .map_err(|_| {
+                ServiceError::InvalidInput(format!(
+                    "line {}: invalid {} '{}'",
+                    self.line, name, text
+                ))
+            })
+    }
+}
+
+/// Unfolds the calendar and collects its to-dos, with the calendar's name
+/// if it has one.
+fn parse(input: &str) -> Result<(Option<String>, Vec<Todo>), ServiceError> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((index + 1, line.to_string())),
+        }
+    }
+
+    if !lines
+        .iter()
+        .find(|(_, line)| !line.trim().is_empty())
+        .is_some_and(|(_, line)| line.eq_ignore_ascii_case("BEGIN:VCALENDAR"))
+    {
+        return Err(ServiceError::InvalidInput(
+            "Not an iCalendar file, it doesn't start with BEGIN:VCALENDAR".to_string(),
+        ));
+    }
+
+    let mut calendar_name = None;
+    let mut todos = Vec::new();
+    let mut current: Option<Todo> = None;
+    // components nested in a to-do, such as alarms
+    let mut depth = 0;
+
+    for (number, line) in lines {
+        let Some((name, value)) = split_property(&line) else {
+            continue;
+        };
+        match (name.as_str(), value, current.as_mut()) {
+            ("BEGIN", "VTODO", None) => {
+                current = Some(Todo {
+                    line: number,
+                    properties: HashMap::new(),
+                })
+            }
+            ("BEGIN", _, Some(_)) => depth += 1,
+            ("END", _, Some(_)) if depth > 0 => depth -= 1,
+            ("END", "VTODO", Some(_)) => todos.extend(current.take()),
+            ("X-WR-CALNAME", value, None) => calendar_name = Some(unescape(value)),
+            (name, value, Some(todo)) if depth == 0 => {
+                todo.properties
+                    .entry(name.to_string())
+                    .or_insert_with(|| match name {
+                        // lists are unescaped item by item
+                        "CATEGORIES" => value.to_string(),
+                        _ => unescape(value),
+                    });
+            }
+            _ => {}
+        }
+    }
+
+    Ok((calendar_name, todos))
+}
+
+/// Splits `NAME;PARAM=x:value` into the upper-cased name and the value.
+fn split_property(line: &str) -> Option<(String, &str)> {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                let name = line[..index].split(';').next()?;
+                return Some((name.to_ascii_uppercase(), &line[index + 1..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    items.last_mut().unwrap().push(next);
+                }
+            }
+            ',' => items.push(String::new()),
+            c => items.last_mut().unwrap().push(c),
+        }
+    }
+    items
+}
+
+fn push_status(lines: &mut Vec<String>, completed: bool, completed_on: Option<NaiveDateTime>) {
+    if completed {
+        lines.push("STATUS:COMPLETED".to_string());
+        if let Some(completed_on) = completed_on {
+            lines.push(format!("COMPLETED:{}", format_date_time(completed_on)));
+        }
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_string());
+    }
+}
+
+/// The `UID` of a row that didn't come from another tool.
+fn own_uid(short_id: &str) -> String {
+    format!("{}@doit", short_id)
+}
+
+fn short_id_of(uid: &str) -> Option<&str> {
+    uid.strip_suffix("@doit")
+}
+
+fn to_ics_priority(priority: &str) -> u8 {
+    match priority {
+        "A" => 1,
+        "B" => 5,
+        _ => 9,
+    }
+}
+
+/// 0 is undefined, 1-4 high, 5 medium and 6-9 low.
+fn from_ics_priority(priority: &str) -> Option<Option<String>> {
+    let priority = match priority.trim().parse::<u8>().ok()? {
+        0 => return Some(None),
+        1..=4 => "A",
+        5 => "B",
+        6..=9 => "C",
+        _ => return None,
+    };
+    Some(Some(priority.to_string()))
+}
+
+fn format_date_time(at: NaiveDateTime) -> String {
+    at.format(DATE_TIME_FORMAT).to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(next) => unescaped.push(next),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Folds a content line into lines of at most [`LINE_LIMIT`] bytes, never
+/// splitting a character.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > LINE_LIMIT {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lines_are_folded_and_unfolded() {
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let folded = fold(&line);
+
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert!(parts.len() > 1);
+        assert!(parts.iter().all(|part| part.len() <= LINE_LIMIT));
+        assert!(parts[1..].iter().all(|part| part.starts_with(' ')));
+        // nothing but the fold is added, and no character is split
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short");
+    }
+
+    #[test]
+    fn round_trip() {
+        let now = Utc::now().naive_utc();
+        let mut dump = Dump::default();
+        let project_id = dump.push_project("backend; ops".to_string(), now);
+        let task = dump.push_task(
+            project_id,
+            format!("deploy, then {}", "wait ".repeat(20))
+                .trim_end()
+                .to_string(),
+            false,
+            now,
+        );
+        task.short_id = "t-4f2a".to_string();
+        task.priority = Some("B".to_string());
+        task.due_on = NaiveDate::from_ymd_opt(2024, 11, 8);
+        task.tags = "work a,b".to_string();
+        task.metadata = r#"{"note":"line one\nline two \\ back"}"#.to_string();
+        let task_id = task.id;
+        let subtask = dump.push_subtask(task_id, "tag release".to_string(), true, now);
+        subtask.short_id = "s-9c01".to_string();
+
+        let text = encode(&dump);
+        assert!(text.contains("X-WR-CALNAME:backend\\; ops\r\n"));
+        assert!(text.contains("RELATED-TO;RELTYPE=PARENT:t-4f2a@doit\r\n"));
+
+        let decoded = decode(&text, None).unwrap();
+        assert_eq!(decoded.projects[0].name, "backend; ops");
+        let task = &decoded.tasks[0];
+        assert_eq!(task.name, dump.tasks[0].name);
+        assert_eq!(task.short_id, "t-4f2a");
+        assert_eq!(task.priority.as_deref(), Some("B"));
+        assert_eq!(task.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        assert_eq!(task.tags, "work a,b");
+        let metadata = task.metadata();
+        assert_eq!(metadata["note"], "line one\nline two \\ back");
+        assert_eq!(metadata[EXTERNAL_ID_KEY], "t-4f2a@doit");
+        let subtask = &decoded.subtasks[0];
+        assert_eq!(subtask.short_id, "s-9c01");
+        assert_eq!(subtask.task_id, task.id);
+        assert!(subtask.completed);
+    }
+
+    #[test]
+    fn calendars_from_other_tools() {
+        let input = "\
+BEGIN:VCALENDAR\r
+X-WR-CALNAME:Errands\r
+BEGIN:VEVENT\r
+UID:event-1\r
+SUMMARY:not a to-do\r
+END:VEVENT\r
+BEGIN:VTODO\r
+UID:abc-123\r
+summary;LANGUAGE=en:buy milk\r
+  and bread\r
+DUE;TZID=Europe/Berlin:20241108T170000\r
+PRIORITY:3\r
+CATEGORIES:shop,home\\, garden\r
+BEGIN:VALARM\r
+SUMMARY:alarm\r
+END:VALARM\r
+END:VTODO\r
+BEGIN:VTODO\r
+UID:abc-124\r
+SUMMARY:call mom\r
+STATUS:CANCELLED\r
+END:VTODO\r
+END:VCALENDAR\r
+";
+        let dump = decode(input, None).unwrap();
+
+        assert_eq!(dump.projects[0].name, "Errands");
+        assert_eq!(dump.tasks.len(), 1);
+        let task = &dump.tasks[0];
+        assert_eq!(task.name, "buy milk and bread");
+        assert_eq!(task.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        assert_eq!(task.priority.as_deref(), Some("A"));
+        // a tag can't hold a space
+        assert_eq!(task.tags, "shop home,-garden");
+        assert_eq!(task.short_id, "");
+        assert_eq!(task.metadata()[EXTERNAL_ID_KEY], "abc-123");
+
+        let dump = decode(input, Some("home")).unwrap();
+        assert_eq!(dump.projects[0].name, "home");
+    }
+
+    #[test]
+    fn invalid_calendars() {
+        let err = decode("BEGIN:VTODO\nEND:VTODO", None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("doesn't start with BEGIN:VCALENDAR"));
+
+        let todo = |property: &str| {
+            format!(
+                "BEGIN:VCALENDAR\nBEGIN:VTODO\nSUMMARY:x\n{}\nEND:VTODO\nEND:VCALENDAR",
+                property
+            )
+        };
+        assert!(decode(&todo("X-DOIT-PROJECT:p"), None).is_ok());
+        let err = decode(&todo("UID:a"), None).unwrap_err();
+        assert!(err.to_string().contains("line 2: no project"));
+        let err = decode(&todo("PRIORITY:10"), Some("p")).unwrap_err();
+        assert!(err.to_string().contains("invalid priority '10'"));
+        let err = decode(&todo("DUE:tomorrow"), Some("p")).unwrap_err();
+        assert!(err.to_string().contains("invalid DUE 'tomorrow'"));
+    }
+}
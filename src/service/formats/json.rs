@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::Dump;
+use crate::service::errors::ServiceError;
+
+/// Identifies a doit export among other JSON files.
+const FORMAT: &str = "doit";
+
+/// Bumped whenever the document changes in a way older builds can't read.
+/// Older documents are still accepted.
+///
+/// 2: tasks gained priority, due_on, tags and metadata
+/// 3: thoughts gained tags
+pub const VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    format: String,
+    version: u32,
+    exported_on: NaiveDateTime,
+    #[serde(flatten)]
+    dump: Dump,
+}
+
+pub fn encode(dump: Dump) -> Result<String, ServiceError> {
+    let document = Document {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        exported_on: Utc::now().naive_utc(),
+        dump,
+    };
+
+    serde_json::to_string_pretty(&document)
+        .map_err(|e| ServiceError::OperationFailed(format!("Failed to write JSON: {}", e)))
+}
+
+/// Parses a document written by [`encode`], checking its version and that
+/// every task and subtask points at a parent within it.
+pub fn decode(input: &str) -> Result<Dump, ServiceError> {
+    if input.trim_start().starts_with('[') {
+        return Err(ServiceError::InvalidInput(
+            "Not a doit export, for a Taskwarrior one pass --format taskwarrior".to_string(),
+        ));
+    }
+    let document: Document = serde_json::from_str(input)
+        .map_err(|e| ServiceError::InvalidInput(format!("Not a doit export: {}", e)))?;
+
+    if document.format != FORMAT {
+        return Err(ServiceError::InvalidInput(format!(
+            "Not a doit export, its format is '{}'",
+            document.format
+        )));
+    }
+    if document.version > VERSION {
+        return Err(ServiceError::InvalidInput(format!(
+            "Export has version {}, which is newer than this version of doit",
+            document.version
+        )));
+    }
+
+    validate(&document.dump)?;
+
+    Ok(document.dump)
+}
+
+fn validate(dump: &Dump) -> Result<(), ServiceError> {
+    let invalid = |msg: String| Err(ServiceError::InvalidInput(msg));
+
+    let project_ids: HashSet<i32> = dump.projects.iter().map(|p| p.id).collect();
+    let task_ids: HashSet<i32> = dump.tasks.iter().map(|t| t.id).collect();
+
+    if let Some(project) = dump.projects.iter().find(|p| p.name.trim().is_empty()) {
+        return invalid(format!("Project {} has an empty name", project.id));
+    }
+    for task in &dump.tasks {
+        if task.name.trim().is_empty() {
+            return invalid(format!("Task {} has an empty name", task.id));
+        }
+        if !project_ids.contains(&task.project_id) {
+            return invalid(format!(
+                "Task '{}' belongs to project {}, which isn't in the export",
+                task.name, task.project_id
+            ));
+        }
+    }
+    for subtask in &dump.subtasks {
+        if subtask.name.trim().is_empty() {
+            return invalid(format!("Subtask {} has an empty name", subtask.id));
+        }
+        if !task_ids.contains(&subtask.task_id) {
+            return invalid(format!(
+                "Subtask '{}' belongs to task {}, which isn't in the export",
+                subtask.name, subtask.task_id
+            ));
+        }
+    }
+    if let Some(thought) = dump.thoughts.iter().find(|t| t.heading.trim().is_empty()) {
+        return invalid(format!("Thought {} has an empty heading", thought.id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump() -> Dump {
+        let now = Utc::now().naive_utc();
+        let mut dump = Dump::default();
+        let project_id = dump.push_project("backend".to_string(), now);
+        let task = dump.push_task(project_id, "deploy".to_string(), false, now);
+        task.tags = "release".to_string();
+        task.metadata = r#"{"owner":"ana"}"#.to_string();
+        let task_id = task.id;
+        dump.push_subtask(task_id, "tag release".to_string(), true, now);
+        dump
+    }
+
+    #[test]
+    fn round_trip() {
+        let decoded = decode(&encode(dump()).unwrap()).unwrap();
+
+        assert_eq!(decoded.projects[0].name, "backend");
+        assert_eq!(decoded.tasks[0].name, "deploy");
+        assert_eq!(decoded.tasks[0].tags, "release");
+        assert_eq!(decoded.tasks[0].metadata()["owner"], "ana");
+        assert_eq!(decoded.subtasks[0].task_id, decoded.tasks[0].id);
+        assert!(decoded.subtasks[0].completed);
+    }
+
+    #[test]
+    fn older_versions_are_read_and_newer_ones_refused() {
+        let document = encode(dump()).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&document).unwrap();
+
+        // version 1 had no task priority, due date, tags or metadata
+        value["version"] = 1.into();
+        for task in value["tasks"].as_array_mut().unwrap() {
+            let task = task.as_object_mut().unwrap();
+            for field in ["priority", "due_on", "tags", "metadata"] {
+                task.remove(field);
+            }
+        }
+        let decoded = decode(&value.to_string()).unwrap();
+        assert_eq!(decoded.tasks[0].tags, "");
+        assert!(decoded.tasks[0].metadata().is_empty());
+
+        value["version"] = (VERSION + 1).into();
+        let err = decode(&value.to_string()).unwrap_err();
+        assert!(err.to_string().contains("newer than this version"));
+    }
+
+    #[test]
+    fn other_documents_are_refused() {
+        let err = decode(r#"[{"uuid": "x"}]"#).unwrap_err();
+        assert!(err.to_string().contains("--format taskwarrior"));
+
+        let err =
+            decode(r#"{"format": "other", "version": 1, "exported_on": "2024-11-01T00:00:00"}"#)
+                .unwrap_err();
+        assert!(err.to_string().contains("its format is 'other'"));
+
+        let err = decode("{").unwrap_err();
+        assert!(err.to_string().contains("Not a doit export: "));
+    }
+
+    #[test]
+    fn rows_must_point_at_parents_in_the_export() {
+        let mut orphaned_task = dump();
+        orphaned_task.tasks[0].project_id = 7;
+        let err = decode(&encode(orphaned_task).unwrap()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("project 7, which isn't in the export"));
+
+        let mut orphaned_subtask = dump();
+        orphaned_subtask.subtasks[0].task_id = 7;
+        let err = decode(&encode(orphaned_subtask).unwrap()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("task 7, which isn't in the export"));
+
+        let mut unnamed = dump();
+        unnamed.tasks[0].name = " ".to_string();
+        let err = decode(&encode(unnamed).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("Task 1 has an empty name"));
+    }
+}
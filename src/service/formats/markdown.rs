@@ -0,0 +1,273 @@
+//! GitHub-style checklists, one `# Project` section each:
+//!
+//! ```text
+//! # backend
+//!
+//! - [ ] deploy
+//!   - [x] tag release
+//! ```
+//!
+//! Thoughts follow under `# Thoughts` as `## heading` sections. Importing
+//! only reads the checklists back.
+
+use chrono::Utc;
+
+use crate::db::models::Dump;
+use crate::service::errors::ServiceError;
+
+const THOUGHTS_HEADING: &str = "Thoughts";
+
+pub fn encode(dump: &Dump) -> String {
+    let mut sections = Vec::new();
+
+    for project in &dump.projects {
+        let mut lines = vec![format!("# {}", project.name), String::new()];
+        for task in dump.tasks.iter().filter(|t| t.project_id == project.id) {
+            lines.push(format!("- [{}] {}", mark(task.completed), task.name));
+            for subtask in dump.subtasks.iter().filter(|s| s.task_id == task.id) {
+                lines.push(format!(
+                    "  - [{}] {}",
+                    mark(subtask.completed),
+                    subtask.name
+                ));
+            }
+        }
+        sections.push(lines.join("\n"));
+    }
+
+    if !dump.thoughts.is_empty() {
+        let mut lines = vec![format!("# {}", THOUGHTS_HEADING)];
+        for thought in &dump.thoughts {
+            lines.push(String::new());
+            lines.push(format!("## {}", thought.heading));
+            if !thought.note.trim().is_empty() {
+                lines.push(String::new());
+                lines.push(thought.note.trim_end().to_string());
+            }
+        }
+        sections.push(lines.join("\n"));
+    }
+
+    sections.join("\n\n")
+}
+
+/// Reads checklist items into tasks, and items indented below them into
+/// subtasks. Each `# heading` names the project of the items under it,
+/// unless `project` is given, in which case everything goes there.
+/// Headings without any items are ignored, as are the notes of `# Thoughts`
+/// and anything that isn't a heading or a checklist item.
+pub fn decode(input: &str, project: Option<&str>) -> Result<Dump, ServiceError> {
+    let now = Utc::now().naive_utc();
+    let mut dump = Dump::default();
+    let mut heading: Option<String> = project.map(str::to_string);
+    let mut project_id: Option<i32> = None;
+    let mut task_id: Option<i32> = None;
+    let mut in_thoughts = false;
+    // within `# Thoughts`, from its first `## heading` on
+    let mut in_notes = false;
+
+    for (index, line) in input.lines().enumerate() {
+        let invalid = |msg: &str| {
+            Err(ServiceError::InvalidInput(format!(
+                "line {}: {}",
+                index + 1,
+                msg
+            )))
+        };
+
+        if let Some(name) = line.strip_prefix("# ") {
+            in_thoughts = name.trim() == THOUGHTS_HEADING;
+            in_notes = false;
+            if project.is_none() {
+                heading = Some(name.trim().to_string());
+                project_id = None;
+                task_id = None;
+            }
+            continue;
+        }
+        in_notes |= in_thoughts && line.starts_with("## ");
+        if in_notes {
+            continue;
+        }
+
+        let Some((indented, completed, name)) = parse_item(line) else {
+            continue;
+        };
+        if name.is_empty() {
+            return invalid("checklist item without a name");
+        }
+
+        if indented {
+            let Some(task_id) = task_id else {
+                return invalid("indented item without a task above it");
+            };
+            dump.push_subtask(task_id, name, completed, now);
+            continue;
+        }
+
+        let current = match project_id {
+            Some(id) => id,
+            None => {
+                let Some(name) = heading.clone() else {
+                    return invalid("item outside a `# Project` section, pass --project");
+                };
+                let id = dump.project_named(&name, now);
+                project_id = Some(id);
+                id
+            }
+        };
+        task_id = Some(dump.push_task(current, name, completed, now).id);
+    }
+
+    Ok(dump)
+}
+
+/// Splits `- [x] name` into whether it's indented, whether it's checked
+/// and the name.
+fn parse_item(line: &str) -> Option<(bool, bool, String)> {
+    let trimmed = line.trim_start();
+    let indented = trimmed.len() < line.len();
+    let rest = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+        .or_else(|| trimmed.strip_prefix("+ "))?;
+
+    let (completed, name) = match rest.get(..3)? {
+        "[ ]" => (false, &rest[3..]),
+        "[x]" | "[X]" => (true, &rest[3..]),
+        _ => return None,
+    };
+
+    Some((indented, completed, name.trim().to_string()))
+}
+
+fn mark(completed: bool) -> char {
+    if completed {
+        'x'
+    } else {
+        ' '
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::models::Thoughts;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let now = Utc::now().naive_utc();
+        let mut dump = Dump::default();
+        let backend = dump.push_project("backend".to_string(), now);
+        let deploy = dump.push_task(backend, "deploy".to_string(), false, now).id;
+        dump.push_subtask(deploy, "tag release".to_string(), true, now);
+        let infra = dump.push_project("infra".to_string(), now);
+        dump.push_task(infra, "build".to_string(), true, now);
+        dump.thoughts.push(Thoughts {
+            id: 1,
+            heading: "retro".to_string(),
+            note: "- [ ] not a task\n".to_string(),
+            created_on: now,
+            short_id: "n-1a2b".to_string(),
+            updated_on: None,
+            tags: String::new(),
+        });
+
+        let text = encode(&dump);
+        assert_eq!(
+            text,
+            "# backend\n\n- [ ] deploy\n  - [x] tag release\n\n\
+             # infra\n\n- [x] build\n\n\
+             # Thoughts\n\n## retro\n\n- [ ] not a task"
+        );
+
+        let decoded = decode(&text, None).unwrap();
+        let projects: Vec<&str> = decoded.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(projects, ["backend", "infra"]);
+        let tasks: Vec<(&str, bool)> = decoded
+            .tasks
+            .iter()
+            .map(|t| (t.name.as_str(), t.completed))
+            .collect();
+        assert_eq!(tasks, [("deploy", false), ("build", true)]);
+        assert_eq!(decoded.subtasks.len(), 1);
+        assert_eq!(decoded.subtasks[0].name, "tag release");
+        assert_eq!(decoded.subtasks[0].task_id, decoded.tasks[0].id);
+        assert!(decoded.subtasks[0].completed);
+    }
+
+    #[test]
+    fn nesting_and_bullets() {
+        let input = "\
+Some prose, and a [link](https://example.com).
+
+# backend
+
+* [X] deploy
+\t+ [ ] smoke test
+    - [x] tag release
+- [ ] rollback
+- not a checklist item
+- [?] nor this
+";
+        let dump = decode(input, None).unwrap();
+
+        let tasks: Vec<(&str, bool)> = dump
+            .tasks
+            .iter()
+            .map(|t| (t.name.as_str(), t.completed))
+            .collect();
+        assert_eq!(tasks, [("deploy", true), ("rollback", false)]);
+        // any indentation makes a subtask of the item above, however deep
+        let subtasks: Vec<(&str, i32)> = dump
+            .subtasks
+            .iter()
+            .map(|s| (s.name.as_str(), s.task_id))
+            .collect();
+        assert_eq!(subtasks, [("smoke test", 1), ("tag release", 1)]);
+    }
+
+    #[test]
+    fn a_project_named_thoughts_is_still_read() {
+        let dump = decode(
+            "# Thoughts\n\n- [ ] write more\n\n## idea\n\n- [ ] note",
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(dump.projects[0].name, "Thoughts");
+        let tasks: Vec<&str> = dump.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tasks, ["write more"]);
+    }
+
+    #[test]
+    fn project_overrides_headings() {
+        let dump = decode("# backend\n- [ ] deploy\n# infra\n- [ ] build", Some("ops")).unwrap();
+
+        assert_eq!(dump.projects.len(), 1);
+        assert_eq!(dump.projects[0].name, "ops");
+        assert!(dump
+            .tasks
+            .iter()
+            .all(|t| t.project_id == dump.projects[0].id));
+    }
+
+    #[test]
+    fn items_need_somewhere_to_go() {
+        let err = decode("- [ ] deploy", None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line 1: item outside a `# Project` section"));
+
+        let err = decode("# backend\n  - [ ] tag release", None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line 2: indented item without a task"));
+
+        let err = decode("# backend\n- [ ]  ", None).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line 2: checklist item without a name"));
+    }
+}
@@ -0,0 +1,135 @@
+//! Codecs for `doit export` and `doit import`.
+
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+
+use crate::db::models::{Dump, Project, SubTask, Task};
+
+pub mod csv;
+pub mod ics;
+pub mod json;
+pub mod markdown;
+pub mod org;
+pub mod taskwarrior;
+pub mod todotxt;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// The whole database as a versioned JSON document
+    Json,
+    /// Checklists per project; importing updates what's already there
+    Markdown,
+    /// todo.txt lines; importing updates what's already there
+    #[value(name = "todotxt")]
+    TodoTxt,
+    /// What `task export` writes; importing syncs by UUID
+    Taskwarrior,
+    /// iCalendar to-dos; importing syncs by UID
+    Ics,
+    /// An Org mode outline; importing updates what's already there
+    Org,
+    /// A task per row; importing updates what's already there
+    Csv,
+}
+
+/// How `doit import` was asked to go about it.
+#[derive(Debug, Default)]
+pub struct ImportOptions {
+    pub replace: bool,
+    pub skip_conflicts: bool,
+    /// Which CSV column each field comes from, as `name=Title,...`.
+    pub map: Option<String>,
+    /// Where Markdown checklist items and Org tasks go, overriding their
+    /// headings, and tasks of other formats that don't name a project.
+    pub project: Option<String>,
+}
+
+impl Format {
+    /// Guesses the format from a file's extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "md" | "markdown" => Some(Format::Markdown),
+            "txt" => Some(Format::TodoTxt),
+            "ics" | "ical" => Some(Format::Ics),
+            "org" => Some(Format::Org),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Rows parsed from formats that carry less than a [`Dump`] does, numbered
+/// in the order they are added.
+impl Dump {
+    fn push_project(&mut self, name: String, at: NaiveDateTime) -> i32 {
+        let id = self.projects.len() as i32 + 1;
+        self.projects.push(Project {
+            id,
+            name,
+            created_on: at,
+            updated_on: None,
+            deleted_on: None,
+            archived_on: None,
+        });
+        id
+    }
+
+    /// The project named `name`, added if it isn't there yet.
+    fn project_named(&mut self, name: &str, at: NaiveDateTime) -> i32 {
+        match self.projects.iter().find(|p| p.name == name) {
+            Some(project) => project.id,
+            None => self.push_project(name.to_string(), at),
+        }
+    }
+
+    fn push_task(
+        &mut self,
+        project_id: i32,
+        name: String,
+        completed: bool,
+        at: NaiveDateTime,
+    ) -> &mut Task {
+        let id = self.tasks.len() as i32 + 1;
+        self.tasks.push(Task {
+            id,
+            project_id,
+            name,
+            completed,
+            created_on: at,
+            short_id: String::new(),
+            updated_on: None,
+            completed_on: completed.then_some(at),
+            deleted_on: None,
+            priority: None,
+            due_on: None,
+            tags: String::new(),
+            metadata: "{}".to_string(),
+        });
+        self.tasks.last_mut().unwrap()
+    }
+
+    fn push_subtask(
+        &mut self,
+        task_id: i32,
+        name: String,
+        completed: bool,
+        at: NaiveDateTime,
+    ) -> &mut SubTask {
+        let id = self.subtasks.len() as i32 + 1;
+        self.subtasks.push(SubTask {
+            id,
+            task_id,
+            name,
+            completed,
+            created_on: at,
+            short_id: String::new(),
+            updated_on: None,
+            completed_on: completed.then_some(at),
+            deleted_on: None,
+        });
+        self.subtasks.last_mut().unwrap()
+    }
+}
@@ -0,0 +1,561 @@
+//! [Org mode](https://orgmode.org) outlines, a top-level headline per
+//! project with tasks and subtasks nested below:
+//!
+//! ```text
+//! * backend
+//! ** TODO [#A] deploy :work:
+//! DEADLINE: <2024-11-08 Fri>
+//! :PROPERTIES:
+//! :DOIT_ID: t-4f2a
+//! :END:
+//! *** DONE tag release
+//! CLOSED: [2024-11-04 Mon 12:00]
+//! ```
+//!
+//! Priority cookies, tags and `DEADLINE` map onto task columns, and
+//! `SCHEDULED` and other properties are kept in the task's metadata.
+//! Thoughts follow under `* Thoughts`, a headline each with the note as its
+//! body. Importing matches rows by their headline path.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+use crate::db::models::{Dump, Thoughts};
+use crate::service::errors::ServiceError;
+
+const THOUGHTS_HEADLINE: &str = "Thoughts";
+/// The property holding a row's short id.
+const ID_PROPERTY: &str = "DOIT_ID";
+/// The metadata key `SCHEDULED` is kept in.
+const SCHEDULED_KEY: &str = "scheduled";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn encode(dump: &Dump) -> String {
+    let mut lines = Vec::new();
+
+    for project in &dump.projects {
+        lines.push(format!("* {}", project.name));
+        for task in dump.tasks.iter().filter(|t| t.project_id == project.id) {
+            let mut headline = format!("** {}", keyword(task.completed));
+            if let Some(priority) = &task.priority {
+                headline.push_str(&format!(" [#{}]", priority));
+            }
+            headline.push(' ');
+            headline.push_str(&task.name);
+            let tags: Vec<&str> = task.tags().collect();
+            if !tags.is_empty() {
+                headline.push_str(&format!(" :{}:", tags.join(":")));
+            }
+            lines.push(headline);
+
+            let mut metadata = task.metadata();
+            let mut planning = Vec::new();
+            if task.completed {
+                planning.extend(
+                    task.completed_on
+                        .map(|at| format!("CLOSED: [{}]", timestamp(at))),
+                );
+            }
+            if let Some(due_on) = task.due_on {
+                planning.push(format!("DEADLINE: <{}>", date(due_on)));
+            }
+            if let Some(scheduled) = metadata
+                .get(SCHEDULED_KEY)
+                .and_then(|text| NaiveDate::parse_from_str(text, DATE_FORMAT).ok())
+            {
+                metadata.remove(SCHEDULED_KEY);
+                planning.push(format!("SCHEDULED: <{}>", date(scheduled)));
+            }
+            if !planning.is_empty() {
+                lines.push(planning.join(" "));
+            }
+            push_properties(&mut lines, &task.short_id, metadata);
+
+            for subtask in dump.subtasks.iter().filter(|s| s.task_id == task.id) {
+                lines.push(format!(
+                    "*** {} {}",
+                    keyword(subtask.completed),
+                    subtask.name
+                ));
+                if let (true, Some(at)) = (subtask.completed, subtask.completed_on) {
+                    lines.push(format!("CLOSED: [{}]", timestamp(at)));
+                }
+                push_properties(&mut lines, &subtask.short_id, BTreeMap::new());
+            }
+        }
+    }
+
+    if !dump.thoughts.is_empty() {
+        lines.push(format!("* {}", THOUGHTS_HEADLINE));
+        for thought in &dump.thoughts {
+            lines.push(format!("** {}", thought.heading));
+            // a line starting with `*` would be read as a headline
+            lines.extend(thought.note.trim_end().lines().map(|line| {
+                match line.starts_with('*') || line.starts_with(",*") {
+                    true => format!(",{}", line),
+                    false => line.to_string(),
+                }
+            }));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Reads second-level headlines into tasks and third-level ones into
+/// subtasks, in the project named by the top-level headline above them
+/// unless `project` is given, in which case everything goes there. Those
+/// under `* Thoughts` are read into thoughts instead. Text that isn't a
+/// headline, planning line or property, like anything before the first
+/// headline, is ignored.
+pub fn decode(input: &str, project: Option<&str>) -> Result<Dump, ServiceError> {
+    let now = Utc::now().naive_utc();
+    let mut dump = Dump::default();
+    let mut section = Section::None;
+    let mut row = Row::None;
+    let mut in_drawer = false;
+
+    for (index, line) in input.lines().enumerate() {
+        let invalid =
+            |msg: &str| ServiceError::InvalidInput(format!("line {}: {}", index + 1, msg));
+
+        if let Some((level, title)) = parse_headline(line) {
+            in_drawer = false;
+            row = Row::None;
+            match (level, &section) {
+                (1, _) if title == THOUGHTS_HEADLINE => section = Section::Thoughts,
+                (1, _) => {
+                    let name = project.unwrap_or(title);
+                    if name.is_empty() {
+                        return Err(invalid("project headline without a name"));
+                    }
+                    section = Section::Project(dump.project_named(name, now));
+                }
+                (2, Section::Thoughts) => {
+                    let id = dump.thoughts.len() as i32 + 1;
+                    dump.thoughts.push(Thoughts {
+                        id,
+                        heading: title.to_string(),
+                        note: String::new(),
+                        created_on: now,
+                        short_id: String::new(),
+                        updated_on: None,
+                        tags: String::new(),
+                    });
+                    row = Row::Thought;
+                }
+                (2, Section::Project(project_id) | Section::Task(project_id, _)) => {
+                    let project_id = *project_id;
+                    let item = Item::parse(title);
+                    if item.name.is_empty() {
+                        return Err(invalid("task headline without a name"));
+                    }
+                    let task = dump.push_task(project_id, item.name, item.completed, now);
+                    task.priority = item.priority;
+                    task.tags = item.tags.join(" ");
+                    let task_id = task.id;
+                    section = Section::Task(project_id, task_id);
+                    row = Row::Task(task_id);
+                }
+                (3, Section::Task(_, task_id)) => {
+                    let item = Item::parse(title);
+                    if item.name.is_empty() {
+                        return Err(invalid("subtask headline without a name"));
+                    }
+                    let subtask = dump.push_subtask(*task_id, item.name, item.completed, now);
+                    row = Row::SubTask(subtask.id);
+                }
+                (3, _) => return Err(invalid("subtask headline without a task above it")),
+                (2, Section::None) => {
+                    return Err(invalid("task headline outside a project, pass --project"))
+                }
+                _ => return Err(invalid("headlines below subtasks aren't supported")),
+            }
+            continue;
+        }
+
+        if let Row::Thought = row {
+            let note = &mut dump.thoughts.last_mut().unwrap().note;
+            // undoes the escaping `encode` does
+            let line = line
+                .strip_prefix(',')
+                .filter(|l| l.starts_with('*') || l.starts_with(",*"))
+                .unwrap_or(line);
+            if !note.is_empty() || !line.trim().is_empty() {
+                note.push_str(line);
+                note.push('\n');
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        match trimmed {
+            ":PROPERTIES:" => in_drawer = true,
+            ":END:" => in_drawer = false,
+            _ if in_drawer => {
+                let Some((key, value)) = parse_property(trimmed) else {
+                    continue;
+                };
+                match row {
+                    Row::Task(id) if key == ID_PROPERTY => {
+                        dump.tasks[id as usize - 1].short_id = value
+                    }
+                    Row::SubTask(id) if key == ID_PROPERTY => {
+                        dump.subtasks[id as usize - 1].short_id = value
+                    }
+                    Row::Task(id) => {
+                        let task = &mut dump.tasks[id as usize - 1];
+                        let mut metadata = task.metadata();
+                        metadata.insert(key, value);
+                        task.metadata = serde_json::to_string(&metadata)
+                            .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                for (keyword, at) in parse_planning(trimmed).map_err(|msg| invalid(&msg))? {
+                    match (keyword, row) {
+                        ("CLOSED", Row::Task(id)) => {
+                            let task = &mut dump.tasks[id as usize - 1];
+                            if task.completed {
+                                task.completed_on = Some(at);
+                            }
+                        }
+                        ("CLOSED", Row::SubTask(id)) => {
+                            let subtask = &mut dump.subtasks[id as usize - 1];
+                            if subtask.completed {
+                                subtask.completed_on = Some(at);
+                            }
+                        }
+                        ("DEADLINE", Row::Task(id)) => {
+                            dump.tasks[id as usize - 1].due_on = Some(at.date())
+                        }
+                        ("SCHEDULED", Row::Task(id)) => {
+                            let task = &mut dump.tasks[id as usize - 1];
+                            let mut metadata = task.metadata();
+                            metadata.insert(
+                                SCHEDULED_KEY.to_string(),
+                                at.format(DATE_FORMAT).to_string(),
+                            );
+                            task.metadata = serde_json::to_string(&metadata)
+                                .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    for thought in &mut dump.thoughts {
+        thought.note = thought.note.trim_end().to_string();
+    }
+
+    Ok(dump)
+}
+
+/// The top-level headline being read, by the dump ids under it.
+enum Section {
+    None,
+    Thoughts,
+    Project(i32),
+    Task(i32, i32),
+}
+
+/// The headline that planning lines and properties belong to.
+#[derive(Clone, Copy)]
+enum Row {
+    None,
+    Thought,
+    Task(i32),
+    SubTask(i32),
+}
+
+/// A task or subtask headline's title taken apart.
+struct Item {
+    completed: bool,
+    priority: Option<String>,
+    name: String,
+    tags: Vec<String>,
+}
+
+impl Item {
+    /// `DONE [#A] name :tag:other:`, all but the name optional. Any keyword
+    /// other than `DONE` leaves the task open.
+    fn parse(title: &str) -> Item {
+        let mut rest = title;
+        let mut completed = false;
+        if let Some((word, after)) = rest.split_once(' ') {
+            if word.len() > 1 && word.chars().all(|c| c.is_ascii_uppercase()) {
+                completed = word == "DONE";
+                rest = after.trim_start();
+            }
+        } else if rest == "DONE" || rest == "TODO" {
+            completed = rest == "DONE";
+            rest = "";
+        }
+
+        let mut priority = None;
+        if let Some(after) = rest.strip_prefix("[#") {
+            if let Some((letter, after)) = after.split_once(']') {
+                if letter.len() == 1 && letter.chars().all(|c| c.is_ascii_uppercase()) {
+                    priority = Some(letter.to_string());
+                    rest = after.trim_start();
+                }
+            }
+        }
+
+        let mut tags = Vec::new();
+        if let Some((name, last)) = rest.rsplit_once(' ') {
+            if last.len() > 2 && last.starts_with(':') && last.ends_with(':') {
+                tags = last
+                    .trim_matches(':')
+                    .split(':')
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                rest = name;
+            }
+        }
+
+        Item {
+            completed,
+            priority,
+            name: rest.trim().to_string(),
+            tags,
+        }
+    }
+}
+
+/// Splits `** title` into its level and title.
+fn parse_headline(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '*').count();
+    let title = line[level..].strip_prefix(' ')?;
+    (level > 0).then(|| (level, title.trim()))
+}
+
+/// `:KEY: value`.
+fn parse_property(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.strip_prefix(':')?.split_once(':')?;
+    (!key.is_empty() && !key.contains(' ')).then(|| (key.to_string(), value.trim().to_string()))
+}
+
+/// Reads `CLOSED: [...]`, `DEADLINE: <...>` and `SCHEDULED: <...>` entries
+/// off a planning line. Anything else yields nothing.
+fn parse_planning(line: &str) -> Result<Vec<(&'static str, NaiveDateTime)>, String> {
+    let mut entries = Vec::new();
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        let Some(keyword) = ["CLOSED", "DEADLINE", "SCHEDULED"]
+            .into_iter()
+            .find(|keyword| rest.starts_with(&format!("{}:", keyword)))
+        else {
+            return Ok(entries);
+        };
+        let after = rest[keyword.len() + 1..].trim_start();
+        let close = match after.chars().next() {
+            Some('<') => '>',
+            Some('[') => ']',
+            _ => return Err(format!("{} without a timestamp", keyword)),
+        };
+        let Some((stamp, after)) = after[1..].split_once(close) else {
+            return Err(format!("unterminated {} timestamp", keyword));
+        };
+        entries.push((keyword, parse_timestamp(stamp)?));
+        rest = after.trim_start();
+    }
+
+    Ok(entries)
+}
+
+/// `2024-11-04 Mon 12:00`, the day name and time optional. Repeaters and
+/// warnings are ignored.
+fn parse_timestamp(stamp: &str) -> Result<NaiveDateTime, String> {
+    let mut parts = stamp.split_whitespace();
+    let date = parts
+        .next()
+        .and_then(|text| NaiveDate::parse_from_str(text, DATE_FORMAT).ok())
+        .ok_or_else(|| format!("invalid timestamp '{}'", stamp))?;
+    let time = parts
+        .find_map(|part| NaiveTime::parse_from_str(part, "%H:%M").ok())
+        .unwrap_or(NaiveTime::MIN);
+    Ok(date.and_time(time))
+}
+
+fn push_properties(lines: &mut Vec<String>, short_id: &str, metadata: BTreeMap<String, String>) {
+    lines.push(":PROPERTIES:".to_string());
+    lines.push(format!(":{}: {}", ID_PROPERTY, short_id));
+    for (key, value) in metadata {
+        lines.push(format!(":{}: {}", key, value));
+    }
+    lines.push(":END:".to_string());
+}
+
+fn keyword(completed: bool) -> &'static str {
+    if completed {
+        "DONE"
+    } else {
+        "TODO"
+    }
+}
+
+fn date(on: NaiveDate) -> String {
+    on.format("%Y-%m-%d %a").to_string()
+}
+
+fn timestamp(at: NaiveDateTime) -> String {
+    at.format("%Y-%m-%d %a %H:%M").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDate::parse_from_str(date, DATE_FORMAT)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    #[test]
+    fn round_trip() {
+        let now = Utc::now().naive_utc();
+        let mut dump = Dump::default();
+        let project_id = dump.push_project("backend".to_string(), now);
+        let task = dump.push_task(project_id, "deploy".to_string(), true, now);
+        task.short_id = "t-4f2a".to_string();
+        task.completed_on = Some(at("2024-11-04", "12:00"));
+        task.priority = Some("A".to_string());
+        task.tags = "work ops".to_string();
+        task.due_on = NaiveDate::from_ymd_opt(2024, 11, 8);
+        task.metadata = r#"{"owner":"ana","scheduled":"2024-11-06"}"#.to_string();
+        let task_id = task.id;
+        let subtask = dump.push_subtask(task_id, "tag release".to_string(), false, now);
+        subtask.short_id = "s-9c01".to_string();
+        dump.thoughts.push(Thoughts {
+            id: 1,
+            heading: "retro".to_string(),
+            note: "* went well\n,* escaped already\nplain".to_string(),
+            created_on: now,
+            short_id: String::new(),
+            updated_on: None,
+            tags: String::new(),
+        });
+
+        let text = encode(&dump);
+        assert_eq!(
+            text,
+            "\
+* backend
+** DONE [#A] deploy :work:ops:
+CLOSED: [2024-11-04 Mon 12:00] DEADLINE: <2024-11-08 Fri> SCHEDULED: <2024-11-06 Wed>
+:PROPERTIES:
+:DOIT_ID: t-4f2a
+:owner: ana
+:END:
+*** TODO tag release
+:PROPERTIES:
+:DOIT_ID: s-9c01
+:END:
+* Thoughts
+** retro
+,* went well
+,,* escaped already
+plain"
+        );
+
+        let decoded = decode(&text, None).unwrap();
+        let task = &decoded.tasks[0];
+        assert_eq!(task.name, "deploy");
+        assert_eq!(task.short_id, "t-4f2a");
+        assert!(task.completed);
+        assert_eq!(task.completed_on, Some(at("2024-11-04", "12:00")));
+        assert_eq!(task.priority.as_deref(), Some("A"));
+        assert_eq!(task.tags, "work ops");
+        assert_eq!(task.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        assert_eq!(task.metadata(), dump.tasks[0].metadata());
+        assert_eq!(decoded.subtasks[0].short_id, "s-9c01");
+        assert_eq!(decoded.subtasks[0].task_id, task.id);
+        assert_eq!(decoded.thoughts[0].heading, "retro");
+        assert_eq!(decoded.thoughts[0].note, dump.thoughts[0].note);
+    }
+
+    #[test]
+    fn only_the_properties_drawer_is_read() {
+        let input = "\
+#+TITLE: Work
+Some text before the first headline.
+* backend
+** WAITING deploy
+:owner: not in a drawer
+:LOGBOOK:
+CLOCK: [2024-11-01 Fri 09:00]--[2024-11-01 Fri 10:00] =>  1:00
+:END:
+  :PROPERTIES:
+  :Effort:   2:00
+  :not a property
+  :END:
+:after: the drawer
+*** DONE smoke test
+:PROPERTIES:
+:DOIT_ID: s-1234
+:owner: subtasks have no metadata
+:END:
+";
+        let dump = decode(input, None).unwrap();
+
+        let task = &dump.tasks[0];
+        assert_eq!(task.name, "deploy");
+        assert!(!task.completed);
+        let metadata = task.metadata();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata["Effort"], "2:00");
+        let subtask = &dump.subtasks[0];
+        assert!(subtask.completed);
+        assert_eq!(subtask.short_id, "s-1234");
+    }
+
+    #[test]
+    fn headlines() {
+        let item = Item::parse("DONE [#B] ship [#A] it :a::b:");
+        assert!(item.completed);
+        assert_eq!(item.priority.as_deref(), Some("B"));
+        assert_eq!(item.name, "ship [#A] it");
+        assert_eq!(item.tags, ["a", "b"]);
+
+        let item = Item::parse("NASA launch");
+        assert!(!item.completed);
+        assert_eq!(item.name, "launch");
+        assert_eq!(Item::parse("a plain task").name, "a plain task");
+        assert_eq!(Item::parse("DONE").name, "");
+
+        assert_eq!(parse_headline("** deploy"), Some((2, "deploy")));
+        assert_eq!(parse_headline("**bold** text"), None);
+        assert_eq!(parse_headline("no headline"), None);
+    }
+
+    #[test]
+    fn project_overrides_headlines() {
+        let dump = decode("* backend\n** deploy\n* infra\n** build", Some("ops")).unwrap();
+
+        assert_eq!(dump.projects.len(), 1);
+        assert_eq!(dump.projects[0].name, "ops");
+        assert_eq!(dump.tasks.len(), 2);
+    }
+
+    #[test]
+    fn invalid_outlines() {
+        let error = |input: &str| decode(input, None).unwrap_err().to_string();
+
+        assert!(error("** deploy").contains("line 1: task headline outside a project"));
+        assert!(error("* backend\n*** cache").contains("line 2: subtask headline without a task"));
+        assert!(error("* p\n** t\n*** s\n**** deeper").contains("line 4: headlines below subtasks"));
+        assert!(error("* p\n** TODO").contains("line 2: task headline without a name"));
+        assert!(error("* p\n** t\nDEADLINE: soon").contains("line 3: DEADLINE without a timestamp"));
+        assert!(error("* p\n** t\nDEADLINE: <2024-11-08").contains("unterminated DEADLINE"));
+        assert!(error("* p\n** t\nCLOSED: [yesterday]").contains("invalid timestamp 'yesterday'"));
+    }
+}
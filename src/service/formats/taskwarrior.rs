@@ -0,0 +1,495 @@
+//! The JSON that Taskwarrior's `task export` writes and `task import`
+//! reads: an array of tasks, or one task per line.
+//!
+//! Tasks keep their UUID in [`EXTERNAL_ID_KEY`] metadata so syncing again
+//! updates them. Subtasks have no metadata to keep one in, so they become
+//! tasks with a UUID derived from their short id, which their parent
+//! `depends` on, and are read back the same way. Annotations become a
+//! thought named after the task, one `YYYY-MM-DD text` line each.
+//! `H`/`M`/`L` priorities map to `A`/`B`/`C`, and other string attributes,
+//! such as UDAs, go into metadata.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::models::{Dump, Task, Thoughts, EXTERNAL_ID_KEY};
+use crate::service::errors::ServiceError;
+use crate::service::fingerprint::fnv1a;
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const ANNOTATION_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Attributes Taskwarrior computes itself, not worth keeping.
+const DERIVED: &[&str] = &["id", "urgency", "mask", "imask"];
+
+#[derive(Serialize, Deserialize)]
+struct TwTask {
+    uuid: String,
+    description: String,
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "deserialize_depends"
+    )]
+    depends: Vec<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Annotation {
+    entry: String,
+    description: String,
+}
+
+pub fn encode(dump: &Dump) -> Result<String, ServiceError> {
+    let mut exported = Vec::new();
+
+    for task in &dump.tasks {
+        let Some(project) = dump.projects.iter().find(|p| p.id == task.project_id) else {
+            continue;
+        };
+
+        let mut metadata = task.metadata();
+        let uuid = metadata
+            .remove(EXTERNAL_ID_KEY)
+            .unwrap_or_else(|| stable_uuid(&task.short_id));
+        let mut depends = Vec::new();
+
+        for subtask in dump.subtasks.iter().filter(|s| s.task_id == task.id) {
+            let subtask_uuid = stable_uuid(&subtask.short_id);
+            depends.push(subtask_uuid.clone());
+            exported.push(TwTask {
+                uuid: subtask_uuid,
+                description: subtask.name.clone(),
+                status: status(subtask.completed),
+                entry: Some(format_date(subtask.created_on)),
+                modified: subtask.updated_on.map(format_date),
+                end: subtask
+                    .completed_on
+                    .filter(|_| subtask.completed)
+                    .map(format_date),
+                due: None,
+                project: Some(project.name.clone()),
+                priority: None,
+                tags: Vec::new(),
+                annotations: Vec::new(),
+                depends: Vec::new(),
+                other: BTreeMap::new(),
+            });
+        }
+
+        let heading = annotations_heading(&project.name, &task.name);
+        let annotations = dump
+            .thoughts
+            .iter()
+            .find(|thought| thought.heading == heading)
+            .map(|thought| thought.note.lines().filter_map(parse_annotation).collect())
+            .unwrap_or_default();
+
+        exported.push(TwTask {
+            uuid,
+            description: task.name.clone(),
+            status: status(task.completed),
+            entry: Some(format_date(task.created_on)),
+            modified: task.updated_on.map(format_date),
+            end: task
+                .completed_on
+                .filter(|_| task.completed)
+                .map(format_date),
+            due: task
+                .due_on
+                .map(|due_on| format_date(due_on.and_time(NaiveTime::MIN))),
+            project: Some(project.name.clone()),
+            priority: task.priority.as_deref().map(to_tw_priority),
+            tags: task.tags().map(str::to_string).collect(),
+            annotations,
+            depends,
+            other: metadata
+                .into_iter()
+                .map(|(key, value)| (key, Value::String(value)))
+                .collect(),
+        });
+    }
+
+    serde_json::to_string_pretty(&exported)
+        .map_err(|e| ServiceError::OperationFailed(format!("Failed to write JSON: {}", e)))
+}
+
+/// Parses `task export` output. Deleted tasks and recurring templates are
+/// skipped. Tasks without a project go to `project`, and are an error if it
+/// isn't given.
+pub fn decode(input: &str, project: Option<&str>) -> Result<Dump, ServiceError> {
+    let invalid = |e: serde_json::Error| {
+        ServiceError::InvalidInput(format!("Not a Taskwarrior export: {}", e))
+    };
+    let trimmed = input.trim_start();
+    let tw_tasks: Vec<TwTask> = if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed).map_err(invalid)?
+    } else {
+        trimmed
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(invalid))
+            .collect::<Result<_, _>>()?
+    };
+    let tw_tasks: Vec<TwTask> = tw_tasks
+        .into_iter()
+        .filter(|t| t.status != "deleted" && t.status != "recurring")
+        .collect();
+
+    let parents = find_parents(&tw_tasks);
+    let now = Utc::now().naive_utc();
+    let mut dump = Dump::default();
+    let mut task_ids: HashMap<&str, i32> = HashMap::new();
+
+    for tw in tw_tasks
+        .iter()
+        .filter(|t| !parents.contains_key(t.uuid.as_str()))
+    {
+        if tw.description.trim().is_empty() {
+            return Err(ServiceError::InvalidInput(format!(
+                "Task {} has no description",
+                tw.uuid
+            )));
+        }
+        let project_name = match tw.project.as_deref().or(project) {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(ServiceError::InvalidInput(format!(
+                    "Task '{}' has no project, pass --project",
+                    tw.description
+                )))
+            }
+        };
+        let project_id = dump.project_named(&project_name, now);
+
+        let mut metadata: BTreeMap<String, String> = tw
+            .other
+            .iter()
+            .filter(|(key, _)| !DERIVED.contains(&key.as_str()))
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+            .collect();
+        metadata.insert(EXTERNAL_ID_KEY.to_string(), tw.uuid.clone());
+
+        let task = dump.push_task(project_id, tw.description.clone(), completed(tw), now);
+        fill_dates(task, tw, now)?;
+        task.priority = tw.priority.as_deref().and_then(from_tw_priority);
+        task.due_on = tw
+            .due
+            .as_deref()
+            .map(parse_date)
+            .transpose()?
+            .map(|due| due.date());
+        task.tags = tw.tags.join(" ");
+        task.metadata = serde_json::to_string(&metadata)
+            .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+        let task_id = task.id;
+        task_ids.insert(&tw.uuid, task_id);
+
+        if !tw.annotations.is_empty() {
+            dump.thoughts.push(Thoughts {
+                id: dump.thoughts.len() as i32 + 1,
+                heading: annotations_heading(&project_name, &tw.description),
+                note: tw
+                    .annotations
+                    .iter()
+                    .map(format_annotation)
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join("\n"),
+                created_on: now,
+                short_id: String::new(),
+                updated_on: None,
+                tags: String::new(),
+            });
+        }
+    }
+
+    for tw in &tw_tasks {
+        let Some(parent) = parents.get(tw.uuid.as_str()) else {
+            continue;
+        };
+        let task_id = task_ids[parent];
+        let completed = completed(tw);
+        let subtask = dump.push_subtask(task_id, tw.description.clone(), completed, now);
+        subtask.created_on = tw
+            .entry
+            .as_deref()
+            .map(parse_date)
+            .transpose()?
+            .unwrap_or(now);
+        if completed {
+            subtask.completed_on = tw.end.as_deref().map(parse_date).transpose()?.or(Some(now));
+        }
+    }
+
+    Ok(dump)
+}
+
+/// Maps each subtask's UUID to its parent's: a task another one in the same
+/// project depends on, which has no dependencies of its own. Only one level
+/// deep, as doit's subtasks are.
+fn find_parents(tw_tasks: &[TwTask]) -> HashMap<&str, &str> {
+    let by_uuid: HashMap<&str, &TwTask> = tw_tasks.iter().map(|t| (t.uuid.as_str(), t)).collect();
+    let mut parents = HashMap::new();
+
+    for parent in tw_tasks {
+        for dependency in &parent.depends {
+            let Some(child) = by_uuid.get(dependency.as_str()) else {
+                continue;
+            };
+            if child.depends.is_empty() && child.project == parent.project {
+                parents
+                    .entry(child.uuid.as_str())
+                    .or_insert(parent.uuid.as_str());
+            }
+        }
+    }
+
+    // a subtask can't have subtasks of its own
+    let children: HashSet<&str> = parents.keys().copied().collect();
+    parents.retain(|_, parent| !children.contains(parent));
+    parents
+}
+
+fn fill_dates(task: &mut Task, tw: &TwTask, now: NaiveDateTime) -> Result<(), ServiceError> {
+    task.created_on = tw
+        .entry
+        .as_deref()
+        .map(parse_date)
+        .transpose()?
+        .unwrap_or(now);
+    task.updated_on = tw.modified.as_deref().map(parse_date).transpose()?;
+    if task.completed {
+        task.completed_on = tw.end.as_deref().map(parse_date).transpose()?.or(Some(now));
+    }
+    Ok(())
+}
+
+fn completed(tw: &TwTask) -> bool {
+    tw.status == "completed"
+}
+
+fn status(completed: bool) -> String {
+    if completed { "completed" } else { "pending" }.to_string()
+}
+
+fn to_tw_priority(priority: &str) -> String {
+    match priority {
+        "A" => "H",
+        "B" => "M",
+        _ => "L",
+    }
+    .to_string()
+}
+
+fn from_tw_priority(priority: &str) -> Option<String> {
+    match priority {
+        "H" => Some("A"),
+        "M" => Some("B"),
+        "L" => Some("C"),
+        _ => None,
+    }
+    .map(str::to_string)
+}
+
+fn annotations_heading(project: &str, task: &str) -> String {
+    format!("{}/{}", project, task)
+}
+
+fn format_annotation(annotation: &Annotation) -> Result<String, ServiceError> {
+    Ok(format!(
+        "{} {}",
+        parse_date(&annotation.entry)?.format(ANNOTATION_DATE_FORMAT),
+        annotation.description
+    ))
+}
+
+fn parse_annotation(line: &str) -> Option<Annotation> {
+    let (date, description) = line.split_once(' ')?;
+    let date = NaiveDate::parse_from_str(date, ANNOTATION_DATE_FORMAT).ok()?;
+    Some(Annotation {
+        entry: format_date(date.and_time(NaiveTime::MIN)),
+        description: description.to_string(),
+    })
+}
+
+fn format_date(at: NaiveDateTime) -> String {
+    at.format(DATE_FORMAT).to_string()
+}
+
+/// Taskwarrior writes `20241101T120000Z`; ISO 8601 is accepted too.
+fn parse_date(text: &str) -> Result<NaiveDateTime, ServiceError> {
+    NaiveDateTime::parse_from_str(text, DATE_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%SZ"))
+        .map_err(|_| ServiceError::InvalidInput(format!("Invalid date '{}'", text)))
+}
+
+/// A UUID derived from a short id, so a task exported twice keeps the same
+/// one and Taskwarrior updates it instead of adding another.
+fn stable_uuid(short_id: &str) -> String {
+    let high = fnv1a(short_id, 0xcbf2_9ce4_8422_2325);
+    let low = fnv1a(short_id, 0x8422_2325_cbf2_9ce4);
+    // version 8 (custom), RFC 4122 variant
+    let high = (high & !0xf000) | 0x8000;
+    let low = (low & !(0b11 << 62)) | (0b10 << 62);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Taskwarrior 2.6 writes an array, older versions a comma separated string.
+fn deserialize_depends<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depends {
+        List(Vec<String>),
+        Csv(String),
+    }
+
+    Ok(match Depends::deserialize(deserializer)? {
+        Depends::List(uuids) => uuids,
+        Depends::Csv(uuids) => uuids.split(',').map(str::to_string).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"[
+        {"id": 1, "uuid": "5f2c8a3e-0b7d-4c1e-9a6f-2d4b8e1c7a90", "description": "deploy",
+         "status": "pending", "entry": "20241101T120000Z", "project": "backend",
+         "priority": "H", "tags": ["release"], "due": "20241108T000000Z",
+         "urgency": 8.2, "estimate": "2h", "depends": "0a1b2c3d-0000-4000-8000-000000000001",
+         "annotations": [{"entry": "20241102T093000Z", "description": "waiting on ops"}]},
+        {"id": 2, "uuid": "0a1b2c3d-0000-4000-8000-000000000001", "description": "tag release",
+         "status": "completed", "entry": "20241101T120000Z", "end": "2024-11-03T10:00:00Z",
+         "project": "backend"},
+        {"uuid": "9e8d7c6b-0000-4000-8000-000000000002", "description": "gone",
+         "status": "deleted", "project": "backend"}
+    ]"#;
+
+    #[test]
+    fn reads_task_export() {
+        let dump = decode(EXPORT, None).unwrap();
+
+        assert_eq!(dump.tasks.len(), 1);
+        let task = &dump.tasks[0];
+        assert_eq!(task.name, "deploy");
+        assert_eq!(task.priority.as_deref(), Some("A"));
+        assert_eq!(task.tags, "release");
+        assert_eq!(task.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        let metadata = task.metadata();
+        assert_eq!(
+            metadata[EXTERNAL_ID_KEY],
+            "5f2c8a3e-0b7d-4c1e-9a6f-2d4b8e1c7a90"
+        );
+        // UDAs are kept, what Taskwarrior computes isn't
+        assert_eq!(metadata["estimate"], "2h");
+        assert!(!metadata.contains_key("urgency") && !metadata.contains_key("id"));
+
+        assert_eq!(dump.subtasks.len(), 1);
+        let subtask = &dump.subtasks[0];
+        assert_eq!(subtask.name, "tag release");
+        assert!(subtask.completed);
+        assert_eq!(
+            subtask.completed_on,
+            NaiveDate::from_ymd_opt(2024, 11, 3).and_then(|d| d.and_hms_opt(10, 0, 0))
+        );
+
+        assert_eq!(dump.thoughts.len(), 1);
+        assert_eq!(dump.thoughts[0].heading, "backend/deploy");
+        assert_eq!(dump.thoughts[0].note, "2024-11-02 waiting on ops");
+    }
+
+    #[test]
+    fn reads_one_task_per_line() {
+        let input = r#"{"uuid": "a", "description": "deploy", "status": "pending"}
+
+{"uuid": "b", "description": "build", "status": "completed", "project": "infra"}"#;
+        let dump = decode(input, Some("backend")).unwrap();
+
+        let projects: Vec<&str> = dump.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(projects, ["backend", "infra"]);
+        assert!(dump.tasks[1].completed);
+
+        let err = decode(input, None).unwrap_err();
+        assert!(err.to_string().contains("'deploy' has no project"));
+    }
+
+    #[test]
+    fn round_trip_keeps_uuids_and_annotations() {
+        let mut dump = decode(EXPORT, None).unwrap();
+        dump.subtasks[0].short_id = "s-9c01".to_string();
+
+        let text = encode(&dump).unwrap();
+        let exported: Vec<TwTask> = serde_json::from_str(&text).unwrap();
+        let subtask_uuid = stable_uuid("s-9c01");
+        assert_eq!(exported[0].uuid, subtask_uuid);
+        assert_eq!(exported[1].uuid, "5f2c8a3e-0b7d-4c1e-9a6f-2d4b8e1c7a90");
+        assert_eq!(exported[1].depends, [subtask_uuid]);
+        assert_eq!(exported[1].priority.as_deref(), Some("H"));
+        assert!(!exported[1].other.contains_key(EXTERNAL_ID_KEY));
+
+        let decoded = decode(&text, None).unwrap();
+        assert_eq!(decoded.tasks.len(), 1);
+        assert_eq!(decoded.tasks[0].metadata(), dump.tasks[0].metadata());
+        assert_eq!(decoded.subtasks[0].name, "tag release");
+        assert_eq!(decoded.thoughts[0].note, "2024-11-02 waiting on ops");
+    }
+
+    #[test]
+    fn stable_uuids() {
+        let uuid = stable_uuid("t-4f2a");
+
+        assert_eq!(uuid, stable_uuid("t-4f2a"));
+        assert_ne!(uuid, stable_uuid("t-4f2b"));
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "8");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+    }
+
+    #[test]
+    fn only_one_level_of_dependencies_is_a_subtask() {
+        let input = r#"[
+            {"uuid": "a", "description": "release", "status": "pending", "project": "p", "depends": ["b"]},
+            {"uuid": "b", "description": "deploy", "status": "pending", "project": "p", "depends": ["c"]},
+            {"uuid": "c", "description": "build", "status": "pending", "project": "p"}
+        ]"#;
+        let dump = decode(input, None).unwrap();
+
+        let tasks: Vec<&str> = dump.tasks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tasks, ["release", "deploy"]);
+        assert_eq!(dump.subtasks[0].name, "build");
+        assert_eq!(dump.subtasks[0].task_id, dump.tasks[1].id);
+    }
+}
@@ -0,0 +1,424 @@
+//! [todo.txt](https://github.com/todotxt/todo.txt), one task per line:
+//!
+//! ```text
+//! (A) 2024-11-01 deploy +backend @work due:2024-11-08 id:t-4f2a
+//! x 2024-11-04 2024-11-01 tag release +backend task:t-4f2a id:s-9c01
+//! ```
+//!
+//! `+project`, `@context` (a tag), `(A)` priority, `x` with its dates and
+//! `due:` map onto task columns. `id:` carries the short id, and `task:`
+//! turns a line into a subtask of the task with that short id. Any other
+//! `key:value` pair is kept in the task's metadata and written back out.
+//!
+//! So that everything reads back the way it was written, doit
+//! percent-encodes what the format can't hold: spaces, colons and `%` in
+//! projects, tags and metadata (`+My%20Project`), and the first character
+//! of a description word that would otherwise read as a project, a tag or
+//! a `key:value` pair (`%40bob`, `16%3A9`). Every token is decoded on
+//! import.
+
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+use crate::db::models::Dump;
+use crate::service::errors::ServiceError;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn encode(dump: &Dump) -> String {
+    let mut lines = Vec::new();
+
+    for task in &dump.tasks {
+        let Some(project) = dump.projects.iter().find(|p| p.id == task.project_id) else {
+            continue;
+        };
+
+        let mut line = prefix(
+            task.completed,
+            task.completed_on,
+            task.created_on,
+            task.priority.as_deref(),
+        );
+        line.push(description(&task.name));
+        line.push(format!("+{}", escape(&project.name)));
+        line.extend(task.tags().map(|tag| format!("@{}", escape(tag))));
+        if let Some(due_on) = task.due_on {
+            line.push(format!("due:{}", due_on.format(DATE_FORMAT)));
+        }
+        // completed tasks lose the `(A)` prefix, so keep it the way the
+        // format suggests
+        if let (true, Some(priority)) = (task.completed, &task.priority) {
+            line.push(format!("pri:{}", priority));
+        }
+        line.extend(
+            task.metadata()
+                .iter()
+                .map(|(key, value)| format!("{}:{}", metadata_key(key), escape(value))),
+        );
+        line.push(format!("id:{}", task.short_id));
+        lines.push(line.join(" "));
+
+        for subtask in dump.subtasks.iter().filter(|s| s.task_id == task.id) {
+            let mut line = prefix(
+                subtask.completed,
+                subtask.completed_on,
+                subtask.created_on,
+                None,
+            );
+            line.push(description(&subtask.name));
+            line.push(format!("+{}", escape(&project.name)));
+            line.push(format!("task:{}", task.short_id));
+            line.push(format!("id:{}", subtask.short_id));
+            lines.push(line.join(" "));
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// Parses a todo.txt file. Lines without a `+project` go to `project`,
+/// and are an error if it isn't given.
+pub fn decode(input: &str, project: Option<&str>) -> Result<Dump, ServiceError> {
+    let now = Utc::now().naive_utc();
+    let mut dump = Dump::default();
+    // (line number, parent short id, parsed line), added once all tasks are in
+    let mut subtasks = Vec::new();
+
+    for (index, text) in input.lines().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let invalid =
+            |msg: String| ServiceError::InvalidInput(format!("line {}: {}", index + 1, msg));
+
+        let line = Line::parse(text).map_err(invalid)?;
+        if line.name.is_empty() {
+            return Err(invalid("task without a description".to_string()));
+        }
+        if let Some(parent) = line.parent.clone() {
+            subtasks.push((index + 1, parent, line));
+            continue;
+        }
+
+        let project_name = match line.project.as_deref().or(project) {
+            Some(name) => name.to_string(),
+            None => return Err(invalid("no +project, pass --project".to_string())),
+        };
+        let project_id = dump.project_named(&project_name, now);
+
+        let task = dump.push_task(project_id, line.name, line.completed, now);
+        task.created_on = line.created_on.unwrap_or(now);
+        task.completed_on = line.completed.then(|| line.completed_on.unwrap_or(now));
+        task.short_id = line.id.unwrap_or_default();
+        task.priority = line.priority;
+        task.due_on = line.due_on;
+        task.tags = line.tags.join(" ");
+        task.metadata = serde_json::to_string(&line.metadata)
+            .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+    }
+
+    for (number, parent, line) in subtasks {
+        let Some(task_id) = dump
+            .tasks
+            .iter()
+            .find(|task| task.short_id == parent)
+            .map(|task| task.id)
+        else {
+            return Err(ServiceError::InvalidInput(format!(
+                "line {}: no task with id:{} in the file",
+                number, parent
+            )));
+        };
+
+        let subtask = dump.push_subtask(task_id, line.name, line.completed, now);
+        subtask.created_on = line.created_on.unwrap_or(now);
+        subtask.completed_on = line.completed.then(|| line.completed_on.unwrap_or(now));
+        subtask.short_id = line.id.unwrap_or_default();
+    }
+
+    Ok(dump)
+}
+
+#[derive(Default)]
+struct Line {
+    completed: bool,
+    completed_on: Option<NaiveDateTime>,
+    created_on: Option<NaiveDateTime>,
+    priority: Option<String>,
+    name: String,
+    project: Option<String>,
+    tags: Vec<String>,
+    due_on: Option<NaiveDate>,
+    id: Option<String>,
+    parent: Option<String>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl Line {
+    fn parse(text: &str) -> Result<Line, String> {
+        let mut line = Line::default();
+        let mut tokens = text.split_whitespace().peekable();
+
+        if tokens.peek() == Some(&"x") {
+            tokens.next();
+            line.completed = true;
+            line.completed_on = tokens
+                .next_if(|t| parse_date(t).is_some())
+                .and_then(parse_date);
+        } else if let Some(priority) = tokens.peek().and_then(|t| parse_priority(t)) {
+            tokens.next();
+            line.priority = Some(priority);
+        }
+        line.created_on = tokens
+            .next_if(|t| parse_date(t).is_some())
+            .and_then(parse_date);
+
+        let mut words = Vec::new();
+        for token in tokens {
+            if let Some(project) = token.strip_prefix('+').filter(|p| !p.is_empty()) {
+                if line.project.is_none() {
+                    line.project = Some(unescape(project));
+                    continue;
+                }
+            } else if let Some(tag) = token.strip_prefix('@').filter(|t| !t.is_empty()) {
+                line.tags.push(unescape(tag));
+                continue;
+            } else if let Some((key, value)) = key_value(token) {
+                match key {
+                    "due" => {
+                        let date = NaiveDate::parse_from_str(value, DATE_FORMAT)
+                            .map_err(|_| format!("invalid due date '{}'", value))?;
+                        line.due_on = Some(date);
+                    }
+                    // priority of a completed task, by convention
+                    "pri" if value.len() == 1 && value.chars().all(|c| c.is_ascii_uppercase()) => {
+                        line.priority = Some(value.to_string());
+                    }
+                    "id" => line.id = Some(value.to_string()),
+                    "task" => line.parent = Some(value.to_string()),
+                    _ => {
+                        line.metadata.insert(unescape(key), unescape(value));
+                    }
+                }
+                continue;
+            }
+            words.push(unescape(token));
+        }
+        line.name = words.join(" ");
+
+        Ok(line)
+    }
+}
+
+/// `x 2024-11-04 2024-11-01` or `(A) 2024-11-01`.
+fn prefix(
+    completed: bool,
+    completed_on: Option<NaiveDateTime>,
+    created_on: NaiveDateTime,
+    priority: Option<&str>,
+) -> Vec<String> {
+    let mut prefix = Vec::new();
+    if completed {
+        // a creation date needs a completion date before it, or it's read
+        // as one
+        prefix.push("x".to_string());
+        let completed_on = completed_on.unwrap_or(created_on);
+        prefix.push(completed_on.format(DATE_FORMAT).to_string());
+    } else if let Some(priority) = priority {
+        prefix.push(format!("({})", priority));
+    }
+    prefix.push(created_on.format(DATE_FORMAT).to_string());
+
+    prefix
+}
+
+fn parse_date(token: &str) -> Option<NaiveDateTime> {
+    NaiveDate::parse_from_str(token, DATE_FORMAT)
+        .ok()
+        .map(|date| date.and_time(NaiveTime::MIN))
+}
+
+fn parse_priority(token: &str) -> Option<String> {
+    let letter = token.strip_prefix('(')?.strip_suffix(')')?;
+    (letter.len() == 1 && letter.chars().all(|c| c.is_ascii_uppercase()))
+        .then(|| letter.to_string())
+}
+
+/// `key:value` with neither side empty or containing another colon. URLs
+/// such as `https://example.com` are left in the description.
+fn key_value(token: &str) -> Option<(&str, &str)> {
+    let (key, value) = token.split_once(':')?;
+    let valid =
+        !key.is_empty() && !value.is_empty() && !value.contains(':') && !value.starts_with("//");
+    valid.then_some((key, value))
+}
+
+/// Keys with a meaning of their own in doit's lines.
+const RESERVED_KEYS: [&str; 4] = ["due", "pri", "id", "task"];
+
+/// `text` as a single token: whitespace, colons and `%` percent-encoded.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || c == ':' || c == '%' {
+            escaped.push_str(&percent_encode(c));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// A metadata key, with the first letter of one doit reads itself encoded
+/// so it stays metadata.
+fn metadata_key(key: &str) -> String {
+    let key = escape(key);
+    match key.chars().next() {
+        Some(first) if RESERVED_KEYS.contains(&key.as_str()) => {
+            format!("{}{}", percent_encode(first), &key[first.len_utf8()..])
+        }
+        _ => key,
+    }
+}
+
+/// A task or subtask name, with the words that would read as something
+/// else encoded.
+fn description(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            let word = word.replace('%', "%25");
+            let Some(first) = word.chars().next() else {
+                return word;
+            };
+            if word.len() > 1 && (first == '+' || first == '@') {
+                format!("{}{}", percent_encode(first), &word[first.len_utf8()..])
+            } else if key_value(&word).is_some() {
+                word.replacen(':', "%3A", 1)
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn percent_encode(c: char) -> String {
+    let mut bytes = [0; 4];
+    c.encode_utf8(&mut bytes)
+        .bytes()
+        .map(|byte| format!("%{:02X}", byte))
+        .collect()
+}
+
+/// Decodes `%XX` sequences. A `%` not followed by two hex digits is kept,
+/// as other tools don't encode anything.
+fn unescape(token: &str) -> String {
+    let bytes = token.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> NaiveDateTime {
+        parse_date(date).unwrap()
+    }
+
+    #[test]
+    fn everything_reads_back_the_way_it_was_written() {
+        let mut dump = Dump::default();
+        let project_id = dump.push_project("My Project".to_string(), at("2024-11-01"));
+        let task = dump.push_task(
+            project_id,
+            "Ship v2 @bob ratio 16:9".to_string(),
+            false,
+            at("2024-11-01"),
+        );
+        task.short_id = "t-4f2a".to_string();
+        task.priority = Some("A".to_string());
+        task.tags = "release 100%".to_string();
+        task.metadata =
+            r#"{"due":"soon","link":"https://example.com","size":"16:9 wide"}"#.to_string();
+        let task_id = task.id;
+        let subtask = dump.push_subtask(
+            task_id,
+            "2024-11-02 +1 for 50% off".to_string(),
+            true,
+            at("2024-11-01"),
+        );
+        subtask.short_id = "s-9c01".to_string();
+        subtask.completed_on = None;
+
+        let decoded = decode(&encode(&dump), None).unwrap();
+
+        assert_eq!(decoded.projects.len(), 1);
+        assert_eq!(decoded.projects[0].name, "My Project");
+        let task = &decoded.tasks[0];
+        assert_eq!(task.name, "Ship v2 @bob ratio 16:9");
+        assert_eq!(task.short_id, "t-4f2a");
+        assert_eq!(task.priority.as_deref(), Some("A"));
+        assert_eq!(task.tags, "release 100%");
+        assert_eq!(task.metadata(), dump.tasks[0].metadata());
+        assert_eq!(task.due_on, None);
+        let subtask = &decoded.subtasks[0];
+        assert_eq!(subtask.task_id, task.id);
+        assert_eq!(subtask.name, "2024-11-02 +1 for 50% off");
+        // there's no completion date without one, so it takes the creation date
+        assert_eq!(subtask.created_on, at("2024-11-01"));
+        assert_eq!(subtask.completed_on, Some(at("2024-11-01")));
+    }
+
+    #[test]
+    fn lines_from_other_tools() {
+        let input = "\
+(B) 2024-11-01 call mom +family @phone due:2024-11-08 50%off https://example.com
+x 2024-11-04 2024-11-01 file taxes +home pri:A
+";
+        let dump = decode(input, None).unwrap();
+
+        let names: Vec<&str> = dump.projects.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["family", "home"]);
+        let call = &dump.tasks[0];
+        assert_eq!(call.name, "call mom 50%off https://example.com");
+        assert_eq!(call.priority.as_deref(), Some("B"));
+        assert_eq!(call.tags, "phone");
+        assert_eq!(call.due_on, NaiveDate::from_ymd_opt(2024, 11, 8));
+        let taxes = &dump.tasks[1];
+        assert!(taxes.completed);
+        assert_eq!(taxes.completed_on, Some(at("2024-11-04")));
+        assert_eq!(taxes.created_on, at("2024-11-01"));
+        assert_eq!(taxes.priority.as_deref(), Some("A"));
+    }
+
+    #[test]
+    fn lines_without_a_project_or_parent() {
+        let err = decode("call mom", None).unwrap_err();
+        assert!(err.to_string().contains("line 1: no +project"));
+        let dump = decode("call mom", Some("family")).unwrap();
+        assert_eq!(dump.projects[0].name, "family");
+
+        let err = decode("pack +trip\nsocks +trip task:t-beef", None).unwrap_err();
+        assert!(err.to_string().contains("line 2: no task with id:t-beef"));
+    }
+}
@@ -0,0 +1,77 @@
+const MIN_SIMILARITY: f64 = 0.75;
+/// The most suggestions offered for a name.
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/// Returns the candidates closest to `query`, best match first.
+///
+/// Matching is case-insensitive; a candidate is kept if it contains the
+/// query (or vice versa) or is similar enough by Jaro-Winkler distance.
+pub fn closest<I, S>(query: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut scored: Vec<(f64, String)> = candidates
+        .into_iter()
+        .map(Into::into)
+        .filter_map(|candidate| {
+            let lowered = candidate.to_lowercase();
+            let mut score = strsim::jaro_winkler(&query, &lowered);
+            if lowered.contains(&query) || query.contains(&lowered) {
+                score = score.max(MIN_SIMILARITY);
+            }
+            (score >= MIN_SIMILARITY).then_some((score, candidate))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_match_first() {
+        let close = closest("deplyo", ["docs", "deploy", "deployment", "release"]);
+        assert_eq!(close, ["deploy", "deployment"]);
+    }
+
+    #[test]
+    fn case_insensitive_and_substrings() {
+        assert_eq!(closest("BACKEND", ["backend"]), ["backend"]);
+        // too far apart by distance, but one contains the other
+        assert_eq!(
+            closest("api", ["public-api-gateway", "ui"]),
+            ["public-api-gateway"]
+        );
+    }
+
+    #[test]
+    fn nothing_for_blank_queries_or_distant_names() {
+        assert!(closest("  ", ["backend"]).is_empty());
+        assert!(closest("zzz", ["backend", "infra"]).is_empty());
+    }
+
+    #[test]
+    fn capped_and_deduplicated() {
+        let names = [
+            "task", "task", "tasks", "tusk", "tasky", "taskz", "tasq", "tas",
+        ];
+        let close = closest("task", names);
+        assert_eq!(close.len(), MAX_SUGGESTIONS);
+        assert_eq!(close[0], "task");
+        assert_eq!(close.iter().filter(|c| *c == "task").count(), 1);
+    }
+}
@@ -0,0 +1,352 @@
+//! `doit git-sync`: tasks named in commit message trailers, read from a
+//! local repository with the `git` command:
+//!
+//! ```text
+//! Retry deploys that time out
+//!
+//! Closes: backend/deploy-pipeline
+//! Refs: t-4f2a, s-9c01
+//! ```
+//!
+//! `Closes`, `Fixes` and `Resolves` mark a task or subtask done and
+//! attach the commit to the task. `Refs` only attaches it; a subtask's
+//! commit goes to its task. Several references can share a trailer,
+//! comma separated.
+//!
+//! The last commit synced is kept in `.git/doit-synced`, so each sync only
+//! reads the commits made since. Should that commit be gone, say after a
+//! rebase and `git gc`, only the latest commit is read and syncing carries
+//! on from there. `doit git-hook install` syncs after every commit.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::db::models::CommitReport;
+
+use super::errors::ServiceError;
+
+const STATE_FILE: &str = "doit-synced";
+const HOOK: &str = "post-commit";
+/// Marks a hook as written by doit, so it can be replaced.
+const HOOK_MARKER: &str = "# Added by `doit git-hook install`";
+const CLOSING_KEYS: [&str; 3] = ["closes", "fixes", "resolves"];
+const REFERENCING_KEYS: [&str; 2] = ["refs", "references"];
+
+/// What a commit trailer asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Close,
+    Reference,
+}
+
+#[derive(Debug)]
+pub struct Commit {
+    pub hash: String,
+    /// Task and subtask references, in trailer order.
+    pub references: Vec<(Action, String)>,
+}
+
+impl Commit {
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+}
+
+/// What `doit git-sync` read and changed.
+#[derive(Debug)]
+pub struct GitSyncSummary {
+    pub commits: usize,
+    pub report: CommitReport,
+    /// References that didn't resolve, and why.
+    pub unresolved: Vec<String>,
+    /// The last synced commit, if it no longer exists and only the latest
+    /// commit was read instead.
+    pub lost: Option<String>,
+}
+
+/// The commits reachable from `HEAD` but not from `since`, oldest first.
+/// Without `since` that's the whole history, and a repository without
+/// commits has none.
+pub fn commits(repo: &Path, since: Option<&str>) -> Result<Vec<Commit>, ServiceError> {
+    let range = match since {
+        Some(since) => format!("{}..HEAD", since),
+        None => "HEAD".to_string(),
+    };
+    log(repo, &[&range])
+}
+
+/// The commit at `HEAD`, if there is one.
+pub fn head_commit(repo: &Path) -> Result<Option<Commit>, ServiceError> {
+    Ok(log(repo, &["-1", "HEAD"])?.pop())
+}
+
+/// Whether `rev` names a commit in the repository.
+pub fn exists(repo: &Path, rev: &str) -> bool {
+    git(
+        repo,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ],
+    )
+    .is_ok()
+}
+
+fn log(repo: &Path, args: &[&str]) -> Result<Vec<Commit>, ServiceError> {
+    if git(repo, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_err() {
+        return Ok(Vec::new());
+    }
+    let mut log_args = vec![
+        "log",
+        "--reverse",
+        "--format=%H%x1f%(trailers:only,unfold)%x1e",
+    ];
+    log_args.extend(args);
+    let log = git(repo, &log_args)?;
+
+    Ok(log
+        .split('\x1e')
+        .filter_map(|record| {
+            let (hash, trailers) = record.trim_start().split_once('\x1f')?;
+            Some(Commit {
+                hash: hash.to_string(),
+                references: trailers.lines().flat_map(parse_trailer).collect(),
+            })
+        })
+        .collect())
+}
+
+/// The references in one `Key: value` trailer line, if its key is one
+/// doit knows.
+fn parse_trailer(line: &str) -> Vec<(Action, String)> {
+    let Some((key, value)) = line.split_once(':') else {
+        return Vec::new();
+    };
+    let key = key.trim().to_lowercase();
+    let action = if CLOSING_KEYS.contains(&key.as_str()) {
+        Action::Close
+    } else if REFERENCING_KEYS.contains(&key.as_str()) {
+        Action::Reference
+    } else {
+        return Vec::new();
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|reference| !reference.is_empty())
+        .map(|reference| (action, reference.to_string()))
+        .collect()
+}
+
+/// The last commit synced, if any.
+pub fn last_synced(repo: &Path) -> Result<Option<String>, ServiceError> {
+    let path = git_path(repo, STATE_FILE)?;
+    match fs::read_to_string(&path) {
+        Ok(hash) => Ok(Some(hash.trim().to_string()).filter(|hash| !hash.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ServiceError::OperationFailed(format!(
+            "Failed to read '{}': {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+pub fn save_synced(repo: &Path, hash: &str) -> Result<(), ServiceError> {
+    let path = git_path(repo, STATE_FILE)?;
+    fs::write(&path, format!("{}\n", hash)).map_err(|e| {
+        ServiceError::OperationFailed(format!("Failed to write '{}': {}", path.display(), e))
+    })
+}
+
+/// Writes a post-commit hook that runs `command`, returning its path. A hook
+/// doit didn't write is left alone. Nothing is written unless `write` is
+/// set, so dry runs leave the repository be.
+pub fn install_hook(repo: &Path, command: &str, write: bool) -> Result<PathBuf, ServiceError> {
+    let path = git_path(repo, &format!("hooks/{}", HOOK))?;
+    if let Ok(existing) = fs::read_to_string(&path) {
+        if !existing.contains(HOOK_MARKER) {
+            return Err(ServiceError::InvalidInput(format!(
+                "'{}' already exists, add this line to it instead:\n  {}",
+                path.display(),
+                command
+            )));
+        }
+    }
+    if !write {
+        return Ok(path);
+    }
+
+    let failed = |e: std::io::Error| {
+        ServiceError::OperationFailed(format!("Failed to write '{}': {}", path.display(), e))
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(failed)?;
+    }
+    fs::write(&path, format!("#!/bin/sh\n{}\n{}\n", HOOK_MARKER, command)).map_err(failed)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).map_err(failed)?;
+    }
+
+    Ok(path)
+}
+
+/// Quotes `text` for a POSIX shell.
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Where git keeps `name` for the repository, honouring `core.hooksPath`
+/// and worktrees.
+fn git_path(repo: &Path, name: &str) -> Result<PathBuf, ServiceError> {
+    let path = PathBuf::from(git(repo, &["rev-parse", "--git-path", name])?.trim());
+    Ok(if path.is_absolute() {
+        path
+    } else {
+        repo.join(path)
+    })
+}
+
+/// Runs git in `repo` and returns what it printed.
+fn git(repo: &Path, args: &[&str]) -> Result<String, ServiceError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| ServiceError::OperationFailed(format!("Failed to run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(ServiceError::OperationFailed(format!(
+            "git {} failed in '{}': {}",
+            args.join(" "),
+            repo.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repository with no commits yet.
+    fn test_repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]).unwrap();
+        dir
+    }
+
+    fn commit(repo: &Path, message: &str) -> String {
+        git(
+            repo,
+            &[
+                "-c",
+                "user.name=doit",
+                "-c",
+                "user.email=doit@example.com",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                message,
+            ],
+        )
+        .unwrap();
+        git(repo, &["rev-parse", "HEAD"])
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    #[test]
+    fn trailers_name_references_by_key() {
+        assert_eq!(
+            parse_trailer("Closes: backend/deploy"),
+            [(Action::Close, "backend/deploy".to_string())]
+        );
+        assert_eq!(
+            parse_trailer("References: t-4f2a"),
+            [(Action::Reference, "t-4f2a".to_string())]
+        );
+        assert!(parse_trailer("Signed-off-by: doit <doit@example.com>").is_empty());
+        assert!(parse_trailer("no trailer here").is_empty());
+    }
+
+    #[test]
+    fn trailer_keys_are_case_insensitive() {
+        for line in ["FIXES: t-4f2a", "resolves: t-4f2a", " Closes : t-4f2a"] {
+            assert_eq!(
+                parse_trailer(line),
+                [(Action::Close, "t-4f2a".to_string())],
+                "{}",
+                line
+            );
+        }
+        assert_eq!(parse_trailer("REFS: t-4f2a")[0].0, Action::Reference);
+    }
+
+    #[test]
+    fn references_can_share_a_trailer() {
+        assert_eq!(
+            parse_trailer("Refs: t-4f2a, s-9c01,,backend/deploy "),
+            [
+                (Action::Reference, "t-4f2a".to_string()),
+                (Action::Reference, "s-9c01".to_string()),
+                (Action::Reference, "backend/deploy".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn commits_are_read_oldest_first_since_a_revision() {
+        let repo = test_repo();
+        assert!(commits(repo.path(), None).unwrap().is_empty());
+        assert!(head_commit(repo.path()).unwrap().is_none());
+
+        let first = commit(repo.path(), "Start\n\nRefs: t-4f2a");
+        let second = commit(
+            repo.path(),
+            "Retry deploys\n\nA body line: not a trailer.\n\nCloses: backend/deploy\nRefs: s-9c01",
+        );
+
+        let all = commits(repo.path(), None).unwrap();
+        let hashes: Vec<&str> = all.iter().map(|c| c.hash.as_str()).collect();
+        assert_eq!(hashes, [first.as_str(), second.as_str()]);
+        assert_eq!(
+            all[0].references,
+            [(Action::Reference, "t-4f2a".to_string())]
+        );
+        assert_eq!(
+            all[1].references,
+            [
+                (Action::Close, "backend/deploy".to_string()),
+                (Action::Reference, "s-9c01".to_string()),
+            ]
+        );
+
+        let since = commits(repo.path(), Some(&first)).unwrap();
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].hash, second);
+        assert!(commits(repo.path(), Some(&second)).unwrap().is_empty());
+        assert_eq!(head_commit(repo.path()).unwrap().unwrap().hash, second);
+    }
+
+    #[test]
+    fn revisions_that_are_gone_dont_exist() {
+        let repo = test_repo();
+        let head = commit(repo.path(), "Start");
+
+        assert!(exists(repo.path(), &head));
+        assert!(exists(repo.path(), "HEAD"));
+        assert!(!exists(repo.path(), &"0".repeat(40)));
+        assert!(commits(repo.path(), Some(&"0".repeat(40))).is_err());
+    }
+}
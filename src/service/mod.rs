@@ -1,2 +1,10 @@
+pub mod dates;
 pub mod errors;
+pub mod fingerprint;
+pub mod formats;
+pub mod fuzzy;
+pub mod git;
+pub mod references;
+pub mod scan;
 pub mod svc;
+pub mod vault;
@@ -0,0 +1,132 @@
+use crate::db::ids;
+
+/// A task as addressed on the command line: by short id (`t-4f2a`), by
+/// `project/task` path, or by bare name.
+#[derive(Debug, PartialEq)]
+pub enum TaskRef {
+    /// As typed, in any case. Taken as a name if no task has this id.
+    ShortId(String),
+    Path {
+        project: String,
+        task: String,
+    },
+    Name(String),
+}
+
+impl TaskRef {
+    pub fn parse(reference: &str) -> Self {
+        let reference = reference.trim();
+        if ids::matches(ids::TASK_PREFIX, reference) {
+            return TaskRef::ShortId(reference.to_string());
+        }
+
+        match reference.split_once('/') {
+            Some((project, task)) => TaskRef::Path {
+                project: project.to_string(),
+                task: task.to_string(),
+            },
+            None => TaskRef::Name(reference.to_string()),
+        }
+    }
+}
+
+/// A subtask as addressed on the command line: by short id (`s-91c0`), by
+/// `<task>/subtask` path where `<task>` is any [`TaskRef`], or by bare name.
+#[derive(Debug, PartialEq)]
+pub enum SubTaskRef {
+    /// As typed, in any case. Taken as a name if no subtask has this id.
+    ShortId(String),
+    Path {
+        task: TaskRef,
+        subtask: String,
+    },
+    Name(String),
+}
+
+impl SubTaskRef {
+    pub fn parse(reference: &str) -> Self {
+        let reference = reference.trim();
+        if ids::matches(ids::SUBTASK_PREFIX, reference) {
+            return SubTaskRef::ShortId(reference.to_string());
+        }
+
+        match reference.rsplit_once('/') {
+            Some((task, subtask)) => SubTaskRef::Path {
+                task: TaskRef::parse(task),
+                subtask: subtask.to_string(),
+            },
+            None => SubTaskRef::Name(reference.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_refs() {
+        assert_eq!(
+            TaskRef::parse(" t-4f2a "),
+            TaskRef::ShortId("t-4f2a".to_string())
+        );
+        assert_eq!(
+            TaskRef::parse("T-4F2A"),
+            TaskRef::ShortId("T-4F2A".to_string())
+        );
+        assert_eq!(
+            TaskRef::parse("backend/deploy"),
+            TaskRef::Path {
+                project: "backend".to_string(),
+                task: "deploy".to_string(),
+            }
+        );
+        // the first slash splits, the rest is the task's
+        assert_eq!(
+            TaskRef::parse("backend/ci/cd"),
+            TaskRef::Path {
+                project: "backend".to_string(),
+                task: "ci/cd".to_string(),
+            }
+        );
+        assert_eq!(TaskRef::parse("t-"), TaskRef::Name("t-".to_string()));
+        assert_eq!(TaskRef::parse("t-xyz"), TaskRef::Name("t-xyz".to_string()));
+        assert_eq!(
+            TaskRef::parse("s-4f2a"),
+            TaskRef::Name("s-4f2a".to_string())
+        );
+    }
+
+    #[test]
+    fn subtask_refs() {
+        assert_eq!(
+            SubTaskRef::parse("s-9c01"),
+            SubTaskRef::ShortId("s-9c01".to_string())
+        );
+        assert_eq!(
+            SubTaskRef::parse("S-9C01"),
+            SubTaskRef::ShortId("S-9C01".to_string())
+        );
+        assert_eq!(
+            SubTaskRef::parse("backend/deploy/tag release"),
+            SubTaskRef::Path {
+                task: TaskRef::Path {
+                    project: "backend".to_string(),
+                    task: "deploy".to_string(),
+                },
+                subtask: "tag release".to_string(),
+            }
+        );
+        assert_eq!(
+            SubTaskRef::parse("t-4f2a/tag release"),
+            SubTaskRef::Path {
+                task: TaskRef::ShortId("t-4f2a".to_string()),
+                subtask: "tag release".to_string(),
+            }
+        );
+        assert_eq!(
+            SubTaskRef::parse("tag release"),
+            SubTaskRef::Name("tag release".to_string())
+        );
+    }
+}
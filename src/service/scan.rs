@@ -0,0 +1,286 @@
+//! `doit scan`: `TODO`, `FIXME` and `HACK` comments in a source tree, kept
+//! as tasks in a project.
+//!
+//! ```text
+//! // TODO: retry on timeouts
+//! # FIXME(ana) the cache never expires
+//! ```
+//!
+//! The tree is walked the way git sees it, skipping what `.gitignore`,
+//! `.ignore` and hidden files hide, along with binary and very large files.
+//! Each comment is keyed by a fingerprint of its file, marker, text and how
+//! many identical comments come before it in the file, so a comment keeps
+//! its task when lines are added above it, and editing its text makes it a
+//! new one.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use ignore::WalkBuilder;
+
+use crate::db::models::{ScanReport, ScannedComment};
+
+use super::errors::ServiceError;
+use super::fingerprint::fingerprint;
+
+const MARKERS: [&str; 3] = ["TODO", "FIXME", "HACK"];
+/// What starts a comment in the languages doit is likely to meet. A marker
+/// only counts after one of them on the same line.
+const COMMENT_LEADERS: [&str; 7] = ["//", "/*", "#", "--", ";", "<!--", "%"];
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// What `doit scan` found and changed.
+#[derive(Debug)]
+pub struct ScanSummary {
+    pub files: usize,
+    pub comments: usize,
+    pub report: ScanReport,
+}
+
+/// Reads every file under `dir` that isn't ignored and returns the comments
+/// found, in path and line order, along with the number of files read.
+pub fn scan(dir: &Path) -> Result<(usize, Vec<ScannedComment>), ServiceError> {
+    let mut files = 0;
+    let mut comments = Vec::new();
+
+    let walker = WalkBuilder::new(dir)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker {
+        let entry = entry.map_err(|e| {
+            ServiceError::OperationFailed(format!("Failed to scan '{}': {}", dir.display(), e))
+        })?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let too_large = entry
+            .metadata()
+            .map_or(true, |metadata| metadata.len() > MAX_FILE_SIZE);
+        if too_large {
+            continue;
+        }
+        // binary files and anything else that isn't text
+        let Ok(text) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if text.contains('\0') {
+            continue;
+        }
+
+        let path = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let path = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files += 1;
+        comments.extend(comments_in(&path, &text));
+    }
+
+    Ok((files, comments))
+}
+
+/// The comments in one file, `path` being relative to the scanned directory.
+fn comments_in(path: &str, text: &str) -> Vec<ScannedComment> {
+    let mut comments = Vec::new();
+    // how many times each marker and text was seen so far
+    let mut seen: HashMap<(&str, String), usize> = HashMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let Some((marker, text)) = parse_comment(line) else {
+            continue;
+        };
+        let occurrence = seen.entry((marker, text.clone())).or_default();
+        *occurrence += 1;
+
+        let name = if text.is_empty() {
+            format!("{} in {}", marker, path)
+        } else {
+            format!("{}: {}", marker, text)
+        };
+        comments.push(ScannedComment {
+            fingerprint: fingerprint(&format!("{}\n{}\n{}\n{}", path, marker, text, occurrence)),
+            name,
+            source: format!("{}:{}", path, index + 1),
+        });
+    }
+
+    comments
+}
+
+/// The marker and text of a `TODO`-style comment, `(author)` and colon
+/// dropped and whitespace collapsed. Markers in code, such as a `"TODO"`
+/// string, or as part of a longer word aren't comments.
+fn parse_comment(line: &str) -> Option<(&'static str, String)> {
+    let comment = &line[comment_start(line)?..];
+
+    let (marker, rest) = MARKERS.iter().find_map(|&marker| {
+        comment.match_indices(marker).find_map(|(at, _)| {
+            let before = comment[..at].chars().next_back();
+            let rest = &comment[at + marker.len()..];
+            let after = rest.chars().next();
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            (!is_word(before) && !is_word(after)).then_some((marker, rest))
+        })
+    })?;
+
+    let mut rest = rest.trim_start();
+    if let Some(after_author) = rest
+        .strip_prefix('(')
+        .and_then(|r| r.split_once(')'))
+        .map(|(_, after)| after)
+    {
+        rest = after_author;
+    }
+    let rest = rest.trim_start_matches([':', '-', ' ', '\t']);
+    let rest = rest.trim_end();
+    let rest = rest
+        .strip_suffix("*/")
+        .or_else(|| rest.strip_suffix("-->"))
+        .unwrap_or(rest);
+
+    Some((
+        marker,
+        rest.split_whitespace().collect::<Vec<_>>().join(" "),
+    ))
+}
+
+/// Where the comment on `line` starts, if it has one. Leaders inside
+/// double-quoted strings don't count, and a line starting with `*` is the
+/// middle of a block comment.
+fn comment_start(line: &str) -> Option<usize> {
+    if line.trim_start().starts_with('*') {
+        return Some(0);
+    }
+
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if COMMENT_LEADERS
+            .iter()
+            .any(|leader| line[index..].starts_with(leader))
+        {
+            return Some(index);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(line: &str) -> Option<String> {
+        parse_comment(line).map(|(marker, text)| format!("{} {}", marker, text))
+    }
+
+    #[test]
+    fn comment_leaders() {
+        let comments = [
+            ("// TODO: retry on timeouts", "TODO retry on timeouts"),
+            (
+                "# FIXME the cache never expires",
+                "FIXME the cache never expires",
+            ),
+            ("(setq x 1) ; TODO: use a let", "TODO use a let"),
+            ("% HACK - fudge the margins", "HACK fudge the margins"),
+            ("-- TODO index this", "TODO index this"),
+            ("/* TODO: free it */", "TODO free it"),
+            ("   * FIXME: in a block comment", "FIXME in a block comment"),
+            ("<!-- TODO: alt text -->", "TODO alt text"),
+        ];
+        for (line, expected) in comments {
+            assert_eq!(text(line).as_deref(), Some(expected), "{}", line);
+        }
+    }
+
+    #[test]
+    fn markers_outside_comments() {
+        let lines = [
+            r#"let marker = "TODO";"#,
+            r#"let line = "// TODO: not a comment";"#,
+            r#"printf("50% \" # TODO done\n");"#,
+            "// TODOS are not a marker",
+            "# see NOT_A_TODO",
+            "TODO: no leader",
+        ];
+        for line in lines {
+            assert_eq!(text(line), None, "{}", line);
+        }
+        assert_eq!(
+            text(r#"let s = "// x"; // TODO: after the string"#).as_deref(),
+            Some("TODO after the string")
+        );
+    }
+
+    #[test]
+    fn authors_and_punctuation() {
+        assert_eq!(text("// TODO(ana): fix it").as_deref(), Some("TODO fix it"));
+        assert_eq!(
+            text("# FIXME (bob) -- later").as_deref(),
+            Some("FIXME later")
+        );
+        assert_eq!(
+            text("//   TODO:   spaced    out  ").as_deref(),
+            Some("TODO spaced out")
+        );
+        assert_eq!(parse_comment("// TODO"), Some(("TODO", String::new())));
+    }
+
+    #[test]
+    fn identical_comments_are_told_apart_by_occurrence() {
+        let file = "// TODO: retry\nfn a() {}\n// TODO: retry\n// TODO\n";
+        let comments = comments_in("src/lib.rs", file);
+
+        let names: Vec<&str> = comments.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["TODO: retry", "TODO: retry", "TODO in src/lib.rs"]);
+        let sources: Vec<&str> = comments.iter().map(|c| c.source.as_str()).collect();
+        assert_eq!(sources, ["src/lib.rs:1", "src/lib.rs:3", "src/lib.rs:4"]);
+        assert_ne!(comments[0].fingerprint, comments[1].fingerprint);
+
+        // lines added above keep the fingerprints, other files don't share them
+        let moved = comments_in("src/lib.rs", &format!("use x;\n\n{}", file));
+        let fingerprints = |comments: &[ScannedComment]| {
+            comments
+                .iter()
+                .map(|c| c.fingerprint.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fingerprints(&moved), fingerprints(&comments));
+        let elsewhere = comments_in("src/main.rs", file);
+        assert_ne!(elsewhere[0].fingerprint, comments[0].fingerprint);
+    }
+
+    #[test]
+    fn walks_what_git_would_see() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &[u8]| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(".gitignore", b"target/\n");
+        write("src/lib.rs", b"// TODO: in the source\n");
+        write("notes.md", b"<!-- FIXME: in the notes -->\n");
+        write("target/out.rs", b"// TODO: ignored\n");
+        write(".hidden/x.rs", b"// TODO: hidden\n");
+        write("logo.png", b"\x89PNG\0// TODO: binary\n");
+
+        let (files, comments) = scan(dir.path()).unwrap();
+
+        let sources: Vec<&str> = comments.iter().map(|c| c.source.as_str()).collect();
+        assert_eq!(sources, ["notes.md:1", "src/lib.rs:1"]);
+        assert_eq!(files, 2);
+    }
+}
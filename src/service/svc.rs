@@ -1,24 +1,62 @@
-use crate::db::{errors::DatabaseError, models::*, operations::Database};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::db::{
+    backup,
+    errors::DatabaseError,
+    ids,
+    models::*,
+    operations::{Database, DryRun},
+};
+
+use super::dates;
 use super::errors::ServiceError;
+use super::formats::{csv, ics, json, markdown, org, taskwarrior, todotxt, Format, ImportOptions};
+use super::fuzzy;
+use super::git::{self, Action, GitSyncSummary};
+use super::references::{SubTaskRef, TaskRef};
+use super::scan::{self, ScanSummary};
+use super::vault::{self, Side, SyncReport};
+
+/// Asked to choose among close matches when a name doesn't exist. Receives
+/// the entity kind, the name that was given and the suggestions; returns the
+/// chosen suggestion, or `None` to give up.
+pub type Picker = Box<dyn FnMut(&str, &str, &[String]) -> Option<String>>;
 
 pub struct DoitService {
     db: Database,
+    database_url: String,
+    picker: Option<Picker>,
 }
 
 impl DoitService {
     pub fn new(database_url: &str) -> Result<Self, ServiceError> {
-        let db = Database::new(database_url)
-            .map_err(|e| ServiceError::Database(DatabaseError::ConnectionError(e.to_string())))?;
-        Ok(DoitService { db })
+        Self::with_database(Database::new(database_url)?, database_url)
     }
 
-    pub fn add_new_project(&mut self, project_name: String) -> Result<i32, ServiceError> {
-        if project_name.trim().is_empty() {
-            return Err(ServiceError::InvalidInput(
-                "Project name cannot be empty".to_string(),
-            ));
-        }
+    /// Opens the database without running pending migrations. Only
+    /// [`DoitService::repair_database`] may be called before they've run,
+    /// and it runs them.
+    pub fn without_migrating(database_url: &str) -> Result<Self, ServiceError> {
+        Self::with_database(Database::without_migrating(database_url)?, database_url)
+    }
+
+    fn with_database(db: Database, database_url: &str) -> Result<Self, ServiceError> {
+        Ok(DoitService {
+            db,
+            database_url: database_url.to_string(),
+            picker: None,
+        })
+    }
+
+    pub fn set_picker(&mut self, picker: Picker) {
+        self.picker = Some(picker);
+    }
+
+    pub fn add_new_project(&mut self, project_name: String) -> Result<Project, ServiceError> {
+        check_name("Project", &project_name)?;
 
         let new_project = NewProject { name: project_name };
 
@@ -29,14 +67,13 @@ impl DoitService {
         &mut self,
         project_name: String,
         task_name: String,
-    ) -> Result<i32, ServiceError> {
-        if task_name.trim().is_empty() {
-            return Err(ServiceError::InvalidInput(
-                "Task name cannot be empty".to_string(),
-            ));
-        }
+    ) -> Result<Task, ServiceError> {
+        check_name("Task", &task_name)?;
 
-        let project = self.db.get_project_by_name(project_name)?;
+        let project = self.get_project(project_name)?;
+        if project.archived_on.is_some() {
+            return Err(ServiceError::ProjectArchived(project.name));
+        }
 
         let new_task = NewTask {
             project_id: project.id,
@@ -49,36 +86,16 @@ impl DoitService {
 
     pub fn add_subtask(
         &mut self,
-        project_name: String,
-        task_name: String,
+        task_reference: String,
         subtask_name: String,
-    ) -> Result<i32, ServiceError> {
-        if subtask_name.trim().is_empty() {
-            return Err(ServiceError::InvalidInput(
-                "Subtask name cannot be empty".to_string(),
-            ));
-        }
+    ) -> Result<SubTask, ServiceError> {
+        check_name("Subtask", &subtask_name)?;
 
-        let project = self
-            .db
-            .get_project_by_name(project_name.clone())
-            .map_err(|e| match e {
-                DatabaseError::NotFound(_) => {
-                    ServiceError::InvalidInput(format!("Project '{}' does not exist", project_name))
-                }
-                e => e.into(),
-            })?;
-
-        let task = self
-            .db
-            .get_task_by_name_and_project_id(task_name.clone(), project.id)
-            .map_err(|e| match e {
-                DatabaseError::NotFound(_) => ServiceError::InvalidInput(format!(
-                    "Task '{}' does not exist in project '{}'",
-                    task_name, project_name
-                )),
-                e => e.into(),
-            })?;
+        let task = self.resolve_task(&task_reference)?;
+        let project = self.db.get_project_by_id(task.project_id)?;
+        if project.archived_on.is_some() {
+            return Err(ServiceError::ProjectArchived(project.name));
+        }
 
         let new_subtask = NewSubTask {
             task_id: task.id,
@@ -89,17 +106,1094 @@ impl DoitService {
         Ok(self.db.add_new_subtask(new_subtask)?)
     }
 
+    /// Lists projects by name, leaving out archived ones unless asked.
+    pub fn get_all_projects(
+        &mut self,
+        include_archived: bool,
+    ) -> Result<Vec<Project>, ServiceError> {
+        Ok(self.db.get_all_projects(include_archived)?)
+    }
+
+    pub fn archive_project(&mut self, project_name: String) -> Result<Project, ServiceError> {
+        self.set_project_archived(project_name, true)
+    }
+
+    pub fn unarchive_project(&mut self, project_name: String) -> Result<Project, ServiceError> {
+        self.set_project_archived(project_name, false)
+    }
+
+    fn set_project_archived(
+        &mut self,
+        project_name: String,
+        archived: bool,
+    ) -> Result<Project, ServiceError> {
+        let project = self.get_project(project_name)?;
+        if project.archived_on.is_some() == archived {
+            return Err(ServiceError::InvalidInput(format!(
+                "Project '{}' is {}archived",
+                project.name,
+                if archived { "already " } else { "not " }
+            )));
+        }
+
+        Ok(self.db.set_project_archived(project.name, archived)?)
+    }
+
     pub fn get_all_tasks(&mut self, project_name: String) -> Result<Vec<Task>, ServiceError> {
-        let project = self
+        let project = self.get_project(project_name)?;
+
+        Ok(self.db.get_all_tasks(project.id)?)
+    }
+
+    pub fn get_all_subtasks(
+        &mut self,
+        task_reference: String,
+    ) -> Result<Vec<SubTask>, ServiceError> {
+        let task = self.resolve_task(&task_reference)?;
+
+        Ok(self.db.get_all_subtasks(task.id)?)
+    }
+
+    /// Renames a task, returning it as it is now.
+    pub fn rename_task(
+        &mut self,
+        task_reference: String,
+        new_task_name: String,
+    ) -> Result<Task, ServiceError> {
+        check_name("Task", &new_task_name)?;
+
+        let task = self.resolve_task(&task_reference)?;
+        let id = self
             .db
-            .get_project_by_name(project_name.clone())
-            .map_err(|e| match e {
-                DatabaseError::NotFound(_) => {
-                    ServiceError::InvalidInput(format!("Project '{}' does not exist", project_name))
+            .update_task(task.project_id, task.name, new_task_name)?;
+
+        Ok(self.db.get_tasks_by_id(id)?)
+    }
+
+    pub fn update_task_status(
+        &mut self,
+        task_reference: String,
+        completed: bool,
+    ) -> Result<Task, ServiceError> {
+        let task = self.resolve_task(&task_reference)?;
+        self.db
+            .update_task_status(task.project_id, task.name.clone(), completed)?;
+
+        Ok(task)
+    }
+
+    pub fn delete_project(&mut self, project_name: String) -> Result<Project, ServiceError> {
+        let project = self.get_project(project_name)?;
+        self.db.delete_project(project.name.clone())?;
+
+        Ok(project)
+    }
+
+    pub fn delete_task(&mut self, task_reference: String) -> Result<Task, ServiceError> {
+        let task = self.resolve_task(&task_reference)?;
+        self.db.delete_task(task.project_id, task.name.clone())?;
+
+        Ok(task)
+    }
+
+    /// Returns the audit log newest first. `since` and `until` accept the
+    /// formats understood by [`dates::parse_since`].
+    pub fn get_log(
+        &mut self,
+        since: Option<String>,
+        until: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Event>, ServiceError> {
+        let since = since.as_deref().map(dates::parse_since).transpose()?;
+        let until = until.as_deref().map(dates::parse_until).transpose()?;
+
+        Ok(self.db.get_events(None, since, until, limit)?)
+    }
+
+    /// Returns the audit events for a task and its subtasks, newest first.
+    /// A task in the trash is found too, unless a live one has the name.
+    pub fn get_task_history(
+        &mut self,
+        task_reference: String,
+        since: Option<String>,
+        until: Option<String>,
+    ) -> Result<(Task, Vec<Event>), ServiceError> {
+        let since = since.as_deref().map(dates::parse_since).transpose()?;
+        let until = until.as_deref().map(dates::parse_until).transpose()?;
+
+        // close matches among live tasks are only offered once the trash
+        // has been looked through
+        let picker = self.picker.take();
+        let live = self.resolve_task(&task_reference);
+        self.picker = picker;
+        let task = match live {
+            Err(ServiceError::NotFound { .. }) => match self.find_trashed_task(&task_reference)? {
+                Some(task) => task,
+                None => self.resolve_task(&task_reference)?,
+            },
+            result => result?,
+        };
+        let events = self.db.get_events(Some(task.id), since, until, None)?;
+
+        Ok((task, events))
+    }
+
+    pub fn get_trash(&mut self) -> Result<Vec<TrashedItem>, ServiceError> {
+        Ok(self.db.get_trash()?)
+    }
+
+    /// Restores a trashed item given its short id, path or bare name, as
+    /// shown by `doit trash list`.
+    pub fn restore_from_trash(&mut self, reference: String) -> Result<TrashedItem, ServiceError> {
+        let items = self.db.get_trash()?;
+        let paths: Vec<String> = items.iter().map(TrashedItem::path).collect();
+        let short_id = ids::normalize(&reference);
+        let mut matches: Vec<TrashedItem> = items
+            .into_iter()
+            .filter(|item| {
+                let path = item.path();
+                item.short_id() == Some(short_id.as_str())
+                    || path == reference
+                    || path.rsplit('/').next() == Some(reference.as_str())
+            })
+            .collect();
+
+        let item = match matches.len() {
+            0 => {
+                return Err(ServiceError::NotFound {
+                    entity: "Trashed item",
+                    suggestions: closest_path(&reference, paths.into_iter()),
+                    name: reference,
+                })
+            }
+            1 => matches.remove(0),
+            _ => {
+                return Err(ServiceError::Ambiguous {
+                    reference,
+                    candidates: matches
+                        .iter()
+                        .map(|item| match item.short_id() {
+                            Some(short_id) => format!("{} ({})", item.path(), short_id),
+                            None => item.path(),
+                        })
+                        .collect(),
+                })
+            }
+        };
+
+        match &item {
+            TrashedItem::Project(project) => {
+                self.db.restore_project(project.id)?;
+            }
+            TrashedItem::Task(task, project) => {
+                if project.deleted_on.is_some() {
+                    return Err(ServiceError::InvalidInput(format!(
+                        "Project '{}' is in the trash, restore it first",
+                        project.name
+                    )));
                 }
-                e => e.into(),
-            })?;
+                self.db.restore_task(task.id)?;
+            }
+            TrashedItem::SubTask(subtask, task, _) => {
+                if task.deleted_on.is_some() {
+                    return Err(ServiceError::InvalidInput(format!(
+                        "Task '{}' is in the trash, restore it first",
+                        task.name
+                    )));
+                }
+                self.db.restore_subtask(subtask.id)?;
+            }
+        }
 
-        Ok(self.db.get_all_tasks(project.id)?)
+        Ok(item)
+    }
+
+    /// The trashed task with this short id, `project/task` path or name.
+    fn find_trashed_task(&mut self, reference: &str) -> Result<Option<Task>, ServiceError> {
+        let short_id = ids::normalize(reference);
+        let mut matches: Vec<(Task, Project)> = self
+            .db
+            .get_trashed_tasks()?
+            .into_iter()
+            .filter(|(task, project)| {
+                task.short_id == short_id
+                    || task.name == reference
+                    || format!("{}/{}", project.name, task.name) == reference
+            })
+            .collect();
+
+        match matches.len() {
+            0 => Ok(None),
+            1 => Ok(Some(matches.remove(0).0)),
+            _ => Err(ServiceError::Ambiguous {
+                reference: reference.to_string(),
+                candidates: matches
+                    .iter()
+                    .map(|(task, project)| {
+                        format!("{}/{} ({})", project.name, task.name, task.short_id)
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Permanently removes trashed items, or only those deleted longer ago
+    /// than `older_than` (e.g. `30d`).
+    pub fn empty_trash(&mut self, older_than: Option<String>) -> Result<usize, ServiceError> {
+        let before = older_than
+            .as_deref()
+            .map(dates::parse_duration)
+            .transpose()?
+            .map(|age| chrono::Utc::now().naive_utc() - age);
+
+        Ok(self.db.empty_trash(before)?)
+    }
+
+    /// Writes a consistent copy of the database to `path`, or to a
+    /// timestamped file in the current directory.
+    pub fn backup(&mut self, path: Option<String>) -> Result<PathBuf, ServiceError> {
+        let path = path
+            .map(PathBuf::from)
+            .unwrap_or_else(|| backup::default_backup_path(&self.database_url));
+        backup::backup(&self.database_url, &path)?;
+
+        Ok(path)
+    }
+
+    /// Checks that `path` is a backup this version can restore.
+    pub fn validate_backup(&mut self, path: &str) -> Result<(), ServiceError> {
+        backup::open_backup(Path::new(path))?;
+        Ok(())
+    }
+
+    /// Replaces the database with a backup, snapshotting the current one
+    /// first. The database is reopened so older backups get migrated.
+    pub fn restore(&mut self, path: &str) -> Result<(), ServiceError> {
+        backup::restore(&self.database_url, Path::new(path))?;
+        self.db = Database::new(&self.database_url)?;
+
+        Ok(())
+    }
+
+    /// Serializes the whole database, see [`Dump`] for what that covers, or
+    /// one project's tasks and subtasks.
+    pub fn export(
+        &mut self,
+        format: Format,
+        project: Option<String>,
+    ) -> Result<String, ServiceError> {
+        let mut dump = self.db.export()?;
+        if let Some(project) = project {
+            let project = self.get_project(project)?;
+            dump.projects.retain(|p| p.id == project.id);
+            dump.tasks.retain(|task| task.project_id == project.id);
+            let task_ids: HashSet<i32> = dump.tasks.iter().map(|task| task.id).collect();
+            dump.subtasks
+                .retain(|subtask| task_ids.contains(&subtask.task_id));
+            // thoughts don't belong to a project
+            dump.thoughts.clear();
+        }
+
+        match format {
+            Format::Json => json::encode(dump),
+            Format::Markdown => Ok(markdown::encode(&dump)),
+            Format::TodoTxt => Ok(todotxt::encode(&dump)),
+            Format::Taskwarrior => taskwarrior::encode(&dump),
+            Format::Ics => Ok(ics::encode(&dump)),
+            Format::Org => Ok(org::encode(&dump)),
+            Format::Csv => csv::encode(&dump),
+        }
+    }
+
+    /// Imports a document written by [`DoitService::export`], or one edited
+    /// by hand. Nothing is imported if any of it fails, including name
+    /// conflicts unless `skip_conflicts` is set. The other formats update
+    /// tasks that already exist rather than conflicting.
+    pub fn import(
+        &mut self,
+        input: &str,
+        format: Format,
+        options: ImportOptions,
+    ) -> Result<ImportReport, ServiceError> {
+        if options.project.is_some() && format == Format::Json {
+            return Err(ServiceError::InvalidInput(
+                "--project doesn't apply to JSON".to_string(),
+            ));
+        }
+        if options.map.is_some() && format != Format::Csv {
+            return Err(ServiceError::InvalidInput(
+                "--map only applies to CSV".to_string(),
+            ));
+        }
+        if options.replace && format != Format::Json {
+            return Err(ServiceError::InvalidInput(
+                "Only JSON can replace the database, other formats add to it".to_string(),
+            ));
+        }
+
+        let (dump, mode) = match format {
+            Format::Json if options.replace => (json::decode(input)?, ImportMode::Replace),
+            Format::Json => (json::decode(input)?, ImportMode::Merge),
+            Format::Markdown => (
+                markdown::decode(input, options.project.as_deref())?,
+                ImportMode::Update,
+            ),
+            Format::TodoTxt => (
+                todotxt::decode(input, options.project.as_deref())?,
+                ImportMode::Sync,
+            ),
+            Format::Taskwarrior => (
+                taskwarrior::decode(input, options.project.as_deref())?,
+                ImportMode::Sync,
+            ),
+            Format::Ics => (
+                ics::decode(input, options.project.as_deref())?,
+                ImportMode::Sync,
+            ),
+            Format::Org => (
+                org::decode(input, options.project.as_deref())?,
+                ImportMode::Sync,
+            ),
+            Format::Csv => {
+                let rows = self.check_csv(input, &options)?;
+                let errors: Vec<String> = rows
+                    .iter()
+                    .filter_map(|row| {
+                        let error = row.values.as_ref().err()?;
+                        Some(format!("line {}: {}", row.line, error))
+                    })
+                    .collect();
+                if !errors.is_empty() {
+                    return Err(ServiceError::InvalidInput(format!(
+                        "Nothing was imported, fix these rows first:\n  {}",
+                        errors.join("\n  ")
+                    )));
+                }
+                let values = rows.into_iter().filter_map(|row| row.values.ok());
+                (csv::into_dump(values.collect()), ImportMode::Update)
+            }
+        };
+
+        // as with `add task`, archived projects take no new tasks
+        if mode != ImportMode::Replace {
+            for project in &dump.projects {
+                if !dump.tasks.iter().any(|task| task.project_id == project.id) {
+                    continue;
+                }
+                match self.db.get_project_by_name(project.name.clone()) {
+                    Ok(existing) if existing.archived_on.is_some() => {
+                        return Err(ServiceError::ProjectArchived(existing.name))
+                    }
+                    Ok(_) | Err(DatabaseError::NotFound(_)) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        Ok(self.db.import(&dump, mode, options.skip_conflicts)?)
+    }
+
+    pub fn check_database(&mut self) -> Result<CheckReport, ServiceError> {
+        Ok(self.db.check()?)
+    }
+
+    /// Deletes orphaned tasks and subtasks, returning them, then runs any
+    /// pending migrations.
+    pub fn repair_database(&mut self) -> Result<RepairReport, ServiceError> {
+        Ok(self.db.remove_orphans()?)
+    }
+
+    /// Vacuums the database, returning its size in bytes before and after.
+    pub fn vacuum_database(&mut self) -> Result<(u64, u64), ServiceError> {
+        let before = self.database_size();
+        self.db.vacuum()?;
+
+        Ok((before, self.database_size()))
+    }
+
+    /// Returns row counts and the size of the database file in bytes.
+    pub fn get_database_stats(&mut self) -> Result<(DbStats, u64), ServiceError> {
+        Ok((self.db.get_stats()?, self.database_size()))
+    }
+
+    /// The database file plus its write-ahead log.
+    fn database_size(&self) -> u64 {
+        [
+            self.database_url.clone(),
+            format!("{}-wal", self.database_url),
+        ]
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum()
+    }
+
+    /// Starts a dry run. Everything up to [`DoitService::rollback_dry_run`]
+    /// runs normally and is then discarded.
+    pub fn begin_dry_run(&mut self) -> Result<DryRun, ServiceError> {
+        Ok(self.db.begin_dry_run()?)
+    }
+
+    /// Discards a dry run, returning descriptions of what it would have
+    /// changed.
+    pub fn rollback_dry_run(&mut self, dry_run: DryRun) -> Result<Vec<String>, ServiceError> {
+        Ok(self.db.rollback_dry_run(dry_run)?)
+    }
+
+    /// Reverts the most recent change, returning its journal entry.
+    pub fn undo(&mut self) -> Result<JournalEntry, ServiceError> {
+        self.db
+            .undo()?
+            .ok_or_else(|| ServiceError::InvalidInput("Nothing to undo".to_string()))
+    }
+
+    /// Replays the most recently undone change.
+    pub fn redo(&mut self) -> Result<JournalEntry, ServiceError> {
+        self.db
+            .redo()?
+            .ok_or_else(|| ServiceError::InvalidInput("Nothing to redo".to_string()))
+    }
+
+    /// Lists the changes `undo` would revert, most recent first.
+    pub fn get_undo_list(&mut self, limit: i64) -> Result<Vec<JournalEntry>, ServiceError> {
+        Ok(self.db.get_undo_list(limit)?)
+    }
+
+    /// Looks up a task by short id, `project/task` path or bare name. A bare
+    /// name shared by tasks in several projects is reported as ambiguous.
+    pub fn resolve_task(&mut self, reference: &str) -> Result<Task, ServiceError> {
+        self.resolve_task_ref(TaskRef::parse(reference))
+    }
+
+    fn resolve_task_ref(&mut self, task_ref: TaskRef) -> Result<Task, ServiceError> {
+        match task_ref {
+            TaskRef::ShortId(short_id) => {
+                match self.db.get_task_by_short_id(ids::normalize(&short_id)) {
+                    // a task can be named like a short id
+                    Err(DatabaseError::NotFound(_)) => {
+                        self.resolve_task_ref(TaskRef::Name(short_id))
+                    }
+                    result => Ok(result?),
+                }
+            }
+            TaskRef::Path { project, task } => {
+                let project = self.get_project(project)?;
+                match self
+                    .db
+                    .get_task_by_name_and_project_id(task.clone(), project.id)
+                {
+                    Err(DatabaseError::NotFound(_)) => {
+                        let paths = self
+                            .db
+                            .get_all_tasks(project.id)?
+                            .into_iter()
+                            .map(|t| format!("{}/{}", project.name, t.name));
+                        let chosen = self.suggest_or_fail(
+                            "Task",
+                            format!("{}/{}", project.name, task),
+                            closest_path(&task, paths),
+                        )?;
+                        self.resolve_task_ref(TaskRef::parse(&chosen))
+                    }
+                    result => Ok(result?),
+                }
+            }
+            TaskRef::Name(name) => {
+                let mut matches = self.db.find_tasks_by_name(name.clone())?;
+                match matches.len() {
+                    0 => {
+                        let paths = self
+                            .db
+                            .get_all_tasks_with_projects()?
+                            .into_iter()
+                            .map(|(t, p)| format!("{}/{}", p.name, t.name));
+                        let chosen =
+                            self.suggest_or_fail("Task", name.clone(), closest_path(&name, paths))?;
+                        self.resolve_task_ref(TaskRef::parse(&chosen))
+                    }
+                    1 => Ok(matches.remove(0).0),
+                    _ => Err(ServiceError::Ambiguous {
+                        reference: name,
+                        candidates: matches
+                            .iter()
+                            .map(|(task, project)| {
+                                format!("{}/{} ({})", project.name, task.name, task.short_id)
+                            })
+                            .collect(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Looks up a subtask by short id, `<task>/subtask` path or bare name.
+    pub fn resolve_subtask(&mut self, reference: &str) -> Result<SubTask, ServiceError> {
+        self.resolve_subtask_ref(SubTaskRef::parse(reference))
+    }
+
+    fn resolve_subtask_ref(&mut self, subtask_ref: SubTaskRef) -> Result<SubTask, ServiceError> {
+        match subtask_ref {
+            SubTaskRef::ShortId(short_id) => {
+                match self.db.get_subtask_by_short_id(ids::normalize(&short_id)) {
+                    // a subtask can be named like a short id
+                    Err(DatabaseError::NotFound(_)) => {
+                        self.resolve_subtask_ref(SubTaskRef::Name(short_id))
+                    }
+                    result => Ok(result?),
+                }
+            }
+            SubTaskRef::Path { task, subtask } => {
+                let task = self.resolve_task_ref(task)?;
+                match self
+                    .db
+                    .get_subtask_by_name_and_task_id(subtask.clone(), task.id)
+                {
+                    Err(DatabaseError::NotFound(_)) => {
+                        let project = self.db.get_project_by_id(task.project_id)?;
+                        let paths = self
+                            .db
+                            .get_all_subtasks(task.id)?
+                            .into_iter()
+                            .map(|s| format!("{}/{}/{}", project.name, task.name, s.name));
+                        let chosen = self.suggest_or_fail(
+                            "Subtask",
+                            format!("{}/{}/{}", project.name, task.name, subtask),
+                            closest_path(&subtask, paths),
+                        )?;
+                        self.resolve_subtask(&chosen)
+                    }
+                    result => Ok(result?),
+                }
+            }
+            SubTaskRef::Name(name) => {
+                let mut matches = self.db.find_subtasks_by_name(name.clone())?;
+                match matches.len() {
+                    0 => {
+                        let paths = self
+                            .db
+                            .get_all_subtasks_with_parents()?
+                            .into_iter()
+                            .map(|(s, t, p)| format!("{}/{}/{}", p.name, t.name, s.name));
+                        let chosen = self.suggest_or_fail(
+                            "Subtask",
+                            name.clone(),
+                            closest_path(&name, paths),
+                        )?;
+                        self.resolve_subtask(&chosen)
+                    }
+                    1 => Ok(matches.remove(0).0),
+                    _ => Err(ServiceError::Ambiguous {
+                        reference: name,
+                        candidates: matches
+                            .iter()
+                            .map(|(subtask, task, project)| {
+                                format!(
+                                    "{}/{}/{} ({})",
+                                    project.name, task.name, subtask.name, subtask.short_id
+                                )
+                            })
+                            .collect(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Syncs thoughts with a directory of Markdown files, see [`vault`].
+    /// Conflicts are left alone unless `prefer` picks a side. Files are
+    /// only touched if `write` is set, so dry runs leave them be.
+    pub fn sync_thoughts(
+        &mut self,
+        dir: &Path,
+        prefer: Option<Side>,
+        write: bool,
+    ) -> Result<SyncReport, ServiceError> {
+        if !dir.is_dir() {
+            return Err(ServiceError::InvalidInput(format!(
+                "'{}' isn't a directory",
+                dir.display()
+            )));
+        }
+
+        let mut state = vault::State::load(dir)?;
+        let notes = vault::Note::read_all(dir)?;
+        let thoughts = self.db.get_all_thoughts()?;
+        let mut plan = vault::plan(&thoughts, &notes, &state, prefer);
+
+        let changes = plan.take_changes();
+        let added = self
+            .db
+            .change_thoughts(format!("sync thoughts with '{}'", dir.display()), changes)?;
+        let conflicting = plan.conflicting.clone();
+        let report = plan.apply(dir, &notes, added, write)?;
+
+        if write {
+            state.update(&self.db.get_all_thoughts()?, &conflicting);
+            state.save(dir)?;
+        }
+        Ok(report)
+    }
+
+    /// Scans a source tree for `TODO`-style comments and keeps the
+    /// project's tasks in line with them, see [`scan`].
+    pub fn scan(&mut self, dir: &Path, project_name: String) -> Result<ScanSummary, ServiceError> {
+        if !dir.is_dir() {
+            return Err(ServiceError::InvalidInput(format!(
+                "'{}' isn't a directory",
+                dir.display()
+            )));
+        }
+        let project = self.get_project(project_name)?;
+        if project.archived_on.is_some() {
+            return Err(ServiceError::ProjectArchived(project.name));
+        }
+
+        let (files, comments) = scan::scan(dir)?;
+        let count = comments.len();
+        let report = self.db.scan(
+            project.id,
+            format!("scan '{}' into '{}'", dir.display(), project.name),
+            comments,
+        )?;
+        Ok(ScanSummary {
+            files,
+            comments: count,
+            report,
+        })
+    }
+
+    /// Marks the tasks named in the trailers of the commits since the last
+    /// sync done, and attaches the commits to them, see [`git`]. `since`
+    /// overrides where the last sync got to. References have to be exact,
+    /// as nobody is around to pick among close matches in a hook; those
+    /// that don't resolve are reported and skipped. The repository is only
+    /// told about the sync if `write` is set, so dry runs leave it be.
+    pub fn git_sync(
+        &mut self,
+        repo: &Path,
+        since: Option<String>,
+        write: bool,
+    ) -> Result<GitSyncSummary, ServiceError> {
+        let mut lost = None;
+        let commits = match since {
+            Some(since) => git::commits(repo, Some(&since))?,
+            None => match git::last_synced(repo)? {
+                // rewritten away; reading the whole history again could
+                // reopen or reattach what was synced before, so only the
+                // commit the hook ran for is read
+                Some(last) if !git::exists(repo, &last) => {
+                    lost = Some(last);
+                    git::head_commit(repo)?.into_iter().collect()
+                }
+                last => git::commits(repo, last.as_deref())?,
+            },
+        };
+
+        let picker = self.picker.take();
+        let mut changes = Vec::new();
+        let mut unresolved = Vec::new();
+        for commit in &commits {
+            for (action, reference) in &commit.references {
+                match self.commit_change(*action, reference, &commit.hash) {
+                    Ok(change) => changes.push(change),
+                    Err(e) => unresolved.push(format!("{}: {}", commit.short_hash(), e)),
+                }
+            }
+        }
+        self.picker = picker;
+
+        let report = self
+            .db
+            .apply_commits(format!("sync commits from '{}'", repo.display()), changes)?;
+        if let (true, Some(last)) = (write, commits.last()) {
+            git::save_synced(repo, &last.hash)?;
+        }
+        Ok(GitSyncSummary {
+            commits: commits.len(),
+            report,
+            unresolved,
+            lost,
+        })
+    }
+
+    fn commit_change(
+        &mut self,
+        action: Action,
+        reference: &str,
+        commit: &str,
+    ) -> Result<CommitChange, ServiceError> {
+        let commit = commit.to_string();
+        // a path is a task's, so subtasks can only be named by short id
+        if let SubTaskRef::ShortId(_) = SubTaskRef::parse(reference) {
+            let subtask = self.resolve_subtask(reference)?;
+            return Ok(match action {
+                Action::Close => CommitChange::CloseSubTask { id: subtask.id },
+                Action::Reference => CommitChange::Reference {
+                    id: subtask.task_id,
+                    commit,
+                },
+            });
+        }
+
+        let task = self.resolve_task(reference)?;
+        Ok(match action {
+            Action::Close => CommitChange::CloseTask {
+                id: task.id,
+                commit,
+            },
+            Action::Reference => CommitChange::Reference {
+                id: task.id,
+                commit,
+            },
+        })
+    }
+
+    /// Adds a post-commit hook to a repository that runs `doit git-sync`
+    /// against this database, and returns its path.
+    pub fn install_git_hook(&mut self, repo: &Path, write: bool) -> Result<PathBuf, ServiceError> {
+        let failed = |e: std::io::Error| ServiceError::OperationFailed(e.to_string());
+        let repo = fs::canonicalize(repo).map_err(|e| {
+            ServiceError::InvalidInput(format!("Failed to find '{}': {}", repo.display(), e))
+        })?;
+        let database = fs::canonicalize(&self.database_url).map_err(failed)?;
+        let database_dir = database.parent().unwrap_or(Path::new("/"));
+        let doit = env::current_exe().map_err(failed)?;
+
+        // doit opens the database in the working directory
+        let command = format!(
+            "(cd {} && {} git-sync --repo {}) || true",
+            git::shell_quote(&database_dir.to_string_lossy()),
+            git::shell_quote(&doit.to_string_lossy()),
+            git::shell_quote(&repo.to_string_lossy())
+        );
+        git::install_hook(&repo, &command, write)
+    }
+
+    /// Reads the rows of a CSV file and checks each the way `add task`
+    /// would, settling its project, without importing anything. Rows that
+    /// would fail say why.
+    pub fn check_csv(
+        &mut self,
+        input: &str,
+        options: &ImportOptions,
+    ) -> Result<Vec<csv::Row>, ServiceError> {
+        let map = match &options.map {
+            Some(spec) => csv::ColumnMap::parse(spec)?,
+            None => csv::ColumnMap::default(),
+        };
+        let mut rows = csv::read(input, &map)?;
+        // whether each project named so far is archived
+        let mut archived: HashMap<String, bool> = HashMap::new();
+
+        for row in &mut rows {
+            let Ok(values) = &mut row.values else {
+                continue;
+            };
+            if let Err(e) = self.check_csv_values(values, options.project.as_deref(), &mut archived)
+            {
+                row.values = Err(match e {
+                    ServiceError::InvalidInput(msg) => msg,
+                    e => e.to_string(),
+                });
+            }
+        }
+
+        Ok(rows)
+    }
+
+    fn check_csv_values(
+        &mut self,
+        values: &mut csv::Values,
+        project: Option<&str>,
+        archived: &mut HashMap<String, bool>,
+    ) -> Result<(), ServiceError> {
+        check_name("Task", &values.name)?;
+        let Some(project) = values.project.clone().or(project.map(str::to_string)) else {
+            return Err(ServiceError::InvalidInput(
+                "no project, pass --project or map a project column".to_string(),
+            ));
+        };
+        check_name("Project", &project)?;
+
+        let is_archived = match archived.get(&project) {
+            Some(&is_archived) => is_archived,
+            None => {
+                let is_archived = match self.db.get_project_by_name(project.clone()) {
+                    Ok(existing) => existing.archived_on.is_some(),
+                    Err(DatabaseError::NotFound(_)) => false,
+                    Err(e) => return Err(e.into()),
+                };
+                archived.insert(project.clone(), is_archived);
+                is_archived
+            }
+        };
+        if is_archived {
+            return Err(ServiceError::ProjectArchived(project));
+        }
+
+        values.project = Some(project);
+        Ok(())
+    }
+
+    fn get_project(&mut self, project_name: String) -> Result<Project, ServiceError> {
+        match self.db.get_project_by_name(project_name.clone()) {
+            Err(DatabaseError::NotFound(_)) => {
+                let names = self.db.get_all_projects(true)?.into_iter().map(|p| p.name);
+                let suggestions = fuzzy::closest(&project_name, names);
+                let chosen = self.suggest_or_fail("Project", project_name, suggestions)?;
+                Ok(self.db.get_project_by_name(chosen)?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Offers `suggestions` to the picker if one is installed, otherwise
+    /// fails with a NotFound error listing them.
+    fn suggest_or_fail(
+        &mut self,
+        entity: &'static str,
+        name: String,
+        suggestions: Vec<String>,
+    ) -> Result<String, ServiceError> {
+        if let Some(picker) = self.picker.as_mut() {
+            if !suggestions.is_empty() {
+                if let Some(chosen) = picker(entity, &name, &suggestions) {
+                    return Ok(chosen);
+                }
+            }
+        }
+
+        Err(ServiceError::NotFound {
+            entity,
+            name,
+            suggestions,
+        })
+    }
+}
+
+fn check_name(entity: &str, name: &str) -> Result<(), ServiceError> {
+    if name.trim().is_empty() {
+        return Err(ServiceError::InvalidInput(format!(
+            "{} name cannot be empty",
+            entity
+        )));
+    }
+    Ok(())
+}
+
+/// Fuzzy matches a bare name against the last segment of each path, so
+/// `deplyo` suggests `backend/deploy` rather than nothing. The paths come
+/// closest match first, as many as [`fuzzy::closest`] would suggest.
+fn closest_path(name: &str, paths: impl Iterator<Item = String>) -> Vec<String> {
+    let paths: Vec<String> = paths.collect();
+    let leaf = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+    let close = fuzzy::closest(name, paths.iter().map(|path| leaf(path)));
+
+    close
+        .iter()
+        .flat_map(|close| paths.iter().filter(move |path| &leaf(path) == close))
+        .take(fuzzy::MAX_SUGGESTIONS)
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_service() -> (TempDir, DoitService) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doit.db");
+        let service = DoitService::new(path.to_str().unwrap()).unwrap();
+        (dir, service)
+    }
+
+    /// Gives a row a known short id, since new ones are random.
+    fn pin_short_id(dir: &TempDir, table: &str, id: i32, short_id: &str) {
+        use diesel::{Connection, RunQueryDsl, SqliteConnection};
+
+        let mut conn =
+            SqliteConnection::establish(dir.path().join("doit.db").to_str().unwrap()).unwrap();
+        diesel::sql_query(format!(
+            "UPDATE {} SET short_id = '{}' WHERE id = {}",
+            table, short_id, id
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    }
+
+    #[test]
+    fn short_ids_resolve_in_any_case_and_fall_back_to_names() {
+        let (dir, mut service) = test_service();
+        service.add_new_project("backend".to_string()).unwrap();
+        let deploy = service
+            .add_task("backend".to_string(), "deploy".to_string())
+            .unwrap();
+        let named = service
+            .add_task("backend".to_string(), "t-beef".to_string())
+            .unwrap();
+        let shadowed = service
+            .add_task("backend".to_string(), "t-4f2a".to_string())
+            .unwrap();
+        let subtask = service
+            .add_subtask(deploy.short_id.clone(), "s-cafe".to_string())
+            .unwrap();
+        pin_short_id(&dir, "tasks", deploy.id, "t-4f2a");
+        pin_short_id(&dir, "tasks", named.id, "t-0001");
+        pin_short_id(&dir, "tasks", shadowed.id, "t-0002");
+        pin_short_id(&dir, "subtasks", subtask.id, "s-9c01");
+
+        assert_eq!(service.resolve_task("T-4F2A").unwrap().id, deploy.id);
+        // a short id wins over a task named like it
+        assert_eq!(service.resolve_task("t-4f2a").unwrap().id, deploy.id);
+        assert_eq!(service.resolve_task("t-beef").unwrap().id, named.id);
+        assert_eq!(service.resolve_subtask("S-9C01").unwrap().id, subtask.id);
+        assert_eq!(service.resolve_subtask("s-cafe").unwrap().id, subtask.id);
+        assert!(matches!(
+            service.resolve_task("t-0000000000"),
+            Err(ServiceError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn closest_paths_keep_the_fuzzy_order_and_cap() {
+        let paths = [
+            "a/deploys",
+            "b/deploy",
+            "c/deploy",
+            "d/unrelated",
+            "e/deploy",
+            "f/deploy",
+            "g/deploy",
+            "h/deploy",
+        ]
+        .map(str::to_string);
+
+        let close = closest_path("deploy", paths.clone().into_iter());
+        assert_eq!(close.len(), fuzzy::MAX_SUGGESTIONS);
+        assert_eq!(close[0], "b/deploy");
+        assert!(!close.contains(&"a/deploys".to_string()));
+
+        let close = closest_path("deploys", paths.into_iter());
+        assert_eq!(close[0], "a/deploys");
+        assert_eq!(close[1], "b/deploy");
+    }
+
+    #[test]
+    fn history_finds_trashed_tasks() {
+        let (_dir, mut service) = test_service();
+        service.add_new_project("backend".to_string()).unwrap();
+        service.add_new_project("infra".to_string()).unwrap();
+        let deploy = service
+            .add_task("backend".to_string(), "deploy".to_string())
+            .unwrap();
+        service
+            .add_task("infra".to_string(), "terraform".to_string())
+            .unwrap();
+        service.delete_task("backend/deploy".to_string()).unwrap();
+        service.delete_project("infra".to_string()).unwrap();
+
+        let (task, events) = service
+            .get_task_history("deploy".to_string(), None, None)
+            .unwrap();
+        assert_eq!(task.id, deploy.id);
+        assert!(!events.is_empty());
+        // trashed along with its project
+        let (task, _) = service
+            .get_task_history("infra/terraform".to_string(), None, None)
+            .unwrap();
+        assert_eq!(task.name, "terraform");
+        assert!(matches!(
+            service.get_task_history("nothing".to_string(), None, None),
+            Err(ServiceError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn git_sync_reads_the_latest_commit_when_the_synced_one_is_gone() {
+        let (dir, mut service) = test_service();
+        service.add_new_project("backend".to_string()).unwrap();
+        service
+            .add_task("backend".to_string(), "deploy".to_string())
+            .unwrap();
+        service
+            .add_task("backend".to_string(), "cache".to_string())
+            .unwrap();
+        let repo = dir.path().join("repo");
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(["-c", "user.name=doit", "-c", "user.email=doit@example.com"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        fs::create_dir(&repo).unwrap();
+        git(&["init", "--quiet"]);
+        git(&[
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "Cache\n\nCloses: backend/cache",
+        ]);
+        git(&[
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "-m",
+            "Deploy\n\nCloses: backend/deploy",
+        ]);
+        let gone = "0".repeat(40);
+        git::save_synced(&repo, &gone).unwrap();
+
+        let summary = service.git_sync(&repo, None, true).unwrap();
+
+        assert_eq!(summary.lost.as_deref(), Some(gone.as_str()));
+        assert_eq!(summary.commits, 1);
+        assert_eq!(summary.report.closed, ["deploy"]);
+        let head = git::head_commit(&repo).unwrap().unwrap().hash;
+        assert_eq!(git::last_synced(&repo).unwrap(), Some(head));
+        // and carries on from there
+        let summary = service.git_sync(&repo, None, true).unwrap();
+        assert!(summary.lost.is_none());
+        assert_eq!(summary.commits, 0);
+    }
+
+    #[test]
+    fn trashed_items_restore_by_short_id_in_any_case() {
+        let (_dir, mut service) = test_service();
+        service.add_new_project("backend".to_string()).unwrap();
+        let deploy = service
+            .add_task("backend".to_string(), "deploy".to_string())
+            .unwrap();
+        service.delete_task("backend/deploy".to_string()).unwrap();
+
+        let restored = service
+            .restore_from_trash(deploy.short_id.to_uppercase())
+            .unwrap();
+
+        assert!(matches!(restored, TrashedItem::Task(task, _) if task.id == deploy.id));
+        assert!(service.get_trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn renaming_returns_the_renamed_task() {
+        let (_dir, mut service) = test_service();
+        service.add_new_project("backend".to_string()).unwrap();
+        let deploy = service
+            .add_task("backend".to_string(), "deploy".to_string())
+            .unwrap();
+
+        let renamed = service
+            .rename_task("backend/deploy".to_string(), "release".to_string())
+            .unwrap();
+
+        assert_eq!(renamed.id, deploy.id);
+        assert_eq!(renamed.name, "release");
+        assert!(renamed.updated_on.is_some());
     }
 }
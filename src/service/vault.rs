@@ -0,0 +1,831 @@
+//! `doit thoughts sync`: a thought per Markdown file in a directory, such
+//! as an Obsidian vault, named after its heading:
+//!
+//! ```text
+//! ---
+//! id: th-4f2a
+//! created_on: 2024-11-01T12:00:00
+//! tags: [idea, work]
+//! ---
+//!
+//! The note.
+//! ```
+//!
+//! Renaming a file renames its thought, unless the heading couldn't be a
+//! file name and is kept in a `heading` key instead. Other front matter
+//! keys are left alone, and files without an `id` are new thoughts.
+//!
+//! Each thought's content hash as of the last sync is kept in
+//! `.doit-sync.json` in the directory. A side whose hash differs from it
+//! has changed since. A thought changed on both sides is a conflict and is
+//! left alone on both until one side is preferred.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::{NewThought, ThoughtChange, Thoughts};
+
+use super::dates;
+use super::errors::ServiceError;
+use super::fingerprint::fingerprint;
+
+const STATE_FILE: &str = ".doit-sync.json";
+const EXTENSION: &str = "md";
+/// Longest file name written, leaving room for a ` (th-4f2a)` suffix.
+const MAX_NAME_LEN: usize = 100;
+
+/// Which side wins when a thought was changed in both.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Side {
+    Doit,
+    Vault,
+}
+
+/// What a sync did, or would do in a dry run.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub written: usize,
+    pub removed: usize,
+    pub conflicts: Vec<String>,
+}
+
+/// Content hashes as of the last sync, by short id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    thoughts: BTreeMap<String, Synced>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Synced {
+    hash: String,
+    synced_on: NaiveDateTime,
+}
+
+impl State {
+    /// The directory's state, empty if it was never synced.
+    pub fn load(dir: &Path) -> Result<State, ServiceError> {
+        let path = dir.join(STATE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| {
+                ServiceError::InvalidInput(format!("Can't read '{}': {}", path.display(), e))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), ServiceError> {
+        let path = dir.join(STATE_FILE);
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| ServiceError::OperationFailed(e.to_string()))?;
+        fs::write(&path, text + "\n").map_err(|e| io_error(&path, e))
+    }
+
+    /// Remembers every thought as it is now, except conflicting ones, which
+    /// keep what they had so they still conflict next time.
+    pub fn update(&mut self, thoughts: &[Thoughts], conflicting: &HashSet<String>) {
+        let now = Utc::now().naive_utc();
+        let mut synced: BTreeMap<String, Synced> = thoughts
+            .iter()
+            .filter(|thought| !conflicting.contains(&thought.short_id))
+            .map(|thought| {
+                let entry = Synced {
+                    hash: thought_hash(thought),
+                    synced_on: now,
+                };
+                (thought.short_id.clone(), entry)
+            })
+            .collect();
+        for id in conflicting {
+            if let Some(entry) = self.thoughts.get(id) {
+                synced.insert(id.clone(), entry.clone());
+            }
+        }
+        self.thoughts = synced;
+    }
+}
+
+/// A Markdown file in the directory.
+#[derive(Debug)]
+pub struct Note {
+    path: PathBuf,
+    id: Option<String>,
+    heading: String,
+    tags: Vec<String>,
+    body: String,
+    modified_on: Option<NaiveDateTime>,
+    /// Front matter lines this doesn't know about, written back as they were.
+    extra: Vec<String>,
+}
+
+impl Note {
+    /// Reads the Markdown files directly in `dir`, by name.
+    pub fn read_all(dir: &Path) -> Result<Vec<Note>, ServiceError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|e| io_error(dir, e))? {
+            let path = entry.map_err(|e| io_error(dir, e))?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == EXTENSION) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let text = fs::read_to_string(&path).map_err(|e| io_error(&path, e))?;
+                let modified_on = fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .map(|modified| DateTime::<Utc>::from(modified).naive_utc());
+                Ok(Note::parse(path, &text, modified_on))
+            })
+            .collect()
+    }
+
+    fn parse(path: PathBuf, text: &str, modified_on: Option<NaiveDateTime>) -> Note {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut note = Note {
+            path,
+            id: None,
+            heading: stem,
+            tags: Vec::new(),
+            body: String::new(),
+            modified_on,
+            extra: Vec::new(),
+        };
+
+        let text = text.trim_start_matches('\u{feff}');
+        let mut lines = text.lines();
+        let front_matter: Option<Vec<&str>> = match lines.next() {
+            Some("---") => {
+                let collected: Vec<&str> = lines.by_ref().take_while(|l| *l != "---").collect();
+                Some(collected)
+            }
+            _ => None,
+        };
+        let Some(front_matter) = front_matter else {
+            note.body = text.trim().to_string();
+            return note;
+        };
+        note.body = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+        // the key each indented or list line belongs to
+        let mut current = "";
+        for line in front_matter {
+            if line.starts_with([' ', '\t', '-']) {
+                match current {
+                    "tags" => note
+                        .tags
+                        .extend(line.trim().strip_prefix('-').map(|tag| tag_of(tag.trim()))),
+                    "id" | "heading" | "created_on" => {}
+                    _ => note.extra.push(line.to_string()),
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                note.extra.push(line.to_string());
+                current = "";
+                continue;
+            };
+            current = key.trim();
+            let value = value.trim();
+            match current {
+                "id" => note.id = Some(unquote(value)).filter(|id| !id.is_empty()),
+                "heading" => note.heading = unquote(value),
+                "tags" => note.tags.extend(split_tags(value)),
+                // doit's own, not read back
+                "created_on" => {}
+                _ => note.extra.push(line.to_string()),
+            }
+        }
+        note.tags.retain(|tag| !tag.is_empty());
+
+        note
+    }
+
+    fn hash(&self) -> String {
+        content_hash(
+            &self.heading,
+            self.tags.iter().map(String::as_str),
+            &self.body,
+        )
+    }
+
+    fn file(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// What a sync will do, worked out before anything is changed.
+#[derive(Default)]
+pub struct Plan {
+    changes: Vec<ThoughtChange>,
+    /// The note each added thought comes from, in the order they're added.
+    adds: Vec<usize>,
+    /// Thoughts to write out, replacing the given note.
+    writes: Vec<(Thoughts, Option<usize>)>,
+    removes: Vec<usize>,
+    /// Short ids of thoughts left alone on both sides.
+    pub conflicting: HashSet<String>,
+    pub report: SyncReport,
+}
+
+/// Compares thoughts with notes and the state of the last sync.
+pub fn plan(thoughts: &[Thoughts], notes: &[Note], state: &State, prefer: Option<Side>) -> Plan {
+    let mut plan = Plan::default();
+    let mut by_id: HashMap<&str, usize> = HashMap::new();
+    let mut claimed: HashSet<usize> = HashSet::new();
+
+    for (index, note) in notes.iter().enumerate() {
+        let Some(id) = note.id.as_deref() else {
+            continue;
+        };
+        match by_id.get(id) {
+            Some(&first) => {
+                plan.report.conflicts.push(format!(
+                    "'{}' has the same id as '{}', remove one of them",
+                    note.file(),
+                    notes[first].file()
+                ));
+                claimed.insert(index);
+            }
+            None => {
+                by_id.insert(id, index);
+            }
+        }
+    }
+
+    for thought in thoughts {
+        let base = state.thoughts.get(&thought.short_id);
+        let doit_hash = thought_hash(thought);
+        // a note without an id, say from before the first sync, is taken
+        // to be the thought of the same heading
+        let index = by_id.get(thought.short_id.as_str()).copied().or_else(|| {
+            (0..notes.len()).find(|&i| {
+                notes[i].id.is_none()
+                    && notes[i].heading == thought.heading
+                    && !claimed.contains(&i)
+            })
+        });
+
+        let Some(index) = index else {
+            match base {
+                Some(_) if prefer == Some(Side::Doit) => plan.writes.push((thought.clone(), None)),
+                Some(base) if base.hash == doit_hash || prefer == Some(Side::Vault) => {
+                    plan.changes.push(ThoughtChange::Delete { id: thought.id })
+                }
+                Some(base) => {
+                    plan.conflicting.insert(thought.short_id.clone());
+                    plan.report.conflicts.push(format!(
+                        "'{}' ({}) was deleted from the vault but edited in doit ({}) since \
+                         the last sync ({})",
+                        thought.heading,
+                        thought.short_id,
+                        dates::format_local(thought.updated_on.unwrap_or(thought.created_on)),
+                        dates::format_local(base.synced_on)
+                    ));
+                }
+                None => plan.writes.push((thought.clone(), None)),
+            }
+            continue;
+        };
+        claimed.insert(index);
+
+        let note = &notes[index];
+        let vault_hash = note.hash();
+        let doit_changed = base.is_none_or(|base| base.hash != doit_hash);
+        let vault_changed = base.is_none_or(|base| base.hash != vault_hash);
+
+        if doit_hash == vault_hash {
+            if note.id.is_none() {
+                plan.writes.push((thought.clone(), Some(index)));
+            }
+        } else if !vault_changed || prefer == Some(Side::Doit) {
+            plan.writes.push((thought.clone(), Some(index)));
+        } else if !doit_changed || prefer == Some(Side::Vault) {
+            let tags = note.tags.join(" ");
+            plan.changes.push(ThoughtChange::Edit {
+                id: thought.id,
+                heading: note.heading.clone(),
+                note: note.body.clone(),
+                tags: tags.clone(),
+            });
+            if note.id.is_none() {
+                let edited = Thoughts {
+                    heading: note.heading.clone(),
+                    note: note.body.clone(),
+                    tags,
+                    ..thought.clone()
+                };
+                plan.writes.push((edited, Some(index)));
+            }
+        } else {
+            plan.conflicting.insert(thought.short_id.clone());
+            plan.report.conflicts.push(match base {
+                Some(base) => format!(
+                    "'{}' ({}) was edited in doit ({}) and in the vault ({}) since the last \
+                     sync ({})",
+                    thought.heading,
+                    thought.short_id,
+                    dates::format_local(thought.updated_on.unwrap_or(thought.created_on)),
+                    note.modified_on
+                        .map(dates::format_local)
+                        .unwrap_or_default(),
+                    dates::format_local(base.synced_on)
+                ),
+                None => format!(
+                    "'{}' ({}) differs from '{}', and they were never synced",
+                    thought.heading,
+                    thought.short_id,
+                    note.file()
+                ),
+            });
+        }
+    }
+
+    for (index, note) in notes.iter().enumerate() {
+        if claimed.contains(&index) {
+            continue;
+        }
+        // the note's thought is gone from doit, if it had one
+        let base = note.id.as_ref().and_then(|id| state.thoughts.get(id));
+        match base {
+            Some(_) if prefer == Some(Side::Vault) => plan.add(note, index),
+            Some(base) if base.hash == note.hash() || prefer == Some(Side::Doit) => {
+                plan.removes.push(index)
+            }
+            Some(base) => {
+                let id = note.id.clone().unwrap_or_default();
+                plan.report.conflicts.push(format!(
+                    "'{}' was edited in the vault ({}) since the last sync ({}), but its \
+                     thought {} was deleted from doit",
+                    note.file(),
+                    note.modified_on
+                        .map(dates::format_local)
+                        .unwrap_or_default(),
+                    dates::format_local(base.synced_on),
+                    id
+                ));
+                plan.conflicting.insert(id);
+            }
+            None => plan.add(note, index),
+        }
+    }
+
+    plan
+}
+
+impl Plan {
+    fn add(&mut self, note: &Note, index: usize) {
+        self.changes.push(ThoughtChange::Add(NewThought {
+            heading: note.heading.clone(),
+            note: note.body.clone(),
+            tags: note.tags.join(" "),
+        }));
+        self.adds.push(index);
+    }
+
+    /// The changes to make in doit, for
+    /// [`Database::change_thoughts`](crate::db::operations::Database::change_thoughts).
+    pub fn take_changes(&mut self) -> Vec<ThoughtChange> {
+        for change in &self.changes {
+            match change {
+                ThoughtChange::Add(_) => self.report.added += 1,
+                ThoughtChange::Edit { .. } => self.report.updated += 1,
+                ThoughtChange::Delete { .. } => self.report.deleted += 1,
+            }
+        }
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Writes and removes files, if `write` is set, once the changes are
+    /// made. `added` are the thoughts they added.
+    pub fn apply(
+        mut self,
+        dir: &Path,
+        notes: &[Note],
+        added: Vec<Thoughts>,
+        write: bool,
+    ) -> Result<SyncReport, ServiceError> {
+        // added thoughts get their ids written into their notes
+        for (thought, &index) in added.into_iter().zip(&self.adds) {
+            self.writes.push((thought, Some(index)));
+        }
+
+        // files that are there, other than the ones being replaced
+        let mut taken: HashSet<PathBuf> = notes.iter().map(|note| note.path.clone()).collect();
+        for (_, index) in &self.writes {
+            if let Some(index) = index {
+                taken.remove(&notes[*index].path);
+            }
+        }
+
+        for (thought, index) in &self.writes {
+            let note = index.map(|index| &notes[index]);
+            let mut path = dir.join(format!("{}.{}", file_name(&thought.heading), EXTENSION));
+            if taken.contains(&path) || (path.exists() && note.map(|n| &n.path) != Some(&path)) {
+                path = dir.join(format!(
+                    "{} ({}).{}",
+                    file_name(&thought.heading),
+                    thought.short_id,
+                    EXTENSION
+                ));
+            }
+            taken.insert(path.clone());
+            self.report.written += 1;
+            if !write {
+                continue;
+            }
+
+            let extra = note.map(|note| note.extra.as_slice()).unwrap_or_default();
+            fs::write(&path, render(thought, extra)).map_err(|e| io_error(&path, e))?;
+            if let Some(note) = note.filter(|note| note.path != path) {
+                fs::remove_file(&note.path).map_err(|e| io_error(&note.path, e))?;
+            }
+        }
+
+        for &index in &self.removes {
+            self.report.removed += 1;
+            if write {
+                let path = &notes[index].path;
+                fs::remove_file(path).map_err(|e| io_error(path, e))?;
+            }
+        }
+
+        Ok(self.report)
+    }
+}
+
+fn render(thought: &Thoughts, extra: &[String]) -> String {
+    let mut lines = vec!["---".to_string(), format!("id: {}", thought.short_id)];
+    if file_name(&thought.heading) != thought.heading {
+        lines.push(format!("heading: {}", quote(&thought.heading)));
+    }
+    lines.push(format!(
+        "created_on: {}",
+        thought.created_on.format("%Y-%m-%dT%H:%M:%S")
+    ));
+    let tags: Vec<String> = thought.tags().map(quote).collect();
+    lines.push(format!("tags: [{}]", tags.join(", ")));
+    lines.extend(extra.iter().cloned());
+    lines.push("---".to_string());
+
+    let note = thought.note.trim();
+    if !note.is_empty() {
+        lines.push(String::new());
+        lines.push(note.to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+fn thought_hash(thought: &Thoughts) -> String {
+    content_hash(&thought.heading, thought.tags(), &thought.note)
+}
+
+fn content_hash<'a>(heading: &str, tags: impl Iterator<Item = &'a str>, note: &str) -> String {
+    let tags: Vec<&str> = tags.collect();
+    fingerprint(&format!(
+        "{}\0{}\0{}",
+        heading.trim(),
+        tags.join(" "),
+        note.trim()
+    ))
+}
+
+/// The heading with characters that can't be in a file name, or that break
+/// Obsidian links, replaced.
+fn file_name(heading: &str) -> String {
+    let name: String = heading
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .take(MAX_NAME_LEN)
+        .collect();
+    match name.strip_prefix('.') {
+        Some(rest) => format!("-{}", rest),
+        None if name.is_empty() => "untitled".to_string(),
+        None => name,
+    }
+}
+
+/// Leaves plain words alone, and double quotes anything YAML might read
+/// differently.
+fn quote(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/' | '.' | ' '))
+        && !text.starts_with(['-', ' '])
+        && !text.ends_with(' ');
+    match plain {
+        true => text.to_string(),
+        false => serde_json::to_string(text).unwrap_or_default(),
+    }
+}
+
+/// Splits a `tags` value, a flow list like `[idea, "a,b"]` or bare words,
+/// at the commas (and for bare words spaces) that aren't inside quotes.
+fn split_tags(value: &str) -> Vec<String> {
+    let (items, separators): (&str, &[char]) = match value
+        .strip_prefix('[')
+        .and_then(|items| items.strip_suffix(']'))
+    {
+        Some(items) => (items, &[',']),
+        None => (value, &[',', ' ']),
+    };
+
+    let mut tags = Vec::new();
+    let mut tag = String::new();
+    let mut quoted_by = None;
+    let mut chars = items.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quoted_by {
+            // `\"` in double quotes and `''` in single ones don't end them
+            Some('"') if c == '\\' => {
+                tag.push(c);
+                tag.extend(chars.next());
+                continue;
+            }
+            Some('\'') if c == '\'' && chars.peek() == Some(&'\'') => {
+                tag.push(c);
+                tag.extend(chars.next());
+                continue;
+            }
+            Some(quote) if c == quote => quoted_by = None,
+            Some(_) => {}
+            // only a quote opening an item quotes it, not the one in `don't`
+            None if matches!(c, '"' | '\'') && tag.trim().is_empty() => quoted_by = Some(c),
+            None if separators.contains(&c) => {
+                tags.push(tag_of(tag.trim()));
+                tag.clear();
+                continue;
+            }
+            None => {}
+        }
+        tag.push(c);
+    }
+    tags.push(tag_of(tag.trim()));
+    tags.retain(|tag| !tag.is_empty());
+    tags
+}
+
+/// Tags are space separated in doit, so spaces within one become dashes.
+fn tag_of(text: &str) -> String {
+    unquote(text).trim().replace(' ', "-")
+}
+
+fn unquote(text: &str) -> String {
+    if text.starts_with('"') {
+        if let Ok(unquoted) = serde_json::from_str::<String>(text) {
+            return unquoted;
+        }
+    }
+    match text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        Some(unquoted) => unquoted.replace("''", "'"),
+        None => text.to_string(),
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> ServiceError {
+    ServiceError::OperationFailed(format!("'{}': {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thought(heading: &str, note: &str, tags: &str) -> Thoughts {
+        Thoughts {
+            id: 1,
+            heading: heading.to_string(),
+            note: note.to_string(),
+            created_on: NaiveDateTime::parse_from_str("2024-11-01 12:00:00", "%Y-%m-%d %H:%M:%S")
+                .unwrap(),
+            short_id: "th-4f2a".to_string(),
+            updated_on: None,
+            tags: tags.to_string(),
+        }
+    }
+
+    /// The note a sync would write for `thought`.
+    fn note_of(thought: &Thoughts) -> Note {
+        let path = PathBuf::from(format!("{}.md", file_name(&thought.heading)));
+        Note::parse(path, &render(thought, &[]), None)
+    }
+
+    /// The state right after `thought` was synced.
+    fn synced(thought: &Thoughts) -> State {
+        let mut state = State::default();
+        state.update(std::slice::from_ref(thought), &HashSet::new());
+        state
+    }
+
+    fn is_edit(plan: &Plan, heading: &str) -> bool {
+        matches!(
+            plan.changes.as_slice(),
+            [ThoughtChange::Edit { heading: h, .. }] if h == heading
+        )
+    }
+
+    fn is_delete(plan: &Plan) -> bool {
+        matches!(plan.changes.as_slice(), [ThoughtChange::Delete { id: 1 }])
+    }
+
+    fn writes(plan: &Plan) -> Vec<&str> {
+        plan.writes
+            .iter()
+            .map(|(thought, _)| thought.heading.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn notes_read_back_what_was_rendered() {
+        let mut original = thought("What: next?", "  The note.\n\nMore.  ", "a,b work \"q\"");
+        original.updated_on = Some(original.created_on);
+        let note = note_of(&original);
+
+        assert_eq!(note.id.as_deref(), Some("th-4f2a"));
+        assert_eq!(note.heading, "What: next?");
+        assert_eq!(note.tags, ["a,b", "work", "\"q\""]);
+        assert_eq!(note.body, "The note.\n\nMore.");
+        assert!(note.extra.is_empty());
+        assert_eq!(note.hash(), thought_hash(&original));
+    }
+
+    #[test]
+    fn tags_are_split_outside_quotes() {
+        assert_eq!(split_tags("[idea, work]"), ["idea", "work"]);
+        assert_eq!(
+            split_tags(r#"["a,b", 'c, d', 'it''s', "x\"y"]"#),
+            ["a,b", "c,-d", "it's", "x\"y"]
+        );
+        assert_eq!(split_tags("idea work,later"), ["idea", "work", "later"]);
+        assert_eq!(split_tags("[don't, ]"), ["don't"]);
+        assert!(split_tags("[]").is_empty());
+    }
+
+    #[test]
+    fn front_matter_is_kept_or_read_as_tags() {
+        let text = "---\nid: th-4f2a\naliases:\n  - next\ntags:\n  - idea\n  - \"a b\"\n\
+                    created_on: 2024-11-01T12:00:00\n---\n\nThe note.\n";
+        let note = Note::parse(PathBuf::from("Next.md"), text, None);
+
+        assert_eq!(note.heading, "Next");
+        assert_eq!(note.tags, ["idea", "a-b"]);
+        assert_eq!(note.extra, ["aliases:", "  - next"]);
+        assert_eq!(note.body, "The note.");
+        let rendered = render(&thought("Next", "The note.", "idea"), &note.extra);
+        assert!(rendered.contains("aliases:\n  - next\n---"));
+    }
+
+    #[test]
+    fn unchanged_sides_have_nothing_to_do() {
+        let thought = thought("Next", "The note.", "a,b work");
+        let notes = [note_of(&thought)];
+
+        let plan = plan(
+            std::slice::from_ref(&thought),
+            &notes,
+            &synced(&thought),
+            None,
+        );
+
+        assert!(plan.changes.is_empty());
+        assert!(plan.writes.is_empty() && plan.removes.is_empty());
+        assert!(plan.report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn a_side_edited_since_the_last_sync_wins() {
+        let base = thought("Next", "The note.", "idea");
+        let mut edited = base.clone();
+        edited.note = "Edited.".to_string();
+
+        let plan_doit = plan(&[edited.clone()], &[note_of(&base)], &synced(&base), None);
+        assert!(plan_doit.changes.is_empty());
+        assert_eq!(writes(&plan_doit), ["Next"]);
+
+        let plan_vault = plan(
+            std::slice::from_ref(&base),
+            &[note_of(&edited)],
+            &synced(&base),
+            None,
+        );
+        assert!(is_edit(&plan_vault, "Next"));
+        assert!(plan_vault.writes.is_empty());
+    }
+
+    #[test]
+    fn edits_on_both_sides_conflict_unless_one_is_preferred() {
+        let base = thought("Next", "The note.", "idea");
+        let mut in_doit = base.clone();
+        in_doit.note = "Edited in doit.".to_string();
+        let mut in_vault = base.clone();
+        in_vault.heading = "Edited in the vault".to_string();
+        let thoughts = [in_doit];
+        let notes = [note_of(&in_vault)];
+        let state = synced(&base);
+
+        let conflict = plan(&thoughts, &notes, &state, None);
+        assert!(conflict.changes.is_empty() && conflict.writes.is_empty());
+        assert!(conflict.conflicting.contains("th-4f2a"));
+        assert!(conflict.report.conflicts[0].contains("edited in doit"));
+
+        let doit = plan(&thoughts, &notes, &state, Some(Side::Doit));
+        assert!(doit.changes.is_empty());
+        assert_eq!(writes(&doit), ["Next"]);
+
+        let vault = plan(&thoughts, &notes, &state, Some(Side::Vault));
+        assert!(is_edit(&vault, "Edited in the vault"));
+        assert!(vault.writes.is_empty());
+    }
+
+    #[test]
+    fn deleted_in_the_vault_and_edited_in_doit() {
+        let base = thought("Next", "The note.", "idea");
+        let mut edited = base.clone();
+        edited.note = "Edited.".to_string();
+        let state = synced(&base);
+
+        let unedited = plan(std::slice::from_ref(&base), &[], &state, None);
+        assert!(is_delete(&unedited));
+
+        let thoughts = [edited];
+        let conflict = plan(&thoughts, &[], &state, None);
+        assert!(conflict.changes.is_empty() && conflict.writes.is_empty());
+        assert!(conflict.conflicting.contains("th-4f2a"));
+        assert!(conflict.report.conflicts[0].contains("deleted from the vault"));
+
+        let doit = plan(&thoughts, &[], &state, Some(Side::Doit));
+        assert!(doit.changes.is_empty());
+        assert_eq!(writes(&doit), ["Next"]);
+
+        let vault = plan(&thoughts, &[], &state, Some(Side::Vault));
+        assert!(is_delete(&vault));
+    }
+
+    #[test]
+    fn deleted_in_doit_and_edited_in_the_vault() {
+        let base = thought("Next", "The note.", "idea");
+        let mut edited = base.clone();
+        edited.note = "Edited.".to_string();
+        let state = synced(&base);
+
+        let unedited = plan(&[], &[note_of(&base)], &state, None);
+        assert!(unedited.changes.is_empty());
+        assert_eq!(unedited.removes, [0]);
+
+        let notes = [note_of(&edited)];
+        let conflict = plan(&[], &notes, &state, None);
+        assert!(conflict.changes.is_empty() && conflict.removes.is_empty());
+        assert!(conflict.conflicting.contains("th-4f2a"));
+        assert!(conflict.report.conflicts[0].contains("deleted from doit"));
+
+        let doit = plan(&[], &notes, &state, Some(Side::Doit));
+        assert!(doit.changes.is_empty());
+        assert_eq!(doit.removes, [0]);
+
+        let vault = plan(&[], &notes, &state, Some(Side::Vault));
+        assert!(matches!(
+            vault.changes.as_slice(),
+            [ThoughtChange::Add(NewThought { note, .. })] if note == "Edited."
+        ));
+        assert_eq!(vault.adds, [0]);
+    }
+
+    #[test]
+    fn new_thoughts_and_notes_go_to_the_other_side() {
+        let thought = thought("Next", "The note.", "");
+        let mut note = note_of(&thought);
+        note.id = None;
+        note.heading = "Later".to_string();
+
+        let plan = plan(&[thought], &[note], &State::default(), None);
+
+        assert_eq!(writes(&plan), ["Next"]);
+        assert!(matches!(
+            plan.changes.as_slice(),
+            [ThoughtChange::Add(NewThought { heading, .. })] if heading == "Later"
+        ));
+    }
+}